@@ -0,0 +1,122 @@
+//! Procedural macros for `schemamama_postgres`. Use them through the `embed` feature of that
+//! crate rather than depending on this crate directly.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Shared with `load_sql_migrations`, so that embedded migrations are expanded the same way.
+#[path = "../../src/includes.rs"]
+mod includes;
+
+/// Embed the `V{version}__{name}.up.sql` / `.down.sql` files of a directory (relative to the
+/// crate's `Cargo.toml`) into the binary, expanding to a
+/// `Vec<Box<dyn schemamama_postgres::PostgresMigration>>` of `SqlMigration`s ordered by version.
+/// `-- include:` lines are expanded as `load_sql_migrations` does.
+#[proc_macro]
+pub fn embed_postgres_migrations(input: TokenStream) -> TokenStream {
+    let code = match expand(&input.to_string()) {
+        Ok(code) => code,
+        Err(message) => format!("compile_error!({:?})", message),
+    };
+
+    code.parse().unwrap()
+}
+
+fn expand(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    if input.len() < 2 || !input.starts_with('"') || !input.ends_with('"') {
+        return Err("expected a string literal naming the migrations directory".to_owned());
+    }
+
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| "CARGO_MANIFEST_DIR is not set".to_owned())?;
+    let directory = PathBuf::from(manifest_dir).join(&input[1..input.len() - 1]);
+
+    let mut ups = BTreeMap::new();
+    let mut downs = BTreeMap::new();
+    let entries = fs::read_dir(&directory)
+        .map_err(|e| format!("cannot read {}: {}", directory.display(), e))?;
+
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name.to_owned(),
+            None => continue,
+        };
+
+        let (stem, is_up) = if file_name.ends_with(".up.sql") {
+            (&file_name[..file_name.len() - ".up.sql".len()], true)
+        } else if file_name.ends_with(".down.sql") {
+            (&file_name[..file_name.len() - ".down.sql".len()], false)
+        } else {
+            continue;
+        };
+
+        let (version, name) = match parse_stem(stem) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        let files = if is_up { &mut ups } else { &mut downs };
+        if files.insert(version, (name, path.clone())).is_some() {
+            return Err(format!("duplicate migration version {}", version));
+        }
+    }
+
+    // Every file read, included ones too, so that the crate is rebuilt when one of them changes.
+    let mut read = vec![];
+    let mut migrations = Vec::with_capacity(ups.len());
+    for (version, (name, up)) in ups {
+        let down = match downs.remove(&version) {
+            Some((_, down)) => format!("Some({:?}.to_owned())", read_sql(&directory, &down, &mut read)?),
+            None => "None".to_owned(),
+        };
+
+        migrations.push(format!(
+            "Box::new(::schemamama_postgres::SqlMigration::new({}i64, {:?}, {:?}, {})) \
+             as Box<dyn ::schemamama_postgres::PostgresMigration>",
+            version,
+            name,
+            read_sql(&directory, &up, &mut read)?,
+            down
+        ));
+    }
+
+    if let Some(version) = downs.keys().next() {
+        return Err(format!("migration version {} has no up file", version));
+    }
+
+    read.sort();
+    read.dedup();
+    let tracked = read.iter()
+        .map(|path| path_literal(path).map(|path| format!("include_str!({})", path)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!("{{ const _: &[&str] = &[{}]; vec![{}] }}", tracked.join(", "), migrations.join(", ")))
+}
+
+/// Reads the SQL file `path` with its includes expanded (see `includes::read_with_includes`).
+fn read_sql(directory: &Path, path: &Path, read: &mut Vec<PathBuf>) -> Result<String, String> {
+    includes::read_with_includes(directory, path, read).map_err(|e| e.to_string())
+}
+
+/// Splits a file stem of the form `V{version}__{name}` into its version and name.
+fn parse_stem(stem: &str) -> Option<(i64, String)> {
+    if !stem.starts_with('V') {
+        return None;
+    }
+
+    let separator = stem.find("__")?;
+    let version = stem[1..separator].parse().ok()?;
+    Some((version, stem[separator + 2..].to_owned()))
+}
+
+fn path_literal(path: &Path) -> Result<String, String> {
+    path.to_str()
+        .map(|path| format!("{:?}", path))
+        .ok_or_else(|| format!("{} is not valid UTF-8", path.display()))
+}
@@ -0,0 +1,206 @@
+use async_trait::async_trait;
+use schemamama::{Migration, Version};
+use std::collections::{BTreeMap, BTreeSet};
+use tokio_postgres::error::Error as PostgresError;
+use tokio_postgres::{Client, Transaction};
+
+use crate::table::MetadataTable;
+
+/// A migration to be used within an asynchronous `tokio-postgres` client.
+#[async_trait]
+pub trait AsyncPostgresMigration : Migration + Send + Sync {
+    /// Called when this migration is to be executed. This function has an empty body by default,
+    /// so its implementation is optional.
+    #[allow(unused_variables)]
+    async fn up(&self, transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+        Ok(())
+    }
+
+    /// Called when this migration is to be reversed. This function has an empty body by default,
+    /// so its implementation is optional.
+    #[allow(unused_variables)]
+    async fn down(&self, transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+        Ok(())
+    }
+}
+
+/// An adapter that allows its migrations to act upon `tokio-postgres` client transactions.
+///
+/// The `Adapter` trait of Schemamama is synchronous, so this adapter is driven by an
+/// `AsyncMigrator` instead of `schemamama::Migrator`.
+pub struct AsyncPostgresAdapter<'a> {
+    client: ClientHandle<'a>,
+    metadata_table: MetadataTable,
+}
+
+/// The client an `AsyncPostgresAdapter` runs against: either borrowed from the caller or checked
+/// out of a connection pool for the lifetime of the adapter.
+enum ClientHandle<'a> {
+    Borrowed(&'a mut Client),
+    #[cfg(feature = "deadpool")]
+    Pooled(deadpool_postgres::Object),
+}
+
+impl<'a> ClientHandle<'a> {
+    fn get(&mut self) -> &mut Client {
+        match self {
+            ClientHandle::Borrowed(client) => client,
+            #[cfg(feature = "deadpool")]
+            ClientHandle::Pooled(object) => &mut ***object,
+        }
+    }
+}
+
+impl<'a> AsyncPostgresAdapter<'a> {
+    /// Create a new migrator tied to a `tokio-postgres` client.
+    pub fn new(client: &'a mut Client) -> AsyncPostgresAdapter<'a> {
+        Self::with_metadata_table(client, "schemamama")
+    }
+
+    /// Create a new migrator tied to a `tokio-postgres` client with custom metadata table name
+    pub fn with_metadata_table<T: Into<String>>(
+        client: &'a mut Client,
+        metadata_table: T
+    ) -> AsyncPostgresAdapter<'a> {
+        AsyncPostgresAdapter {
+            client: ClientHandle::Borrowed(client),
+            metadata_table: MetadataTable::parse(&metadata_table.into()),
+        }
+    }
+
+    /// Create a new migrator that checks a connection out of a `deadpool-postgres` pool. The
+    /// connection is held for the lifetime of the adapter, i.e. for one migration run, and is
+    /// returned to the pool when the adapter is dropped. The metadata table is `schemamama`
+    /// unless changed with `with_metadata_table_name`.
+    #[cfg(feature = "deadpool")]
+    pub async fn from_pool(
+        pool: &deadpool_postgres::Pool
+    ) -> Result<AsyncPostgresAdapter<'static>, deadpool_postgres::PoolError> {
+        let object = pool.get().await?;
+        Ok(AsyncPostgresAdapter {
+            client: ClientHandle::Pooled(object),
+            metadata_table: MetadataTable::parse("schemamama"),
+        })
+    }
+
+    /// Use the metadata table `name` rather than the one the adapter was created with, e.g. for
+    /// adapters created with `from_pool`.
+    pub fn with_metadata_table_name<T: Into<String>>(mut self, name: T) -> AsyncPostgresAdapter<'a> {
+        self.metadata_table = MetadataTable::parse(&name.into());
+        self
+    }
+
+    /// Create the tables Schemamama requires to keep track of schema state. If the tables already
+    /// exist, this function has no operation.
+    pub async fn setup_schema(&mut self) -> Result<(), PostgresError> {
+        let query = format!("CREATE TABLE IF NOT EXISTS {} (version BIGINT PRIMARY KEY);", self.metadata_table);
+        let statement = self.client.get().prepare(&query).await?;
+        self.client.get().execute(&statement, &[]).await.map(|_| ())
+    }
+
+    /// Returns the highest version recorded in the metadata table.
+    pub async fn current_version(&mut self) -> Result<Option<Version>, PostgresError> {
+        let query = format!("SELECT version FROM {} ORDER BY version DESC LIMIT 1;", self.metadata_table);
+        let statement = self.client.get().prepare(&query).await?;
+        let row = self.client.get().query(&statement, &[]).await?;
+        Ok(row.iter().next().map(|r| r.get(0)))
+    }
+
+    /// Returns every version recorded in the metadata table.
+    pub async fn migrated_versions(&mut self) -> Result<BTreeSet<Version>, PostgresError> {
+        let query = format!("SELECT version FROM {};", self.metadata_table);
+        let statement = self.client.get().prepare(&query).await?;
+        let row = self.client.get().query(&statement, &[]).await?;
+        Ok(row.iter().map(|r| r.get(0)).collect())
+    }
+
+    /// Executes the `up` function of a migration and records its version, in one transaction.
+    pub async fn apply_migration(
+        &mut self,
+        migration: &dyn AsyncPostgresMigration
+    ) -> Result<(), PostgresError> {
+        let mut transaction = self.client.get().transaction().await?;
+        migration.up(&mut transaction).await?;
+        let query = format!("INSERT INTO {} (version) VALUES ($1);", self.metadata_table);
+        let statement = transaction.prepare(&query).await?;
+        transaction.execute(&statement, &[&migration.version()]).await?;
+        transaction.commit().await
+    }
+
+    /// Executes the `down` function of a migration and erases its version, in one transaction.
+    pub async fn revert_migration(
+        &mut self,
+        migration: &dyn AsyncPostgresMigration
+    ) -> Result<(), PostgresError> {
+        let mut transaction = self.client.get().transaction().await?;
+        migration.down(&mut transaction).await?;
+        let query = format!("DELETE FROM {} WHERE version = $1;", self.metadata_table);
+        let statement = transaction.prepare(&query).await?;
+        transaction.execute(&statement, &[&migration.version()]).await?;
+        transaction.commit().await
+    }
+}
+
+/// Maintains an `AsyncPostgresAdapter` and a registry of migrations, mirroring the behavior of
+/// `schemamama::Migrator`.
+pub struct AsyncMigrator<'a> {
+    adapter: AsyncPostgresAdapter<'a>,
+    migrations: BTreeMap<Version, Box<dyn AsyncPostgresMigration>>,
+}
+
+impl<'a> AsyncMigrator<'a> {
+    /// Create a migrator with a given adapter.
+    pub fn new(adapter: AsyncPostgresAdapter<'a>) -> AsyncMigrator<'a> {
+        AsyncMigrator { adapter, migrations: BTreeMap::new() }
+    }
+
+    /// Get a mutable reference to the adapter.
+    pub fn adapter(&mut self) -> &mut AsyncPostgresAdapter<'a> {
+        &mut self.adapter
+    }
+
+    /// Register a migration. If a migration with the same version is already registered, it is
+    /// replaced.
+    pub fn register(&mut self, migration: Box<dyn AsyncPostgresMigration>) {
+        self.migrations.insert(migration.version(), migration);
+    }
+
+    /// Returns the current version of the database, as recorded by the adapter.
+    pub async fn current_version(&mut self) -> Result<Option<Version>, PostgresError> {
+        self.adapter.current_version().await
+    }
+
+    /// Migrate upwards, applying every pending migration with a version less than or equal to
+    /// `to`. If `to` is `None`, all pending migrations are applied.
+    pub async fn up(&mut self, to: Option<Version>) -> Result<(), PostgresError> {
+        let migrated = self.adapter.migrated_versions().await?;
+        for (version, migration) in &self.migrations {
+            if to.map_or(false, |to| *version > to) {
+                break;
+            }
+
+            if !migrated.contains(version) {
+                self.adapter.apply_migration(&**migration).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Migrate downwards, reverting every applied migration with a version greater than `to`. If
+    /// `to` is `None`, all applied migrations are reverted.
+    pub async fn down(&mut self, to: Option<Version>) -> Result<(), PostgresError> {
+        let migrated = self.adapter.migrated_versions().await?;
+        for (version, migration) in self.migrations.iter().rev() {
+            if to.map_or(false, |to| *version <= to) {
+                break;
+            }
+
+            if migrated.contains(version) {
+                self.adapter.revert_migration(&**migration).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use postgres::error::Error as PostgresError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::MigrationClient;
+
+/// How long a backfill waits between checks of the replication lag, while replicas catch up.
+const LAG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs a data backfill in keyset-paginated batches, committing each batch in its own
+/// transaction so that a huge table is never rewritten in one go.
+///
+/// The query processes one batch: it receives the last key of the previous batch as `$1` and the
+/// batch size as `$2`, and returns the `BIGINT` keys it processed. For example:
+///
+/// ```rust,ignore
+/// Backfill::new(
+///     "WITH batch AS (SELECT id FROM users WHERE id > $1 ORDER BY id LIMIT $2) \
+///      UPDATE users SET email_lower = lower(email) FROM batch WHERE users.id = batch.id \
+///      RETURNING users.id",
+/// )
+/// .with_batch_size(5_000)
+/// .with_pause(Duration::from_millis(50))
+/// .with_max_rows_per_second(20_000)
+/// .with_max_replication_lag(Duration::from_secs(5))
+/// .run(client)?;
+/// ```
+///
+/// Run from `PostgresMigration::up_without_transaction`, each batch is a transaction of its own.
+/// Run from `PostgresMigration::up`, batches are savepoints of the migration's transaction, which
+/// bounds the work lost on failure but keeps every row locked until the migration commits.
+#[derive(Clone, Debug)]
+pub struct Backfill<'q> {
+    query: &'q str,
+    start: i64,
+    batch_size: i64,
+    pause: Duration,
+    estimated_rows: Option<u64>,
+    max_rows_per_second: Option<u64>,
+    max_replication_lag: Option<Duration>,
+}
+
+/// Reports how far a `Backfill` got, after each batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackfillProgress {
+    /// The number of batches committed so far.
+    pub batches: u64,
+    /// The number of rows processed so far.
+    pub rows_processed: u64,
+    /// The time since the backfill started.
+    pub elapsed: Duration,
+    /// The number of batches still to run, estimated from the number of rows announced with
+    /// `Backfill::with_estimated_rows`, or `None` if no estimate was given.
+    pub estimated_remaining_batches: Option<u64>,
+}
+
+impl<'q> Backfill<'q> {
+    /// Create a backfill running `query` in batches of 1000 rows, without pausing between them.
+    pub fn new(query: &'q str) -> Backfill<'q> {
+        Backfill {
+            query,
+            start: i64::MIN,
+            batch_size: 1000,
+            pause: Duration::from_secs(0),
+            estimated_rows: None,
+            max_rows_per_second: None,
+            max_replication_lag: None,
+        }
+    }
+
+    /// Set the number of rows processed by each batch.
+    pub fn with_batch_size(mut self, batch_size: i64) -> Backfill<'q> {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sleep for `pause` between batches, leaving room for the rest of the workload.
+    pub fn with_pause(mut self, pause: Duration) -> Backfill<'q> {
+        self.pause = pause;
+        self
+    }
+
+    /// Pass `start` as the "last key" of the first batch, e.g. to resume an interrupted backfill.
+    /// Defaults to the smallest `BIGINT`.
+    pub fn with_start(mut self, start: i64) -> Backfill<'q> {
+        self.start = start;
+        self
+    }
+
+    /// Sleep between batches as long as needed to process at most `rows` rows per second on
+    /// average, so that the backfill does not saturate I/O.
+    pub fn with_max_rows_per_second(mut self, rows: u64) -> Backfill<'q> {
+        self.max_rows_per_second = Some(rows);
+        self
+    }
+
+    /// Before each batch, wait until the replay lag of every streaming replica (as reported by
+    /// `pg_stat_replication` on the primary) is at most `lag`, checking once per second. Without
+    /// replicas, batches never wait.
+    pub fn with_max_replication_lag(mut self, lag: Duration) -> Backfill<'q> {
+        self.max_replication_lag = Some(lag);
+        self
+    }
+
+    /// Announce the number of rows the backfill is expected to process, e.g. from
+    /// `pg_class.reltuples`, so that its progress reports estimate the remaining batches.
+    pub fn with_estimated_rows(mut self, rows: u64) -> Backfill<'q> {
+        self.estimated_rows = Some(rows);
+        self
+    }
+
+    /// Run batches until one processes fewer rows than the batch size, returning the total number
+    /// of rows processed.
+    pub fn run<C: MigrationClient>(&self, client: &mut C) -> Result<u64, PostgresError> {
+        self.run_with_progress(client, |_| {})
+    }
+
+    /// Like `run`, but call `progress` after each batch commits, e.g. to log how far a
+    /// backfill of several hours got.
+    pub fn run_with_progress<C, F>(&self, client: &mut C, mut progress: F) -> Result<u64, PostgresError>
+    where
+        C: MigrationClient,
+        F: FnMut(&BackfillProgress),
+    {
+        let started = Instant::now();
+        let batch_size = self.batch_size.max(1) as u64;
+        let mut last = self.start;
+        let mut total = 0;
+        let mut batches = 0;
+        loop {
+            if let Some(max_lag) = self.max_replication_lag {
+                wait_for_replicas(client, max_lag)?;
+            }
+
+            let mut transaction = client.begin(None)?;
+            let keys: Vec<i64> = transaction.query(self.query, &[&last, &self.batch_size])?
+                .iter()
+                .map(|r| r.get(0))
+                .collect();
+            transaction.commit()?;
+
+            total += keys.len() as u64;
+            batches += 1;
+            progress(&BackfillProgress {
+                batches,
+                rows_processed: total,
+                elapsed: started.elapsed(),
+                estimated_remaining_batches: self.estimated_rows.map(|rows| {
+                    let remaining = rows.saturating_sub(total);
+                    (remaining + batch_size - 1) / batch_size
+                }),
+            });
+            match keys.iter().max() {
+                Some(&max) if (keys.len() as i64) >= self.batch_size => last = max,
+                _ => return Ok(total),
+            }
+            thread::sleep(self.pause);
+            if let Some(rate) = self.max_rows_per_second.filter(|&rate| rate > 0) {
+                let earliest = Duration::from_secs_f64(total as f64 / rate as f64);
+                if let Some(ahead) = earliest.checked_sub(started.elapsed()) {
+                    thread::sleep(ahead);
+                }
+            }
+        }
+    }
+}
+
+/// Waits until no streaming replica lags behind by more than `max_lag`.
+fn wait_for_replicas<C: MigrationClient>(client: &mut C, max_lag: Duration) -> Result<(), PostgresError> {
+    loop {
+        let row = client.query_one(
+            "SELECT coalesce(EXTRACT(EPOCH FROM max(replay_lag)), 0)::FLOAT8 FROM pg_stat_replication;",
+            &[]
+        )?;
+        if Duration::from_secs_f64(row.get::<_, f64>(0).max(0.0)) <= max_lag {
+            return Ok(());
+        }
+        thread::sleep(LAG_POLL_INTERVAL);
+    }
+}
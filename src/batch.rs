@@ -0,0 +1,63 @@
+use postgres::error::Error as PostgresError;
+use std::error::Error;
+use std::fmt;
+
+use crate::{logging, split_statements, MigrationClient};
+
+/// Run the statements of `sql` one after another, all or none: they run in a transaction of their
+/// own, or in a savepoint when `client` is a `Transaction`. Unlike `batch_execute`, a failure
+/// reports which statement failed. The error converts into a `SchemamamaPostgresError`.
+///
+/// Migrations should call `MigrationContext::execute_statements` from `up_ctx` instead, so that
+/// the adapter reports the failing statement in `SchemamamaPostgresError::Migration`:
+///
+/// ```rust,ignore
+/// fn up_ctx(&self, context: &mut MigrationContext<Transaction>) -> Result<(), PostgresError> {
+///     context.execute_statements(include_str!("V3__orders.sql"))
+/// }
+/// ```
+pub fn execute_statements<C: MigrationClient>(client: &mut C, sql: &str) -> Result<(), StatementError> {
+    let mut transaction = client.begin(None).map_err(|error| StatementError::new(None, "BEGIN", error))?;
+    for (index, statement) in split_statements(sql).into_iter().enumerate() {
+        if let Err(error) = transaction.batch_execute(statement) {
+            let error = StatementError::new(Some(index + 1), statement, error);
+            logging::statement_failed(&error);
+            return Err(error);
+        }
+    }
+    transaction.commit().map_err(|error| StatementError::new(None, "COMMIT", error))
+}
+
+/// A batch run by `execute_statements` failed. The statements before the failing one were rolled
+/// back.
+#[derive(Debug)]
+pub struct StatementError {
+    /// The position of the failing statement in the batch, from 1, or `None` if beginning or
+    /// committing the batch failed.
+    pub index: Option<usize>,
+    /// The failing statement, or `BEGIN` or `COMMIT`.
+    pub statement: String,
+    /// The error it failed with.
+    pub error: PostgresError,
+}
+
+impl StatementError {
+    fn new(index: Option<usize>, statement: &str, error: PostgresError) -> StatementError {
+        StatementError { index, statement: statement.to_owned(), error }
+    }
+}
+
+impl fmt::Display for StatementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "statement {} failed: {}: {}", index, self.error, self.statement),
+            None => write!(f, "{} failed: {}", self.statement, self.error),
+        }
+    }
+}
+
+impl Error for StatementError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
@@ -0,0 +1,25 @@
+//! Runs the SQL migrations found in `MIGRATIONS_DIR` (by default `migrations`) with
+//! `schemamama_postgres::run_cli`.
+
+extern crate schemamama_postgres;
+
+use schemamama_postgres::{load_sql_migrations, run_cli, PostgresMigration};
+use std::env;
+use std::process;
+
+fn main() {
+    let directory = env::var("MIGRATIONS_DIR").unwrap_or_else(|_| "migrations".to_owned());
+    let result = match load_sql_migrations(&directory) {
+        Ok(migrations) => run_cli(
+            migrations.into_iter()
+                .map(|migration| Box::new(migration) as Box<dyn PostgresMigration>)
+                .collect()
+        ),
+        Err(e) => Err(format!("cannot load migrations from {}: {}", directory, e).into()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("schemamama-pg: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,83 @@
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+
+/// Hex-encoded SHA-256 digest of `data`, the default checksum of SQL migrations.
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+/// How `SqlMigration` computes the checksums stored in the metadata table. Projects moving from
+/// another migration tool can pick the algorithm it used, so that the checksums it recorded keep
+/// verifying.
+#[derive(Clone)]
+pub enum ChecksumAlgorithm {
+    /// The hex-encoded SHA-256 digest of the SQL. This is the default.
+    Sha256,
+    /// The hex-encoded MD5 digest of the SQL.
+    Md5,
+    /// The CRC-32 of the SQL computed the way Flyway does: line by line, ignoring line endings
+    /// and a leading byte order mark, and written as a signed decimal integer.
+    Crc32,
+    /// A checksum computed by a custom function of the SQL, which may capture state such as a
+    /// salt. See `ChecksumAlgorithm::custom`.
+    Custom(Arc<dyn Fn(&str) -> String + Send + Sync>),
+}
+
+impl ChecksumAlgorithm {
+    /// A checksum computed by `checksum`.
+    pub fn custom<F>(checksum: F) -> ChecksumAlgorithm
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        ChecksumAlgorithm::Custom(Arc::new(checksum))
+    }
+
+    /// The checksum of `sql`.
+    pub fn checksum(&self, sql: &str) -> String {
+        match self {
+            ChecksumAlgorithm::Sha256 => sha256_hex(sql.as_bytes()),
+            ChecksumAlgorithm::Md5 => hex(&Md5::digest(sql.as_bytes())),
+            ChecksumAlgorithm::Crc32 => {
+                let sql = sql.strip_prefix('\u{feff}').unwrap_or(sql);
+                let crc = sql.lines().fold(0, |crc, line| crc32(crc, line.as_bytes()));
+                (crc as i32).to_string()
+            }
+            ChecksumAlgorithm::Custom(checksum) => checksum(sql),
+        }
+    }
+}
+
+impl fmt::Debug for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChecksumAlgorithm::Sha256 => f.write_str("Sha256"),
+            ChecksumAlgorithm::Md5 => f.write_str("Md5"),
+            ChecksumAlgorithm::Crc32 => f.write_str("Crc32"),
+            ChecksumAlgorithm::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Default for ChecksumAlgorithm {
+    fn default() -> ChecksumAlgorithm {
+        ChecksumAlgorithm::Sha256
+    }
+}
+
+/// Continues the CRC-32 (IEEE) `crc` with `data`.
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,63 @@
+//! A minimal command-line front end, enabled by the `cli` feature, for projects that want
+//! Flyway-style tooling without writing their own `main`.
+
+use postgres::{Client, NoTls};
+use schemamama::{Migrator, Version};
+use std::env;
+use std::error::Error;
+
+use crate::{PostgresAdapter, PostgresMigration};
+
+const USAGE: &str = "usage: <setup | status | up [VERSION] | down [VERSION]>";
+
+/// Run the command given on the command line against the database named by the `DATABASE_URL`
+/// environment variable:
+///
+/// * `setup` creates the metadata table;
+/// * `status` lists applied and pending migrations;
+/// * `up [VERSION]` applies pending migrations, up to and including `VERSION` if given;
+/// * `down [VERSION]` reverts migrations down to (but not including) `VERSION`, or all of them.
+///
+/// `up` and `down` create the metadata table first if necessary.
+pub fn run_cli(migrations: Vec<Box<dyn PostgresMigration>>) -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, version) = match args.len() {
+        1 => (&*args[0], None),
+        2 => (&*args[0], Some(args[1].parse::<Version>()?)),
+        _ => return Err(USAGE.into()),
+    };
+
+    let url = env::var("DATABASE_URL").map_err(|_| "DATABASE_URL is not set")?;
+    let mut client = Client::connect(&url, NoTls)?;
+    let mut adapter = PostgresAdapter::new(&mut client);
+
+    match (command, version) {
+        ("setup", None) => adapter.setup_schema()?,
+        ("status", None) => {
+            let status = adapter.status(migrations.iter().map(|migration| &**migration as &dyn PostgresMigration))?;
+            for record in status.applied {
+                println!("applied  {}  {}", record.version, record.description.unwrap_or_default());
+            }
+            for migration in status.pending {
+                println!("pending  {}  {}", migration.version, migration.description);
+            }
+        }
+        ("up", _) | ("down", _) => {
+            adapter.setup_schema()?;
+            let mut migrator = Migrator::new(adapter);
+            for migration in migrations {
+                migrator.register(migration);
+            }
+
+            if command == "up" {
+                migrator.up(version)?;
+            } else {
+                migrator.down(version)?;
+            }
+            println!("current version: {:?}", migrator.current_version()?);
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use postgres::error::Error as PostgresError;
+use postgres::row::Row;
+use postgres::types::{ToSql, Type};
+use postgres::{Client, GenericClient, IsolationLevel, Statement, Transaction};
+use std::ops::DerefMut;
+
+use crate::SchemamamaPostgresError;
+
+/// A connection a `PostgresAdapter` can run against: either a `Client`, or a `Transaction` owned
+/// by the caller, in which case every migration runs in a savepoint of that transaction and
+/// nothing is committed until the caller commits it.
+pub trait MigrationClient: GenericClient {
+    /// The underlying client, or `None` for a transaction. Migrations that do not run in a
+    /// transaction need it.
+    fn as_client(&mut self) -> Option<&mut Client>;
+
+    /// Begins a transaction at `isolation_level`, or a savepoint (which inherits the isolation
+    /// level of the enclosing transaction) if this is already a transaction.
+    fn begin(&mut self, isolation_level: Option<IsolationLevel>) -> Result<Transaction<'_>, PostgresError>;
+
+    /// Runs `query` as an unnamed statement, with parameters of the given types, in a single round
+    /// trip.
+    fn query_unnamed(&mut self, query: &str, params: &[(&(dyn ToSql + Sync), Type)]) -> Result<Vec<Row>, PostgresError>;
+}
+
+impl MigrationClient for Client {
+    fn as_client(&mut self) -> Option<&mut Client> {
+        Some(self)
+    }
+
+    fn begin(&mut self, isolation_level: Option<IsolationLevel>) -> Result<Transaction<'_>, PostgresError> {
+        match isolation_level {
+            Some(isolation_level) => self.build_transaction().isolation_level(isolation_level).start(),
+            None => self.transaction(),
+        }
+    }
+
+    fn query_unnamed(&mut self, query: &str, params: &[(&(dyn ToSql + Sync), Type)]) -> Result<Vec<Row>, PostgresError> {
+        self.query_typed(query, params)
+    }
+}
+
+impl<'t> MigrationClient for Transaction<'t> {
+    fn as_client(&mut self) -> Option<&mut Client> {
+        None
+    }
+
+    fn begin(&mut self, _isolation_level: Option<IsolationLevel>) -> Result<Transaction<'_>, PostgresError> {
+        self.transaction()
+    }
+
+    fn query_unnamed(&mut self, query: &str, params: &[(&(dyn ToSql + Sync), Type)]) -> Result<Vec<Row>, PostgresError> {
+        self.query_typed(query, params)
+    }
+}
+
+/// A statement a `PostgresAdapter` runs against its metadata tables.
+#[derive(Clone)]
+pub enum MetadataStatement {
+    /// A statement prepared once and cached by the adapter.
+    Prepared(Statement),
+    /// A query prepared each time it runs.
+    Text(String),
+    /// A query sent as an unnamed statement each time it runs, with the types of its parameters,
+    /// for connections going through a pooler that does not support named prepared statements.
+    Unnamed(String, Vec<Type>),
+}
+
+impl MetadataStatement {
+    /// The statement running `query`, whose parameters have the given `types`, without caching it:
+    /// unnamed if `unnamed` is set, or prepared on each use otherwise.
+    pub fn new(query: &str, types: &[Type], unnamed: bool) -> MetadataStatement {
+        if unnamed {
+            MetadataStatement::Unnamed(query.to_owned(), types.to_vec())
+        } else {
+            MetadataStatement::Text(query.to_owned())
+        }
+    }
+
+    /// Runs this statement on `client`, returning the rows it produced, if any.
+    pub fn query<G>(&self, client: &mut G, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, PostgresError>
+    where
+        G: MigrationClient,
+    {
+        match self {
+            MetadataStatement::Prepared(statement) => client.query(statement, params),
+            MetadataStatement::Text(query) => client.query(&**query, params),
+            MetadataStatement::Unnamed(query, types) => {
+                let params: Vec<(&(dyn ToSql + Sync), Type)> =
+                    params.iter().cloned().zip(types.iter().cloned()).collect();
+                client.query_unnamed(query, &params)
+            }
+        }
+    }
+}
+
+/// The connection a `PostgresAdapter` runs against: a client or transaction borrowed from the
+/// caller, a client owned by the adapter (e.g. a connection checked out of a pool), or an outer
+/// transaction opened by the adapter itself for an atomic batch.
+pub enum ClientHandle<'a, C: MigrationClient + 'a> {
+    Borrowed(&'a mut C),
+    Owned(Box<dyn DerefMut<Target = C> + 'a>),
+    Batch(Transaction<'a>),
+    /// The client was moved out of the handle and could not be put back.
+    Closed,
+}
+
+impl<'a, C: MigrationClient + 'a> ClientHandle<'a, C> {
+    /// Returns the underlying client, unless the handle is inside an outer transaction.
+    pub fn client(&mut self) -> Option<&mut Client> {
+        match self {
+            ClientHandle::Borrowed(client) => client.as_client(),
+            ClientHandle::Owned(client) => client.as_client(),
+            ClientHandle::Batch(_) | ClientHandle::Closed => None,
+        }
+    }
+
+    /// Opens the outer transaction of an atomic batch, if it is not open already. Fails if the
+    /// adapter owns its client, as the outer transaction would have to borrow from the adapter
+    /// itself.
+    pub fn begin_batch(&mut self, isolation_level: Option<IsolationLevel>) -> Result<(), SchemamamaPostgresError> {
+        match std::mem::replace(self, ClientHandle::Closed) {
+            ClientHandle::Borrowed(client) => {
+                *self = ClientHandle::Batch(client.begin(isolation_level)?);
+                Ok(())
+            }
+            handle @ ClientHandle::Owned(_) => {
+                *self = handle;
+                Err(SchemamamaPostgresError::BatchRequiresBorrowedClient)
+            }
+            handle => {
+                *self = handle;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn prepare(&mut self, query: &str) -> Result<Statement, PostgresError> {
+        match self {
+            ClientHandle::Batch(transaction) => transaction.prepare(query),
+            handle => handle.expect_connection().prepare(query),
+        }
+    }
+
+    /// Runs `statement` with `params`, returning the rows it produced, if any.
+    pub fn query_metadata(&mut self, statement: &MetadataStatement, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, PostgresError> {
+        match self {
+            ClientHandle::Batch(transaction) => statement.query(transaction, params),
+            handle => statement.query(handle.expect_connection(), params),
+        }
+    }
+
+    pub fn batch_execute(&mut self, query: &str) -> Result<(), PostgresError> {
+        match self {
+            ClientHandle::Batch(transaction) => transaction.batch_execute(query),
+            handle => handle.expect_connection().batch_execute(query),
+        }
+    }
+
+    /// Begins a transaction, which is a savepoint if the handle is inside an outer transaction.
+    /// Savepoints inherit the isolation level of the outer transaction.
+    pub fn transaction(&mut self, isolation_level: Option<IsolationLevel>) -> Result<Transaction<'_>, PostgresError> {
+        match self {
+            ClientHandle::Batch(transaction) => transaction.transaction(),
+            handle => handle.expect_connection().begin(isolation_level),
+        }
+    }
+
+    fn expect_connection(&mut self) -> &mut C {
+        match self {
+            ClientHandle::Borrowed(client) => &mut **client,
+            ClientHandle::Owned(client) => &mut ***client,
+            ClientHandle::Batch(_) | ClientHandle::Closed => panic!("the adapter's client was lost"),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Disposable PostgreSQL servers for integration tests, enabled by the `testcontainers` feature,
+//! so that `cargo test` only needs Docker rather than a local PostgreSQL installation.
+
+use postgres::{Client, NoTls};
+use std::error::Error;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::SyncRunner;
+use testcontainers_modules::testcontainers::Container;
+
+use crate::testing::migrate;
+use crate::PostgresMigration;
+
+/// A PostgreSQL server running in a Docker container, with a client connected to it. The container
+/// is stopped and removed when this is dropped.
+pub struct PostgresContainer {
+    client: Client,
+    url: String,
+    // Declared last so that the client disconnects before the container stops.
+    _container: Container<Postgres>,
+}
+
+impl PostgresContainer {
+    /// Start a container from the official `postgres` image, wait until it accepts connections and
+    /// apply all of `migrations` to its `postgres` database.
+    pub fn start(migrations: Vec<Box<dyn PostgresMigration>>) -> Result<PostgresContainer, Box<dyn Error>> {
+        let container = Postgres::default().start()?;
+        let url = format!(
+            "postgres://postgres:postgres@{}:{}/postgres",
+            container.get_host()?,
+            container.get_host_port_ipv4(5432)?
+        );
+        let mut client = Client::connect(&url, NoTls)?;
+        migrate(&mut client, migrations)?;
+
+        Ok(PostgresContainer { client, url, _container: container })
+    }
+
+    /// The client connected to the container's database.
+    pub fn client(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    /// The URL of the container's database, for opening further connections.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
@@ -0,0 +1,112 @@
+use postgres::error::Error as PostgresError;
+use schemamama::Version;
+use std::collections::HashMap;
+
+use crate::{execute_statements, logging, MigrationClient};
+
+/// What `PostgresMigration::up_ctx` and its siblings run with: the migration's transaction (or
+/// its client, for migrations that do not run in a transaction), the configuration of the adapter
+/// running it, and the application context `A` given to `PostgresAdapter::with_app_context`.
+pub struct MigrationContext<'c, T, A = ()> {
+    client: &'c mut T,
+    schema: Option<&'c str>,
+    environment: Option<&'c str>,
+    placeholders: &'c HashMap<String, String>,
+    app: &'c A,
+    logger: MigrationLogger,
+    failed_statement: Option<(usize, String)>,
+}
+
+impl<'c, T, A> MigrationContext<'c, T, A> {
+    pub(crate) fn new(
+        client: &'c mut T,
+        schema: Option<&'c str>,
+        environment: Option<&'c str>,
+        placeholders: &'c HashMap<String, String>,
+        app: &'c A,
+        version: Version
+    ) -> MigrationContext<'c, T, A> {
+        MigrationContext {
+            client,
+            schema,
+            environment,
+            placeholders,
+            app,
+            logger: MigrationLogger { version },
+            failed_statement: None,
+        }
+    }
+
+    /// The transaction the migration runs in, or its client if it does not run in a transaction.
+    pub fn client(&mut self) -> &mut T {
+        self.client
+    }
+
+    /// The schema of the metadata table, as configured with `PostgresAdapter::with_schema` or
+    /// `with_metadata_table`, if any.
+    pub fn schema(&self) -> Option<&str> {
+        self.schema
+    }
+
+    /// The environment configured with `PostgresAdapter::with_environment`, if any.
+    pub fn environment(&self) -> Option<&str> {
+        self.environment
+    }
+
+    /// The value of the placeholder `name` configured with `PostgresAdapter::with_placeholders`.
+    pub fn placeholder(&self, name: &str) -> Option<&str> {
+        self.placeholders.get(name).map(String::as_str)
+    }
+
+    /// The placeholders configured with `PostgresAdapter::with_placeholders`.
+    pub fn placeholders(&self) -> &HashMap<String, String> {
+        self.placeholders
+    }
+
+    /// The application context given to `PostgresAdapter::with_app_context`, such as an encryption
+    /// key provider or a feature flag client for data migrations, or `()` without one.
+    pub fn app(&self) -> &'c A {
+        self.app
+    }
+
+    /// A logger for messages about the migration, such as the progress of a data migration.
+    pub fn logger(&self) -> MigrationLogger {
+        self.logger
+    }
+
+    /// Run the statements of `sql` with `execute_statements`. If one of them fails, the adapter
+    /// reports its position and text in `SchemamamaPostgresError::Migration`.
+    pub fn execute_statements(&mut self, sql: &str) -> Result<(), PostgresError>
+    where
+        T: MigrationClient,
+    {
+        execute_statements(self.client, sql).map_err(|error| {
+            self.failed_statement = error.index.map(|index| (index, error.statement));
+            error.error
+        })
+    }
+
+    /// Takes the statement that failed in `execute_statements`, if any.
+    pub(crate) fn failed_statement(&mut self) -> Option<(usize, String)> {
+        self.failed_statement.take()
+    }
+}
+
+/// Logs messages about a migration through the `log` crate, prefixed with its version. Without the
+/// `logging` feature, messages are discarded.
+#[derive(Clone, Copy, Debug)]
+pub struct MigrationLogger {
+    version: Version,
+}
+
+impl MigrationLogger {
+    /// Log `message` at the info level.
+    pub fn info(&self, message: &str) {
+        logging::migration_message(self.version, false, message);
+    }
+
+    /// Log `message` at the warning level.
+    pub fn warn(&self, message: &str) {
+        logging::migration_message(self.version, true, message);
+    }
+}
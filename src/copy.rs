@@ -0,0 +1,62 @@
+//! Bulk loading with `COPY ... FROM STDIN`, much faster than row-by-row `INSERT`s for seed and
+//! reference data. The helpers accept a `Transaction` as well as a `Client`, so they can be called
+//! from `PostgresMigration::up`.
+
+use postgres::error::Error as PostgresError;
+use postgres::GenericClient;
+use std::io::Write;
+
+use crate::table::{quote_identifier, quote_qualified};
+
+/// Load `csv`, for example a file embedded with `include_str!`, into `columns` of `table`. If
+/// `header` is set, the first line is skipped. Returns the number of rows loaded.
+///
+/// `table` may be schema-qualified (`schema.table`); it and `columns` are quoted, so they are used
+/// verbatim.
+pub fn copy_csv<G, D>(client: &mut G, table: &str, columns: &[&str], csv: D, header: bool) -> Result<u64, PostgresError>
+where
+    G: GenericClient,
+    D: AsRef<[u8]>,
+{
+    let options = if header { "FORMAT csv, HEADER true" } else { "FORMAT csv" };
+    copy(client, table, columns, options, csv.as_ref())
+}
+
+/// Load `rows` into `columns` of `table`, each row holding one value per column, with `None` for
+/// `NULL`. Values are sent as text and converted to the columns' types by the server. Returns the
+/// number of rows loaded.
+pub fn copy_rows<G, I, R, S>(client: &mut G, table: &str, columns: &[&str], rows: I) -> Result<u64, PostgresError>
+where
+    G: GenericClient,
+    I: IntoIterator<Item = R>,
+    R: IntoIterator<Item = Option<S>>,
+    S: AsRef<str>,
+{
+    let mut csv = String::new();
+    for row in rows {
+        for (i, value) in row.into_iter().enumerate() {
+            if i > 0 {
+                csv.push(',');
+            }
+            // In CSV format, an unquoted empty value is NULL and a quoted one is an empty string.
+            if let Some(value) = value {
+                csv.push('"');
+                csv.push_str(&value.as_ref().replace('"', "\"\""));
+                csv.push('"');
+            }
+        }
+        csv.push('\n');
+    }
+
+    copy(client, table, columns, "FORMAT csv", csv.as_bytes())
+}
+
+fn copy<G: GenericClient>(client: &mut G, table: &str, columns: &[&str], options: &str, data: &[u8]) -> Result<u64, PostgresError> {
+    let columns: Vec<String> = columns.iter().map(|column| quote_identifier(column)).collect();
+    let query = format!("COPY {} ({}) FROM STDIN WITH ({})", quote_qualified(table), columns.join(", "), options);
+
+    let mut writer = client.copy_in(&*query)?;
+    // Writing only fails if the connection does, which `finish` reports as a PostgreSQL error.
+    let _ = writer.write_all(data);
+    writer.finish()
+}
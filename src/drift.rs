@@ -0,0 +1,81 @@
+use schemamama::Version;
+use std::collections::BTreeMap;
+
+use crate::{MigrationClient, PostgresAdapter, SchemamamaPostgresError};
+
+/// The differences between the metadata tables of two databases, as reported by
+/// `PostgresAdapter::compare_with`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Drift {
+    /// Versions applied only to this adapter's database.
+    pub only_here: Vec<Version>,
+    /// Versions applied only to the other adapter's database.
+    pub only_there: Vec<Version>,
+    /// Versions applied to both databases with different checksums.
+    pub checksum_mismatches: Vec<DriftedChecksum>,
+}
+
+impl Drift {
+    /// Whether both databases have the same migrations applied.
+    pub fn is_empty(&self) -> bool {
+        self.only_here.is_empty() && self.only_there.is_empty() && self.checksum_mismatches.is_empty()
+    }
+}
+
+/// A version whose checksum differs between two databases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DriftedChecksum {
+    /// The version applied to both databases with different checksums.
+    pub version: Version,
+    /// The checksum recorded in this adapter's database.
+    pub here: String,
+    /// The checksum recorded in the other adapter's database.
+    pub there: String,
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Compare the metadata table of this adapter's database with that of `other`, e.g. staging
+    /// with production before a release, reporting the versions applied to only one of them and
+    /// the versions whose recorded checksums differ. Versions recorded without a checksum on either
+    /// side are not compared.
+    pub fn compare_with<'b, D, B>(&mut self, other: &mut PostgresAdapter<'b, D, B>) -> Result<Drift, SchemamamaPostgresError>
+    where
+        D: MigrationClient + 'b,
+        B: 'b,
+    {
+        let here = self.applied_checksums()?;
+        let there = other.applied_checksums()?;
+
+        let mut drift = Drift::default();
+        for (&version, checksum) in &here {
+            match there.get(&version) {
+                None => drift.only_here.push(version),
+                Some(other_checksum) => {
+                    if let (Some(checksum), Some(other_checksum)) = (checksum, other_checksum) {
+                        if checksum != other_checksum {
+                            drift.checksum_mismatches.push(DriftedChecksum {
+                                version,
+                                here: checksum.clone(),
+                                there: other_checksum.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        drift.only_there = there.keys().filter(|version| !here.contains_key(version)).cloned().collect();
+
+        Ok(drift)
+    }
+
+    /// The applied versions and their checksums, if recorded.
+    fn applied_checksums(&mut self) -> Result<BTreeMap<Version, Option<String>>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(BTreeMap::new());
+        }
+
+        let query = format!("SELECT version, checksum FROM {} WHERE NOT dirty;", self.metadata_table);
+        let rows = self.metadata_query(&query, &[], &[])?;
+        Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+    }
+}
@@ -0,0 +1,231 @@
+use postgres::error::Error as PostgresError;
+use schemamama::Version;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::{ChecksumMismatch, StatementError};
+
+/// The step of applying or reverting a migration during which an error occurred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Beginning the migration's transaction or executing its `up` function.
+    Up,
+    /// Beginning the migration's transaction or executing its `down` function.
+    Down,
+    /// Recording or erasing the migration's version, or committing its transaction.
+    Record,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Phase::Up => "up",
+            Phase::Down => "down",
+            Phase::Record => "record",
+        })
+    }
+}
+
+/// An error produced by a `PostgresAdapter`.
+#[derive(Debug)]
+pub enum SchemamamaPostgresError {
+    /// Creating or upgrading the metadata table failed.
+    Setup(PostgresError),
+    /// A query that is not part of any particular migration failed, such as reading the metadata
+    /// table.
+    Postgres(PostgresError),
+    /// Applying or reverting a migration failed. If the migration ran its SQL with
+    /// `MigrationContext::execute_statements`, `statement` holds the position (from 1) and text of
+    /// the statement that failed.
+    Migration {
+        version: Version,
+        description: String,
+        phase: Phase,
+        error: PostgresError,
+        statement: Option<(usize, String)>,
+    },
+    /// An atomic batch was requested on an adapter that owns its client.
+    BatchRequiresBorrowedClient,
+    /// A migration that does not run in a transaction was applied or reverted in an atomic batch,
+    /// or by an adapter running against a transaction.
+    NonTransactionalInBatch { version: Version },
+    /// A migration older than the latest applied one was about to be applied, and the adapter's
+    /// `OutOfOrder` policy is `Reject`.
+    OutOfOrder { version: Version, latest: Version },
+    /// The metadata table records versions that no registered migration knows about, which
+    /// usually means an old build is running against a newer database.
+    UnknownVersions { versions: Vec<Version> },
+    /// Writing an export or a script failed.
+    Io(io::Error),
+    /// The history of another migration tool could not be imported.
+    Import(String),
+    /// A migration was about to be reverted by an adapter guarding against destructive
+    /// operations, without `allow_destructive`.
+    DestructiveNotAllowed { version: Version },
+    /// No migration is described by the given name.
+    UnknownMigrationName { name: String },
+    /// Several migrations are described by the given name.
+    AmbiguousMigrationName { name: String, versions: Vec<Version> },
+    /// A migration without an SQL representation was about to be rendered as a script.
+    NoSqlRepresentation { version: Version },
+    /// A migration was interrupted while being applied or reverted outside of a transaction, and
+    /// may be partially applied (see `PostgresAdapter::dirty_version`).
+    Dirty { version: Version },
+    /// Running a repeatable migration failed.
+    Repeatable { name: String, error: PostgresError },
+    /// Running a seed failed.
+    Seed { version: Version, error: PostgresError },
+    /// Applying the pending migrations to the shadow database failed, so they were not applied to
+    /// the real one.
+    Shadow(Box<SchemamamaPostgresError>),
+    /// The advisory lock identified by `key` is held by another migrator, and the adapter's
+    /// `LockBehavior` does not wait (any longer) for it to be released.
+    AlreadyLocked { key: i64 },
+    /// Another instance held the migration lock, but did not apply every migration this one
+    /// expected.
+    PeerIncomplete { pending: Vec<Version> },
+    /// No migration has the given version.
+    UnknownVersion { version: Version },
+    /// Applying a migration on its own would leave older migrations pending, and this was not
+    /// acknowledged.
+    SkipsPending { version: Version, pending: Vec<Version> },
+    /// The version of a migration about to be applied, or just applied, is already recorded in the
+    /// metadata table, e.g. by another migrator running concurrently, and the adapter's
+    /// `RecordConflict` policy is `Fail`.
+    AlreadyRecorded { version: Version },
+    /// A batch run by `execute_statements` failed.
+    Statement(StatementError),
+    /// The table of seeds or repeatable migrations, named after the metadata table, would have a
+    /// name longer than the 63 bytes PostgreSQL allows.
+    SideTableNameTooLong { name: String },
+    /// Applied migrations were edited since they were applied (see
+    /// `PostgresAdapter::verify_checksums`).
+    ChecksumMismatch { mismatches: Vec<ChecksumMismatch> },
+}
+
+impl SchemamamaPostgresError {
+    /// The underlying PostgreSQL error, if any.
+    pub fn postgres_error(&self) -> Option<&PostgresError> {
+        match *self {
+            SchemamamaPostgresError::Setup(ref error) |
+            SchemamamaPostgresError::Postgres(ref error) |
+            SchemamamaPostgresError::Migration { ref error, .. } |
+            SchemamamaPostgresError::Repeatable { ref error, .. } |
+            SchemamamaPostgresError::Seed { ref error, .. } => Some(error),
+            SchemamamaPostgresError::Statement(ref error) => Some(&error.error),
+            SchemamamaPostgresError::BatchRequiresBorrowedClient |
+            SchemamamaPostgresError::NonTransactionalInBatch { .. } |
+            SchemamamaPostgresError::OutOfOrder { .. } |
+            SchemamamaPostgresError::UnknownVersions { .. } |
+            SchemamamaPostgresError::Io(_) |
+            SchemamamaPostgresError::Import(_) |
+            SchemamamaPostgresError::DestructiveNotAllowed { .. } |
+            SchemamamaPostgresError::UnknownMigrationName { .. } |
+            SchemamamaPostgresError::AmbiguousMigrationName { .. } |
+            SchemamamaPostgresError::NoSqlRepresentation { .. } |
+            SchemamamaPostgresError::Dirty { .. } |
+            SchemamamaPostgresError::AlreadyLocked { .. } |
+            SchemamamaPostgresError::PeerIncomplete { .. } |
+            SchemamamaPostgresError::UnknownVersion { .. } |
+            SchemamamaPostgresError::SkipsPending { .. } |
+            SchemamamaPostgresError::AlreadyRecorded { .. } |
+            SchemamamaPostgresError::SideTableNameTooLong { .. } |
+            SchemamamaPostgresError::ChecksumMismatch { .. } => None,
+            SchemamamaPostgresError::Shadow(ref error) => error.postgres_error(),
+        }
+    }
+}
+
+impl fmt::Display for SchemamamaPostgresError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SchemamamaPostgresError::Setup(ref error) => {
+                write!(f, "failed to set up the metadata table: {}", error)
+            }
+            SchemamamaPostgresError::Postgres(ref error) => write!(f, "{}", error),
+            SchemamamaPostgresError::Migration { version, ref description, phase, ref error, ref statement } => {
+                write!(f, "migration {} ({}) failed during {}: {}", version, description, phase, error)?;
+                match *statement {
+                    Some((index, ref statement)) => write!(f, " (statement {}: {})", index, statement),
+                    None => Ok(()),
+                }
+            }
+            SchemamamaPostgresError::BatchRequiresBorrowedClient => {
+                f.write_str("atomic batches require an adapter that borrows its client")
+            }
+            SchemamamaPostgresError::NonTransactionalInBatch { version } => {
+                write!(f, "migration {} does not run in a transaction and cannot run inside an outer transaction", version)
+            }
+            SchemamamaPostgresError::OutOfOrder { version, latest } => {
+                write!(f, "migration {} is older than the latest applied migration {}", version, latest)
+            }
+            SchemamamaPostgresError::UnknownVersions { ref versions } => {
+                write!(f, "the metadata table records unknown migration versions {:?}", versions)
+            }
+            SchemamamaPostgresError::Io(ref error) => write!(f, "{}", error),
+            SchemamamaPostgresError::Import(ref message) => write!(f, "cannot import migration history: {}", message),
+            SchemamamaPostgresError::DestructiveNotAllowed { version } => {
+                write!(f, "migration {} was not reverted: destructive operations are not allowed", version)
+            }
+            SchemamamaPostgresError::UnknownMigrationName { ref name } => write!(f, "no migration is named {:?}", name),
+            SchemamamaPostgresError::AmbiguousMigrationName { ref name, ref versions } => {
+                write!(f, "migrations {:?} are all named {:?}", versions, name)
+            }
+            SchemamamaPostgresError::NoSqlRepresentation { version } => {
+                write!(f, "migration {} is implemented in Rust and cannot be rendered as SQL", version)
+            }
+            SchemamamaPostgresError::Dirty { version } => {
+                write!(f, "migration {} was interrupted and may be partially applied; repair it and call resolve_dirty", version)
+            }
+            SchemamamaPostgresError::Repeatable { ref name, ref error } => {
+                write!(f, "repeatable migration {} failed: {}", name, error)
+            }
+            SchemamamaPostgresError::Seed { version, ref error } => write!(f, "seed {} failed: {}", version, error),
+            SchemamamaPostgresError::Shadow(ref error) => write!(f, "shadow database: {}", error),
+            SchemamamaPostgresError::AlreadyLocked { key } => {
+                write!(f, "the advisory lock {} is held by another migrator", key)
+            }
+            SchemamamaPostgresError::PeerIncomplete { ref pending } => {
+                write!(f, "another instance finished migrating without applying migrations {:?}", pending)
+            }
+            SchemamamaPostgresError::UnknownVersion { version } => write!(f, "no migration has version {}", version),
+            SchemamamaPostgresError::SkipsPending { version, ref pending } => {
+                write!(f, "applying migration {} alone would skip pending migrations {:?}", version, pending)
+            }
+            SchemamamaPostgresError::AlreadyRecorded { version } => {
+                write!(f, "migration {} is already recorded in the metadata table", version)
+            }
+            SchemamamaPostgresError::Statement(ref error) => write!(f, "{}", error),
+            SchemamamaPostgresError::SideTableNameTooLong { ref name } => {
+                write!(f, "the table name {:?} is longer than the 63 bytes PostgreSQL allows", name)
+            }
+            SchemamamaPostgresError::ChecksumMismatch { ref mismatches } => {
+                let versions: Vec<Version> = mismatches.iter().map(|mismatch| mismatch.version).collect();
+                write!(f, "applied migrations {:?} were edited since they were applied", versions)
+            }
+        }
+    }
+}
+
+impl Error for SchemamamaPostgresError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SchemamamaPostgresError::Io(ref error) => Some(error),
+            SchemamamaPostgresError::Shadow(ref error) => Some(&**error),
+            _ => self.postgres_error().map(|error| error as &(dyn Error + 'static)),
+        }
+    }
+}
+
+impl From<PostgresError> for SchemamamaPostgresError {
+    fn from(error: PostgresError) -> SchemamamaPostgresError {
+        SchemamamaPostgresError::Postgres(error)
+    }
+}
+
+impl From<StatementError> for SchemamamaPostgresError {
+    fn from(error: StatementError) -> SchemamamaPostgresError {
+        SchemamamaPostgresError::Statement(error)
+    }
+}
@@ -0,0 +1,96 @@
+use schemamama::Version;
+use std::fmt::Write;
+
+use crate::Plan;
+
+/// An applied migration as exported by `PostgresAdapter::export_metadata`.
+pub struct ExportedMigration {
+    /// The version of the migration.
+    pub version: Version,
+    /// The description of the migration, if recorded.
+    pub description: Option<String>,
+    /// The application time in RFC 3339 format, in UTC with microseconds, e.g.
+    /// `2024-06-01T12:30:00.000000Z`, if recorded.
+    pub applied_at: Option<String>,
+    /// The database user that applied the migration, if recorded.
+    pub applied_by: Option<String>,
+    /// The checksum of the migration as a string in the format of its checksum algorithm, e.g. a
+    /// hex-encoded SHA-256 digest, if recorded.
+    pub checksum: Option<String>,
+}
+
+/// Renders `migrations` as a JSON document of the form `{"migrations": [...]}`, with one object
+/// per migration holding its `version`, `description`, `applied_at`, `applied_by` and `checksum`
+/// (`null` when unknown).
+pub fn render_json(migrations: &[ExportedMigration]) -> String {
+    let mut json = String::from("{\"migrations\":[");
+    for (i, migration) in migrations.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "{{\"version\":{},\"description\":{},\"applied_at\":{},\"applied_by\":{},\"checksum\":{}}}",
+            migration.version,
+            json_string(&migration.description),
+            json_string(&migration.applied_at),
+            json_string(&migration.applied_by),
+            json_string(&migration.checksum)
+        ).unwrap();
+    }
+    json.push_str("]}");
+    json
+}
+
+/// Renders `plan` as described for `Plan::to_json`.
+pub fn render_plan_json(plan: &Plan) -> String {
+    let mut json = format!(
+        "{{\"current_version\":{},\"target_version\":{},\"migrations\":[",
+        json_number(plan.current_version),
+        json_number(plan.target_version)
+    );
+    for (i, migration) in plan.migrations.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "{{\"version\":{},\"description\":{},\"transactional\":{},\"checksum\":{}}}",
+            migration.version,
+            json_string(&Some(migration.description.clone())),
+            migration.transactional,
+            json_string(&migration.checksum)
+        ).unwrap();
+    }
+    json.push_str("]}");
+    json
+}
+
+/// Renders `value` as a JSON number, or `null`.
+fn json_number(value: Option<Version>) -> String {
+    value.map_or("null".to_owned(), |value| value.to_string())
+}
+
+/// Renders `value` as a JSON string, or `null`.
+fn json_string(value: &Option<String>) -> String {
+    let value = match *value {
+        Some(ref value) => value,
+        None => return "null".to_owned(),
+    };
+
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
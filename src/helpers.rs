@@ -0,0 +1,277 @@
+//! Zero-downtime schema changes following the expand/contract pattern: each change is split into
+//! short steps that never hold a lock blocking reads and writes for longer than a catalog update,
+//! with the slow work (scanning or rewriting rows) done under weaker locks or in batches.
+//!
+//! Every step commits separately when given a `Client`, so call these helpers from
+//! `PostgresMigration::up_without_transaction`. Given a `Transaction`, steps run in savepoints
+//! and their locks are held until the migration commits, which defeats most of their purpose.
+
+use postgres::error::Error as PostgresError;
+
+use crate::script::quote_literal;
+use crate::table::{quote_identifier, quote_qualified};
+use crate::{Backfill, MigrationClient};
+
+/// Add `column` to `table` with the given type (e.g. `TEXT`), nullable and without a default, so
+/// that the table is neither rewritten nor scanned. Does nothing if the column exists.
+pub fn add_nullable_column<C: MigrationClient>(
+    client: &mut C,
+    table: &str,
+    column: &str,
+    column_type: &str
+) -> Result<(), PostgresError> {
+    step(client, &format!(
+        "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {};",
+        quote_qualified(table),
+        quote_identifier(column),
+        column_type
+    ))
+}
+
+/// Make `column` of `table` `NOT NULL` without holding an `ACCESS EXCLUSIVE` lock while the table
+/// is scanned: a `CHECK (column IS NOT NULL)` constraint is added as `NOT VALID`, validated under
+/// a `SHARE UPDATE EXCLUSIVE` lock, and then lets `SET NOT NULL` skip its scan (PostgreSQL 12 and
+/// later) before being dropped.
+///
+/// Does nothing if the column is already `NOT NULL`, and can be run again after being interrupted,
+/// e.g. when validation fails on a row that is still `NULL`.
+pub fn set_not_null<C: MigrationClient>(client: &mut C, table: &str, column: &str) -> Result<(), PostgresError> {
+    let row = client.query_opt(
+        "SELECT attnotnull FROM pg_attribute WHERE attrelid = to_regclass($1) AND attname = $2;",
+        &[&quote_qualified(table), &column]
+    )?;
+    if row.map_or(false, |row| row.get(0)) {
+        return Ok(());
+    }
+
+    let table = quote_qualified(table);
+    let constraint = quote_identifier(&format!("{}_not_null", column));
+    let column = quote_identifier(column);
+
+    step(client, &format!(
+        "ALTER TABLE {table} DROP CONSTRAINT IF EXISTS {constraint}, \
+         ADD CONSTRAINT {constraint} CHECK ({column} IS NOT NULL) NOT VALID;",
+        table = table,
+        constraint = constraint,
+        column = column
+    ))?;
+    step(client, &format!("ALTER TABLE {} VALIDATE CONSTRAINT {};", table, constraint))?;
+    step(client, &format!("ALTER TABLE {} ALTER COLUMN {} SET NOT NULL;", table, column))?;
+    step(client, &format!("ALTER TABLE {} DROP CONSTRAINT {};", table, constraint))
+}
+
+/// Add `value` to the enum type `type_name` (which may be schema-qualified), unless it is one of
+/// its values already. The statement runs on its own rather than in a transaction, so that the
+/// new value can be used as soon as it returns, and so that it works before PostgreSQL 12, which
+/// rejects `ALTER TYPE ... ADD VALUE` in a transaction block. Given a `Transaction`, it runs in
+/// that transaction, which requires PostgreSQL 12 and keeps the value unusable until it commits.
+pub fn add_enum_value<C: MigrationClient>(client: &mut C, type_name: &str, value: &str) -> Result<(), PostgresError> {
+    client.batch_execute(&format!(
+        "ALTER TYPE {} ADD VALUE IF NOT EXISTS {};",
+        quote_qualified(type_name),
+        quote_literal(value)
+    ))
+}
+
+/// Add the constraint `name` to `table` as `NOT VALID`, e.g. with the definition
+/// `FOREIGN KEY (user_id) REFERENCES users (id)` or `CHECK (amount >= 0)`. Only rows written from
+/// now on are checked, so the table is not scanned; validate the existing rows later, e.g. in a
+/// following migration, with `validate_constraint`. Until then the constraint is listed by
+/// `PostgresAdapter::pending_validations`.
+pub fn add_constraint_not_valid<C: MigrationClient>(
+    client: &mut C,
+    table: &str,
+    name: &str,
+    definition: &str
+) -> Result<(), PostgresError> {
+    step(client, &format!(
+        "ALTER TABLE {} ADD CONSTRAINT {} {} NOT VALID;",
+        quote_qualified(table),
+        quote_identifier(name),
+        definition
+    ))
+}
+
+/// Validate the existing rows of `table` against the constraint `name`, added with
+/// `add_constraint_not_valid`. Validation holds a `SHARE UPDATE EXCLUSIVE` lock, which does not
+/// block reads and writes.
+pub fn validate_constraint<C: MigrationClient>(client: &mut C, table: &str, name: &str) -> Result<(), PostgresError> {
+    step(client, &format!("ALTER TABLE {} VALIDATE CONSTRAINT {};", quote_qualified(table), quote_identifier(name)))
+}
+
+/// Add a `NOT NULL` column to `table`: add it as nullable, fill it with `value` (an SQL expression
+/// that may refer to the row's other columns) in batches of `batch_size` rows keyed by the
+/// `BIGINT` column `key`, then make it `NOT NULL` with `set_not_null`.
+pub fn add_column_with_backfill<C: MigrationClient>(
+    client: &mut C,
+    table: &str,
+    column: &str,
+    column_type: &str,
+    value: &str,
+    key: &str,
+    batch_size: i64
+) -> Result<(), PostgresError> {
+    add_nullable_column(client, table, column, column_type)?;
+
+    let query = format!(
+        "WITH batch AS (SELECT {key} FROM {table} WHERE {key} > $1 AND {column} IS NULL ORDER BY {key} LIMIT $2) \
+         UPDATE {table} SET {column} = {value} FROM batch WHERE {table}.{key} = batch.{key} \
+         RETURNING {table}.{key}",
+        table = quote_qualified(table),
+        column = quote_identifier(column),
+        key = quote_identifier(key),
+        value = value
+    );
+    Backfill::new(&query).with_batch_size(batch_size).run(client)?;
+
+    set_not_null(client, table, column)
+}
+
+/// Changes the type of a column of a large table without rewriting it under an `ACCESS EXCLUSIVE`
+/// lock, in steps that are meant to be called in order, possibly from several migrations:
+///
+/// 1. `start` adds a column named `{column}_new` of the new type, and a trigger that keeps it in
+///    sync with `column` (converted with `CAST`) on every insert and update;
+/// 2. `backfill` converts the existing rows in batches (or `backfill_query` feeds a `Backfill`
+///    configured with throttling or progress reporting);
+/// 3. `swap` renames `column` to `{column}_old` and `{column}_new` to `column`, and drops the
+///    trigger, in one short transaction;
+/// 4. `drop_old` drops `{column}_old` once nothing reads it any more.
+///
+/// ```rust,ignore
+/// let change = ColumnTypeChange::new("events", "id", "BIGINT");
+/// change.start(client)?;
+/// change.backfill(client, "id", 10_000)?;
+/// change.swap(client)?;
+/// change.drop_old(client)?;
+/// ```
+///
+/// Indexes, constraints and defaults of the column are not carried over: create them on
+/// `{column}_new` (e.g. with `create_index_concurrently`) before `swap`.
+#[derive(Clone, Debug)]
+pub struct ColumnTypeChange<'q> {
+    table: &'q str,
+    column: &'q str,
+    new_type: &'q str,
+}
+
+impl<'q> ColumnTypeChange<'q> {
+    /// Describe changing `column` of `table` (which may be schema-qualified) to `new_type`.
+    pub fn new(table: &'q str, column: &'q str, new_type: &'q str) -> ColumnTypeChange<'q> {
+        ColumnTypeChange { table, column, new_type }
+    }
+
+    /// Add the new column and the trigger filling it in as rows are written.
+    pub fn start<C: MigrationClient>(&self, client: &mut C) -> Result<(), PostgresError> {
+        step(client, &format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {new} {new_type};
+             CREATE OR REPLACE FUNCTION {function}() RETURNS trigger LANGUAGE plpgsql AS $sync$
+             BEGIN
+                 NEW.{new} := CAST(NEW.{column} AS {new_type});
+                 RETURN NEW;
+             END
+             $sync$;
+             DROP TRIGGER IF EXISTS {trigger} ON {table};
+             CREATE TRIGGER {trigger} BEFORE INSERT OR UPDATE ON {table}
+                 FOR EACH ROW EXECUTE FUNCTION {function}();",
+            table = quote_qualified(self.table),
+            column = quote_identifier(self.column),
+            new = self.new_column(),
+            new_type = self.new_type,
+            function = self.function(),
+            trigger = self.trigger()
+        ))
+    }
+
+    /// The query converting one batch of existing rows, keyed by the `BIGINT` column `key`, for a
+    /// `Backfill`.
+    pub fn backfill_query(&self, key: &str) -> String {
+        format!(
+            "WITH batch AS (SELECT {key} FROM {table} WHERE {key} > $1 ORDER BY {key} LIMIT $2) \
+             UPDATE {table} SET {new} = CAST({column} AS {new_type}) FROM batch WHERE {table}.{key} = batch.{key} \
+             RETURNING {table}.{key}",
+            table = quote_qualified(self.table),
+            column = quote_identifier(self.column),
+            new = self.new_column(),
+            new_type = self.new_type,
+            key = quote_identifier(key)
+        )
+    }
+
+    /// Convert the existing rows in batches of `batch_size` rows keyed by the `BIGINT` column
+    /// `key`, returning the number of rows converted.
+    pub fn backfill<C: MigrationClient>(&self, client: &mut C, key: &str, batch_size: i64) -> Result<u64, PostgresError> {
+        Backfill::new(&self.backfill_query(key)).with_batch_size(batch_size).run(client)
+    }
+
+    /// Put the new column in place of the old one and drop the trigger.
+    pub fn swap<C: MigrationClient>(&self, client: &mut C) -> Result<(), PostgresError> {
+        step(client, &format!(
+            "DROP TRIGGER {trigger} ON {table};
+             DROP FUNCTION {function}();
+             ALTER TABLE {table} RENAME COLUMN {column} TO {old};
+             ALTER TABLE {table} RENAME COLUMN {new} TO {column};",
+            table = quote_qualified(self.table),
+            column = quote_identifier(self.column),
+            old = quote_identifier(&format!("{}_old", self.column)),
+            new = self.new_column(),
+            function = self.function(),
+            trigger = self.trigger()
+        ))
+    }
+
+    /// Drop the old column, renamed `{column}_old` by `swap`.
+    pub fn drop_old<C: MigrationClient>(&self, client: &mut C) -> Result<(), PostgresError> {
+        step(client, &format!(
+            "ALTER TABLE {} DROP COLUMN IF EXISTS {};",
+            quote_qualified(self.table),
+            quote_identifier(&format!("{}_old", self.column))
+        ))
+    }
+
+    fn new_column(&self) -> String {
+        quote_identifier(&format!("{}_new", self.column))
+    }
+
+    /// The trigger function, in the table's schema.
+    fn function(&self) -> String {
+        quote_qualified(&format!("{}_{}_sync", self.table, self.column))
+    }
+
+    fn trigger(&self) -> String {
+        let table = self.table.rsplit('.').next().unwrap_or(self.table);
+        quote_identifier(&format!("{}_{}_sync", table, self.column))
+    }
+}
+
+/// Rename table `from` to `to`, leaving behind a view named `from` so that code still using the
+/// old name keeps working (simple views are updatable) until it is deployed everywhere. Drop the
+/// view with `drop_rename_shim` once nothing uses the old name.
+///
+/// `from` may be schema-qualified (`schema.table`); `to` is a bare name, as the table stays in its
+/// schema.
+pub fn rename_table_with_shim<C: MigrationClient>(client: &mut C, from: &str, to: &str) -> Result<(), PostgresError> {
+    let renamed = match from.find('.') {
+        Some(dot) => format!("{}.{}", quote_identifier(&from[..dot]), quote_identifier(to)),
+        None => quote_identifier(to),
+    };
+
+    step(client, &format!(
+        "ALTER TABLE {from} RENAME TO {to}; CREATE VIEW {from} AS SELECT * FROM {renamed};",
+        from = quote_qualified(from),
+        to = quote_identifier(to),
+        renamed = renamed
+    ))
+}
+
+/// Drop the view left behind by `rename_table_with_shim` under the table's old name.
+pub fn drop_rename_shim<C: MigrationClient>(client: &mut C, from: &str) -> Result<(), PostgresError> {
+    step(client, &format!("DROP VIEW IF EXISTS {};", quote_qualified(from)))
+}
+
+/// Runs `sql` in a transaction of its own.
+fn step<C: MigrationClient>(client: &mut C, sql: &str) -> Result<(), PostgresError> {
+    let mut transaction = client.begin(None)?;
+    transaction.batch_execute(sql)?;
+    transaction.commit()
+}
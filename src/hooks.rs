@@ -0,0 +1,22 @@
+use schemamama::Version;
+
+/// Callbacks invoked by a `PostgresAdapter` around each migration it applies or reverts, e.g. to
+/// flush caches or send notifications. Every function has an empty body by default, so
+/// implementations only need to define the callbacks they are interested in.
+pub trait MigrationHooks {
+    /// Called before a migration is applied.
+    #[allow(unused_variables)]
+    fn before_apply(&mut self, version: Version, description: &str) {}
+
+    /// Called after a migration was applied successfully.
+    #[allow(unused_variables)]
+    fn after_apply(&mut self, version: Version, description: &str) {}
+
+    /// Called before a migration is reverted.
+    #[allow(unused_variables)]
+    fn before_revert(&mut self, version: Version, description: &str) {}
+
+    /// Called after a migration was reverted successfully.
+    #[allow(unused_variables)]
+    fn after_revert(&mut self, version: Version, description: &str) {}
+}
@@ -0,0 +1,158 @@
+//! Importers seeding the metadata table from the bookkeeping tables of other migration tools, so
+//! that projects switching to Schemamama do not run their existing migrations again.
+
+use postgres::types::{ToSql, Type};
+use schemamama::Version;
+use std::time::SystemTime;
+
+use crate::table::MetadataTable;
+use crate::{MigrationClient, PostgresAdapter, SchemamamaPostgresError};
+
+/// A migration applied by another tool.
+struct ImportedMigration {
+    version: Version,
+    description: Option<String>,
+    applied_at: Option<SystemTime>,
+    applied_by: Option<String>,
+    checksum: Option<String>,
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Record the versions applied successfully by Flyway, as listed in its history `table`
+    /// (usually `flyway_schema_history`, optionally schema-qualified), along with their
+    /// descriptions, installation times and users. Repeatable migrations, which have no version,
+    /// are skipped, and versions that are already recorded are left alone. Returns the imported
+    /// versions in ascending order.
+    ///
+    /// Schemamama versions are integers, so Flyway versions such as `1.1` cannot be imported and
+    /// fail with `SchemamamaPostgresError::Import` before anything is recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `table` is not a valid table name (see `with_metadata_table`).
+    pub fn import_from_flyway(&mut self, table: &str) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        self.flyway(table, false)
+    }
+
+    /// Like `import_from_flyway`, but record Flyway's checksums too, so that `verify_checksums`
+    /// keeps checking the imported versions. They only match the checksums of migrations computing
+    /// them the same way, such as `SqlMigration`s using `ChecksumAlgorithm::Crc32`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `table` is not a valid table name (see `with_metadata_table`).
+    pub fn import_from_flyway_with_checksums(&mut self, table: &str) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        self.flyway(table, true)
+    }
+
+    fn flyway(&mut self, table: &str, carry_checksums: bool) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        let query = format!(
+            "SELECT version, description, installed_on::timestamptz, installed_by, checksum::TEXT FROM {} \
+             WHERE success AND version IS NOT NULL ORDER BY installed_rank;",
+            MetadataTable::parse(table)
+        );
+        let rows = self.metadata_query(&query, &[], &[])?;
+
+        let mut migrations = Vec::with_capacity(rows.len());
+        for row in rows {
+            let version: String = row.get(0);
+            migrations.push(ImportedMigration {
+                version: parse_version("Flyway", &version)?,
+                description: row.get(1),
+                applied_at: row.get(2),
+                applied_by: row.get(3),
+                checksum: if carry_checksums { row.get(4) } else { None },
+            });
+        }
+
+        self.import(migrations)
+    }
+
+    /// Record the versions applied by Diesel, as listed in its `__diesel_schema_migrations` table,
+    /// along with the times they were run. Diesel does not record descriptions. Versions that are
+    /// already recorded are left alone. Returns the imported versions in ascending order.
+    ///
+    /// Diesel versions are the timestamps prefixing migration directories, such as
+    /// `20240601123000` (or `2024-06-01-123000`, whose dashes are dropped), and become the same
+    /// integer versions.
+    pub fn import_from_diesel(&mut self) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        let rows = self.metadata_query("SELECT version, run_on::timestamptz FROM __diesel_schema_migrations;", &[], &[])?;
+
+        let mut migrations = Vec::with_capacity(rows.len());
+        for row in rows {
+            let version: String = row.get(0);
+            migrations.push(ImportedMigration {
+                version: parse_version("Diesel", &version.replace('-', ""))?,
+                description: None,
+                applied_at: row.get(1),
+                applied_by: None,
+                checksum: None,
+            });
+        }
+
+        self.import(migrations)
+    }
+
+    /// Record the versions applied successfully by sqlx, as listed in its `_sqlx_migrations` table,
+    /// along with their descriptions and installation times. Versions that are already recorded
+    /// are left alone. Returns the imported versions in ascending order.
+    ///
+    /// If `carry_checksums` is set, sqlx's checksums are recorded too, hex-encoded. sqlx computes
+    /// them as the SHA-384 of each migration's SQL, so `verify_checksums` only accepts them from
+    /// migrations whose `checksum` is computed the same way; leave it unset for migrations using
+    /// the default SHA-256 checksums of `SqlMigration`.
+    pub fn import_from_sqlx(&mut self, carry_checksums: bool) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        let rows = self.metadata_query(
+            "SELECT version, description, installed_on, encode(checksum, 'hex') FROM _sqlx_migrations WHERE success;",
+            &[],
+            &[]
+        )?;
+
+        let migrations = rows.iter().map(|row| ImportedMigration {
+            version: row.get(0),
+            description: row.get(1),
+            applied_at: row.get(2),
+            applied_by: None,
+            checksum: if carry_checksums { row.get(3) } else { None },
+        }).collect();
+
+        self.import(migrations)
+    }
+
+    /// Records `migrations` in a single transaction, returning the versions that were not
+    /// recorded already.
+    fn import(&mut self, migrations: Vec<ImportedMigration>) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        let query = format!(
+            "INSERT INTO {} (version, description, applied_at, applied_by, checksum) VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (version) DO NOTHING RETURNING version;",
+            self.metadata_table
+        );
+        let statement = self.prepare_cached(query, &[Type::INT8, Type::TEXT, Type::TIMESTAMPTZ, Type::TEXT, Type::TEXT])?;
+        self.invalidate_cached_versions();
+
+        let mut imported = vec![];
+        let mut transaction = self.client.transaction(self.isolation_level)?;
+        for migration in &migrations {
+            let params: [&(dyn ToSql + Sync); 5] = [
+                &migration.version,
+                &migration.description,
+                &migration.applied_at,
+                &migration.applied_by,
+                &migration.checksum,
+            ];
+            if !statement.query(&mut transaction, &params)?.is_empty() {
+                imported.push(migration.version);
+            }
+        }
+        transaction.commit()?;
+
+        imported.sort();
+        Ok(imported)
+    }
+}
+
+fn parse_version(tool: &str, version: &str) -> Result<Version, SchemamamaPostgresError> {
+    version.trim().parse().map_err(|_| {
+        SchemamamaPostgresError::Import(format!("{} version {:?} is not an integer", tool, version))
+    })
+}
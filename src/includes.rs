@@ -0,0 +1,68 @@
+//! Expansion of `-- include:` lines in SQL files. The `embed_postgres_migrations!` macro compiles
+//! this file as a module of its own, so that SQL files are expanded the same way whether they are
+//! loaded at run time or embedded at compile time.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Reads the SQL file `path`, replacing each line of the form `-- include: shared/functions.sql`
+/// with the contents of that file, resolved relative to `directory`, recursively. Included files
+/// must lie inside `directory` and may not include themselves. The canonical paths of the files
+/// read, `path` included, are appended to `read`.
+pub fn read_with_includes(directory: &Path, path: &Path, read: &mut Vec<PathBuf>) -> io::Result<String> {
+    let directory = canonicalize(directory)?;
+    let path = canonicalize(path)?;
+    expand(&directory, &path, &mut vec![], read)
+}
+
+/// Expands the file at the canonical `path`, which is included by the files in `including`.
+fn expand(directory: &Path, path: &Path, including: &mut Vec<PathBuf>, read: &mut Vec<PathBuf>) -> io::Result<String> {
+    if including.iter().any(|included| included == path) {
+        return Err(invalid_data(format!("{} includes itself", path.display())));
+    }
+
+    let sql = fs::read_to_string(path).map_err(|error| {
+        io::Error::new(error.kind(), format!("cannot read {}: {}", path.display(), error))
+    })?;
+    including.push(path.to_owned());
+    read.push(path.to_owned());
+    let mut expanded = String::with_capacity(sql.len());
+    for line in sql.split_inclusive('\n') {
+        match line.trim().strip_prefix("-- include:") {
+            Some(include) => {
+                let include = include.trim();
+                let included_path = canonicalize(&directory.join(include))?;
+                if !included_path.starts_with(directory) {
+                    return Err(invalid_data(format!(
+                        "{} includes {}, which is outside of {}",
+                        path.display(),
+                        include,
+                        directory.display()
+                    )));
+                }
+
+                let included = expand(directory, &included_path, including, read)?;
+                expanded.push_str(&included);
+                if !included.ends_with('\n') {
+                    expanded.push('\n');
+                }
+            }
+            None => expanded.push_str(line),
+        }
+    }
+    including.pop();
+
+    Ok(expanded)
+}
+
+/// Resolves `path` to an absolute path without `.`, `..` or symbolic links.
+fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    path.canonicalize().map_err(|error| {
+        io::Error::new(error.kind(), format!("cannot read {}: {}", path.display(), error))
+    })
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
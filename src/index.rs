@@ -0,0 +1,101 @@
+use postgres::error::Error as PostgresError;
+use postgres::Client;
+
+use crate::is_transient;
+use crate::table::{quote_identifier, quote_qualified};
+
+/// Options of `create_index_concurrently`.
+#[derive(Clone, Debug)]
+pub struct IndexOptions {
+    /// The name of the index. Defaults to `{table}_{columns}_idx`, like PostgreSQL's own default.
+    pub name: Option<String>,
+    /// Whether to create a unique index.
+    pub unique: bool,
+    /// The index method, such as `gin`. Defaults to the server's default, `btree`.
+    pub method: Option<String>,
+    /// The condition of a partial index, as SQL.
+    pub predicate: Option<String>,
+    /// The number of times creating the index is attempted in total, if it fails with a transient
+    /// error (see `is_transient`).
+    pub max_attempts: u32,
+}
+
+impl Default for IndexOptions {
+    fn default() -> IndexOptions {
+        IndexOptions { name: None, unique: false, method: None, predicate: None, max_attempts: 3 }
+    }
+}
+
+/// Create an index on `columns` of `table` with `CREATE INDEX CONCURRENTLY`, which does not block
+/// writes to the table. It cannot run in a transaction, so call it from
+/// `PostgresMigration::up_without_transaction`. Returns the name of the index.
+///
+/// A failed concurrent build leaves an `INVALID` index behind, which is still maintained on every
+/// write but never used by queries. Such indexes are dropped before building the index and after a
+/// failed attempt, and building is attempted again if it failed with a transient error. If a valid
+/// index with the same name already exists, nothing is done, so the call can safely be repeated.
+///
+/// `table` may be schema-qualified (`schema.table`); the index is created in the table's schema.
+pub fn create_index_concurrently(
+    client: &mut Client,
+    table: &str,
+    columns: &[&str],
+    options: &IndexOptions
+) -> Result<String, PostgresError> {
+    let (schema, table_name) = match table.find('.') {
+        Some(dot) => (Some(&table[..dot]), &table[dot + 1..]),
+        None => (None, table),
+    };
+    let name = options.name.clone().unwrap_or_else(|| format!("{}_{}_idx", table_name, columns.join("_")));
+    let qualified_name = match schema {
+        Some(schema) => format!("{}.{}", quote_identifier(schema), quote_identifier(&name)),
+        None => quote_identifier(&name),
+    };
+
+    let mut create = format!(
+        "CREATE {}INDEX CONCURRENTLY {} ON {}",
+        if options.unique { "UNIQUE " } else { "" },
+        quote_identifier(&name),
+        quote_qualified(table)
+    );
+    if let Some(ref method) = options.method {
+        create.push_str(&format!(" USING {}", method));
+    }
+    let columns: Vec<String> = columns.iter().map(|column| quote_identifier(column)).collect();
+    create.push_str(&format!(" ({})", columns.join(", ")));
+    if let Some(ref predicate) = options.predicate {
+        create.push_str(&format!(" WHERE {}", predicate));
+    }
+
+    let mut attempt = 1;
+    loop {
+        match index_validity(client, &qualified_name)? {
+            Some(true) => return Ok(name),
+            Some(false) => drop_index(client, &qualified_name)?,
+            None => {}
+        }
+
+        match client.batch_execute(&create) {
+            Ok(()) => return Ok(name),
+            Err(error) => {
+                if index_validity(client, &qualified_name)? == Some(false) {
+                    drop_index(client, &qualified_name)?;
+                }
+                if attempt >= options.max_attempts || !is_transient(&error) {
+                    return Err(error);
+                }
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Whether the index named `qualified_name` is valid, or `None` if it does not exist.
+fn index_validity(client: &mut Client, qualified_name: &str) -> Result<Option<bool>, PostgresError> {
+    let row = client.query_opt("SELECT indisvalid FROM pg_index WHERE indexrelid = to_regclass($1);", &[&qualified_name])?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+fn drop_index(client: &mut Client, qualified_name: &str) -> Result<(), PostgresError> {
+    client.batch_execute(&format!("DROP INDEX CONCURRENTLY IF EXISTS {};", qualified_name))
+}
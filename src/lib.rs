@@ -1,13 +1,119 @@
 extern crate schemamama;
 extern crate postgres;
 
-use postgres::error::Error as PostgresError;
-use postgres::{Client, Transaction};
+use postgres::error::{Error as PostgresError, SqlState};
+use postgres::types::ToSql;
+use postgres::row::Row;
+use postgres::types::Type;
+use postgres::{Client, IsolationLevel, Statement, Transaction};
 use schemamama::{Adapter, Migration, Version};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-/// A migration to be used within a PostgreSQL client.
-pub trait PostgresMigration : Migration {
+use client::{ClientHandle, MetadataStatement};
+use export::ExportedMigration;
+use table::{quote_identifier, quote_qualified, validate_identifier, MetadataTable};
+
+pub use backfill::{Backfill, BackfillProgress};
+pub use batch::{execute_statements, StatementError};
+pub use checksum::{sha256_hex, ChecksumAlgorithm};
+pub use client::MigrationClient;
+pub use context::{MigrationContext, MigrationLogger};
+pub use copy::{copy_csv, copy_rows};
+pub use drift::{Drift, DriftedChecksum};
+
+pub use error::{Phase, SchemamamaPostgresError};
+pub use helpers::{
+    add_column_with_backfill, add_constraint_not_valid, add_enum_value, add_nullable_column, drop_rename_shim,
+    rename_table_with_shim, set_not_null, validate_constraint, ColumnTypeChange,
+};
+pub use hooks::MigrationHooks;
+pub use index::{create_index_concurrently, IndexOptions};
+pub use lint::LockWarning;
+pub use multi_schema::{MultiSchemaRunner, SchemaOutcome};
+pub use partition::{PartitionInterval, TimePartitions};
+pub use peer::PeerOutcome;
+pub use progress::{Direction, ProgressEvent, ProgressObserver, ProgressStatus};
+pub use repeatable::{load_repeatable_migrations, RepeatableMigration};
+pub use retry::{is_transient, RetryPolicy};
+pub use seeds::{load_seeds, Seed};
+pub use snapshot::IrreversibleMigration;
+pub use split::split_statements;
+
+pub use sql::{
+    load_sql_callbacks, load_sql_migrations, load_sql_migrations_with_checksum_algorithm,
+    load_sql_migrations_with_placeholders, SqlCallbacks,
+};
+pub use sql::{register_sql_migrations, register_sql_migrations_with_placeholders, SqlMigration};
+pub use timestamp::timestamp_version;
+pub use validation::PendingValidation;
+
+#[cfg(feature = "embed")]
+pub use schemamama_postgres_macros::embed_postgres_migrations;
+
+mod backfill;
+mod batch;
+mod checksum;
+mod client;
+mod context;
+mod copy;
+mod drift;
+mod error;
+mod export;
+mod helpers;
+mod hooks;
+mod import;
+mod includes;
+mod index;
+mod lint;
+mod logging;
+mod multi_schema;
+mod partition;
+mod peer;
+mod progress;
+mod repeatable;
+mod retry;
+mod script;
+mod seeds;
+mod shadow;
+mod side_table;
+mod snapshot;
+mod split;
+mod sql;
+mod table;
+mod timestamp;
+mod validation;
+
+#[cfg(feature = "async")]
+mod async_adapter;
+
+#[cfg(feature = "cli")]
+mod cli;
+
+#[cfg(feature = "testcontainers")]
+mod container;
+
+#[cfg(feature = "testing")]
+mod testing;
+
+#[cfg(feature = "cli")]
+pub use cli::run_cli;
+
+#[cfg(feature = "testcontainers")]
+pub use container::PostgresContainer;
+
+#[cfg(feature = "testing")]
+pub use testing::{temp_schema_client, with_migrated_db, FakeAdapter};
+
+#[cfg(feature = "async")]
+pub use async_adapter::{AsyncMigrator, AsyncPostgresAdapter, AsyncPostgresMigration};
+
+/// A migration to be used within a PostgreSQL client. Migrations needing an application context
+/// `A`, such as an encryption key provider, implement `PostgresMigration<A>` and are run by an
+/// adapter given that context with `PostgresAdapter::with_app_context`.
+pub trait PostgresMigration<A = ()> : Migration {
     /// Called when this migration is to be executed. This function has an empty body by default,
     /// so its implementation is optional.
     #[allow(unused_variables)]
@@ -21,80 +127,2404 @@ pub trait PostgresMigration : Migration {
     fn down(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
         Ok(())
     }
+
+    /// Called instead of `up` with a `MigrationContext`, which gives access to the application
+    /// context, the adapter's configuration (schema, environment and placeholders) and a logger
+    /// alongside the transaction. Calls `up` with the context's transaction by default.
+    fn up_ctx(&self, context: &mut MigrationContext<Transaction, A>) -> Result<(), PostgresError> {
+        self.up(context.client())
+    }
+
+    /// Called instead of `down` with a `MigrationContext`. Calls `down` with the context's
+    /// transaction by default.
+    fn down_ctx(&self, context: &mut MigrationContext<Transaction, A>) -> Result<(), PostgresError> {
+        self.down(context.client())
+    }
+
+    /// The SQL executed by `up`, if this migration can be represented as plain SQL. Used to render
+    /// scripts in dry-run mode. Returns `None` by default.
+    fn up_sql(&self) -> Option<&str> {
+        None
+    }
+
+    /// The SQL executed by `down`, if this migration can be represented as plain SQL. Used to
+    /// render scripts in dry-run mode. Returns `None` by default.
+    fn down_sql(&self) -> Option<&str> {
+        None
+    }
+
+    /// A checksum of this migration's contents, stored alongside its version when it is applied so
+    /// that `PostgresAdapter::verify_checksums` can detect migrations edited after being applied.
+    /// Returns `None` by default, which opts out of verification.
+    fn checksum(&self) -> Option<String> {
+        None
+    }
+
+    /// The `statement_timeout` to apply while this migration runs, overriding the adapter's
+    /// `with_statement_timeout`. Returns `None` by default, which uses the adapter's setting.
+    fn statement_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// The `lock_timeout` to apply while this migration runs, overriding the adapter's
+    /// `with_lock_timeout`. Returns `None` by default, which uses the adapter's setting.
+    fn lock_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Session parameters to set while this migration runs, such as `("work_mem", "256MB")` or
+    /// `("synchronous_commit", "off")`. They are set with `SET LOCAL` semantics, after and taking
+    /// precedence over the adapter's own settings, and restored once the migration finishes.
+    /// Returns no parameters by default.
+    fn session_params(&self) -> &[(&str, &str)] {
+        &[]
+    }
+
+    /// Tags grouping this migration with others, such as `"schema"` or `"seed"`, so that an adapter
+    /// configured with `PostgresAdapter::with_tags` or `without_tags` runs only some groups in a
+    /// given environment. Returns no tags by default.
+    fn tags(&self) -> &[&str] {
+        &[]
+    }
+
+    /// The environments this migration applies to, such as `"dev"` or `"staging"`. An adapter
+    /// configured `with_environment` records migrations that do not apply to its environment as
+    /// skipped instead of running them. Returns no environments by default, which applies the
+    /// migration everywhere.
+    fn environments(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Arbitrary metadata recorded with this migration in the JSONB `metadata` column of the
+    /// metadata table, such as a ticket number, reviewers or a risk classification. Returns
+    /// `Value::Null` by default, which records nothing. Requires the `json` feature.
+    #[cfg(feature = "json")]
+    fn metadata(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// Materialized views (possibly schema-qualified) that this migration makes stale, such as
+    /// views over a backfilled table. Once it is applied, they are refreshed with
+    /// `REFRESH MATERIALIZED VIEW CONCURRENTLY` by `PostgresAdapter::refresh_materialized_views` or
+    /// `after_migrate`, after the structural changes committed. Returns no views by default.
+    fn refreshes_materialized_views(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Whether this migration adds values to enum types with `ALTER TYPE ... ADD VALUE`, which
+    /// cannot run in a transaction block before PostgreSQL 12, and whose new values cannot be used
+    /// in the transaction that added them since. Such migrations run without a transaction (see
+    /// `run_in_transaction`), so they should implement `up_without_transaction` (e.g. with
+    /// `add_enum_value`) instead of `up`. Returns `false` by default.
+    fn alters_enums(&self) -> bool {
+        false
+    }
+
+    /// Whether this migration runs inside a transaction. Statements such as
+    /// `CREATE INDEX CONCURRENTLY` cannot run in a transaction block; migrations containing them
+    /// should return `false` and implement `up_without_transaction` and `down_without_transaction`
+    /// instead of `up` and `down`. Returns `true` by default, unless the migration `alters_enums`.
+    fn run_in_transaction(&self) -> bool {
+        !self.alters_enums()
+    }
+
+    /// Called instead of `up` when `run_in_transaction` returns `false`. The version is recorded
+    /// only after this function succeeds. This function has an empty body by default, so its
+    /// implementation is optional.
+    #[allow(unused_variables)]
+    fn up_without_transaction(&self, client: &mut Client) -> Result<(), PostgresError> {
+        Ok(())
+    }
+
+    /// Called instead of `down` when `run_in_transaction` returns `false`. The version is erased
+    /// only after this function succeeds. This function has an empty body by default, so its
+    /// implementation is optional.
+    #[allow(unused_variables)]
+    fn down_without_transaction(&self, client: &mut Client) -> Result<(), PostgresError> {
+        Ok(())
+    }
+
+    /// Called instead of `up_without_transaction` with a `MigrationContext` holding the client.
+    /// Calls `up_without_transaction` by default.
+    fn up_without_transaction_ctx(&self, context: &mut MigrationContext<Client, A>) -> Result<(), PostgresError> {
+        self.up_without_transaction(context.client())
+    }
+
+    /// Called instead of `down_without_transaction` with a `MigrationContext` holding the client.
+    /// Calls `down_without_transaction` by default.
+    fn down_without_transaction_ctx(&self, context: &mut MigrationContext<Client, A>) -> Result<(), PostgresError> {
+        self.down_without_transaction(context.client())
+    }
 }
 
 /// An adapter that allows its migrations to act upon PostgreSQL client transactions.
-pub struct PostgresAdapter<'a> {
-    client: &'a mut Client,
-    metadata_table: &'static str,
+///
+/// The adapter usually runs against a `Client`, but can also run against a `Transaction` owned by
+/// the application, e.g. to migrate as part of a larger bootstrap transaction:
+///
+/// ```rust,ignore
+/// let mut transaction = client.transaction()?;
+/// Migrator::new(PostgresAdapter::new(&mut transaction)).up(None)?;
+/// seed(&mut transaction)?;
+/// transaction.commit()?;
+/// ```
+///
+/// Each migration then runs in a savepoint of that transaction, and migrations that do not run in
+/// a transaction fail with `SchemamamaPostgresError::NonTransactionalInBatch`.
+pub struct PostgresAdapter<'a, C: MigrationClient + 'a = Client, A: 'a = ()> {
+    client: ClientHandle<'a, C>,
+    metadata_table: MetadataTable,
+    history_table: Option<MetadataTable>,
+    notify_channel: Option<String>,
+    ensure_schema: bool,
+    metadata_exists: bool,
+    skipped_versions: BTreeSet<Version>,
+    environment: Option<String>,
+    placeholders: HashMap<String, String>,
+    app_context: &'a A,
+    included_tags: Option<BTreeSet<String>>,
+    excluded_tags: BTreeSet<String>,
+    advisory_lock: Option<i64>,
+    lock_behavior: LockBehavior,
+    atomic_batch: bool,
+    store_sql: bool,
+    dry_run: Option<String>,
+    statement_timeout: Option<Duration>,
+    lock_timeout: Option<Duration>,
+    isolation_level: Option<IsolationLevel>,
+    search_path: Option<Vec<String>>,
+    role: Option<String>,
+    keepalive: Option<Duration>,
+    retry: Option<RetryPolicy>,
+    reconnect: Option<Box<dyn FnMut() -> Result<ClientHandle<'a, C>, PostgresError> + 'a>>,
+    statements: HashMap<String, Statement>,
+    unnamed_statements: bool,
+    guard_destructive: bool,
+    allow_destructive: bool,
+    out_of_order: OutOfOrder,
+    record_conflict: RecordConflict,
+    revert_order: RevertOrder,
+    cache_versions: bool,
+    cached_versions: Option<BTreeSet<Version>>,
+    hooks: Vec<Box<dyn MigrationHooks + 'a>>,
+    callbacks: SqlCallbacks,
+    pending_refreshes: Vec<String>,
+    progress: Option<Progress<'a>>,
 }
 
-impl<'a> PostgresAdapter<'a> {
-    /// Create a new migrator tied to a PostgreSQL client.
-    pub fn new(client: &'a mut Client) -> PostgresAdapter<'a> {
+/// The progress observer of an adapter and its position within the current run.
+struct Progress<'a> {
+    observer: Box<dyn ProgressObserver + 'a>,
+    position: usize,
+    total: Option<usize>,
+}
+
+impl<'a, C: MigrationClient + 'a> PostgresAdapter<'a, C> {
+    /// Create a new migrator tied to a PostgreSQL client or transaction.
+    pub fn new(client: &'a mut C) -> PostgresAdapter<'a, C> {
         Self::with_metadata_table(client, "schemamama")
     }
 
-    /// Create a new migrator tied to a PostgreSQL client with custom metadata table name
-    pub fn with_metadata_table(
-        client: &'a mut Client,
-        metadata_table: &'static str
-    ) -> PostgresAdapter<'a> {
-        PostgresAdapter { client, metadata_table }
+    /// Create a new migrator tied to a PostgreSQL client or transaction with custom metadata table
+    /// name. A name of the form `schema.table` places the table in the given schema, which
+    /// `setup_schema` creates if necessary. Names are quoted, so they are used verbatim (including
+    /// their case).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table or schema name is empty, contains a NUL character, or is longer than the
+    /// 63 bytes PostgreSQL allows.
+    pub fn with_metadata_table<T: Into<String>>(
+        client: &'a mut C,
+        metadata_table: T
+    ) -> PostgresAdapter<'a, C> {
+        Self::from_handle(ClientHandle::Borrowed(client), MetadataTable::parse(&metadata_table.into()))
+    }
+
+    fn from_handle(client: ClientHandle<'a, C>, metadata_table: MetadataTable) -> PostgresAdapter<'a, C> {
+        PostgresAdapter {
+            client,
+            metadata_table,
+            history_table: None,
+            notify_channel: None,
+            ensure_schema: false,
+            metadata_exists: false,
+            skipped_versions: BTreeSet::new(),
+            environment: None,
+            placeholders: HashMap::new(),
+            app_context: &(),
+            included_tags: None,
+            excluded_tags: BTreeSet::new(),
+            advisory_lock: None,
+            lock_behavior: LockBehavior::Block,
+            atomic_batch: false,
+            store_sql: false,
+            dry_run: None,
+            statement_timeout: None,
+            lock_timeout: None,
+            isolation_level: None,
+            search_path: None,
+            role: None,
+            keepalive: None,
+            retry: None,
+            reconnect: None,
+            statements: HashMap::new(),
+            unnamed_statements: false,
+            guard_destructive: false,
+            allow_destructive: false,
+            out_of_order: OutOfOrder::Ignore,
+            record_conflict: RecordConflict::Fail,
+            revert_order: RevertOrder::Version,
+            cache_versions: false,
+            cached_versions: None,
+            hooks: vec![],
+            callbacks: SqlCallbacks::default(),
+            pending_refreshes: vec![],
+            progress: None,
+        }
+    }
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Use the metadata table `name` rather than the one the adapter was created with, for
+    /// adapters that own their client, such as those of `reconnecting` or `from_pool`. Names are
+    /// parsed as described for `with_metadata_table`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table or schema name is invalid, as described for `with_metadata_table`.
+    pub fn with_metadata_table_name<T: Into<String>>(mut self, name: T) -> PostgresAdapter<'a, C, A> {
+        self.metadata_table = MetadataTable::parse(&name.into());
+        self.metadata_exists = false;
+        self
+    }
+
+    /// Place the metadata table in `schema`, which `setup_schema` creates if necessary, rather than
+    /// in the first schema of the `search_path`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the schema name is invalid, as described for `with_metadata_table`.
+    pub fn with_schema<T: Into<String>>(mut self, schema: T) -> PostgresAdapter<'a, C, A> {
+        let schema = schema.into();
+        validate_identifier(&schema);
+        self.metadata_table.schema = Some(schema);
+        self
+    }
+
+    /// Also append a row to the history table `name` (e.g. `schemamama_history`) for every
+    /// migration applied or reverted, recording its version, direction, time, user, duration and
+    /// outcome, so that reverting a migration does not erase the evidence that it ever ran.
+    /// `setup_schema` creates the table. Successes are recorded in the migration's transaction;
+    /// failures are recorded afterwards, if the connection is still usable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table name is invalid, as described for `with_metadata_table`.
+    pub fn with_history_table<T: Into<String>>(mut self, name: T) -> PostgresAdapter<'a, C, A> {
+        self.history_table = Some(MetadataTable::parse(&name.into()));
+        self
+    }
+
+    /// Send a notification on `channel` (e.g. `schemamama`) with the version as payload, as
+    /// `NOTIFY schemamama, '<version>'` would, after each migration this adapter applies or reverts,
+    /// so that services listening on the database can invalidate caches or reload prepared
+    /// statements. Notifications are sent when the migration's transaction commits, so failed
+    /// migrations send none.
+    pub fn with_notify<T: Into<String>>(mut self, channel: T) -> PostgresAdapter<'a, C, A> {
+        self.notify_channel = Some(channel.into());
+        self
+    }
+
+    /// Create the metadata table with `setup_schema` when it is first needed, if it does not exist
+    /// yet. Without this option, an adapter whose metadata table is missing reports that no
+    /// migration is applied, and fails to apply migrations.
+    pub fn ensure_schema(mut self) -> PostgresAdapter<'a, C, A> {
+        self.ensure_schema = true;
+        self
+    }
+
+    /// Record the migrations of `versions` as applied, marked as skipped, instead of running them,
+    /// e.g. in an environment that already received a change through other means. Reverting a
+    /// version that was skipped erases its record without running its `down` step either.
+    pub fn with_skipped_versions<I: IntoIterator<Item = Version>>(mut self, versions: I) -> PostgresAdapter<'a, C, A> {
+        self.skipped_versions.extend(versions);
+        self
+    }
+
+    /// Name the environment this adapter migrates, e.g. `"production"`. Migrations whose
+    /// `PostgresMigration::environments` do not include it are recorded as skipped, as with
+    /// `with_skipped_versions`, instead of being run.
+    pub fn with_environment<T: Into<String>>(mut self, environment: T) -> PostgresAdapter<'a, C, A> {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// Make `placeholders`, e.g. those used to load SQL migrations with
+    /// `load_sql_migrations_with_placeholders`, available to migrations through
+    /// `MigrationContext::placeholder`.
+    pub fn with_placeholders(mut self, placeholders: HashMap<String, String>) -> PostgresAdapter<'a, C, A> {
+        self.placeholders = placeholders;
+        self
+    }
+
+    /// Make `context`, an application value such as an encryption key provider or a feature flag
+    /// client, available to migrations through `MigrationContext::app`. The adapter then runs
+    /// migrations implementing `PostgresMigration<B>`.
+    pub fn with_app_context<B: 'a>(self, context: &'a B) -> PostgresAdapter<'a, C, B> {
+        PostgresAdapter {
+            client: self.client,
+            metadata_table: self.metadata_table,
+            history_table: self.history_table,
+            notify_channel: self.notify_channel,
+            ensure_schema: self.ensure_schema,
+            metadata_exists: self.metadata_exists,
+            skipped_versions: self.skipped_versions,
+            environment: self.environment,
+            placeholders: self.placeholders,
+            app_context: context,
+            included_tags: self.included_tags,
+            excluded_tags: self.excluded_tags,
+            advisory_lock: self.advisory_lock,
+            lock_behavior: self.lock_behavior,
+            atomic_batch: self.atomic_batch,
+            store_sql: self.store_sql,
+            dry_run: self.dry_run,
+            statement_timeout: self.statement_timeout,
+            lock_timeout: self.lock_timeout,
+            isolation_level: self.isolation_level,
+            search_path: self.search_path,
+            role: self.role,
+            keepalive: self.keepalive,
+            retry: self.retry,
+            reconnect: self.reconnect,
+            statements: self.statements,
+            unnamed_statements: self.unnamed_statements,
+            guard_destructive: self.guard_destructive,
+            allow_destructive: self.allow_destructive,
+            out_of_order: self.out_of_order,
+            record_conflict: self.record_conflict,
+            revert_order: self.revert_order,
+            cache_versions: self.cache_versions,
+            cached_versions: self.cached_versions,
+            hooks: self.hooks,
+            callbacks: self.callbacks,
+            pending_refreshes: self.pending_refreshes,
+            progress: self.progress,
+        }
+    }
+
+    /// Only apply and revert migrations carrying at least one of `tags` (see
+    /// `PostgresMigration::tags`). Other migrations are passed over without being recorded, so they
+    /// remain pending until an adapter that selects them runs.
+    pub fn with_tags<I, T>(mut self, tags: I) -> PostgresAdapter<'a, C, A>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.included_tags.get_or_insert_with(BTreeSet::new).extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Pass over migrations carrying any of `tags`, e.g. `"seed"` outside of development, without
+    /// recording them. Takes precedence over `with_tags`.
+    pub fn without_tags<I, T>(mut self, tags: I) -> PostgresAdapter<'a, C, A>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.excluded_tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Hold the session-level advisory lock identified by `key` (via `pg_advisory_lock`) while
+    /// applying or reverting each migration, so that concurrent migrators sharing the same key
+    /// never run migrations at the same time.
+    pub fn with_advisory_lock(mut self, key: i64) -> PostgresAdapter<'a, C, A> {
+        self.advisory_lock = Some(key);
+        self
+    }
+
+    /// Set what happens when the advisory lock of `with_advisory_lock` is held by another
+    /// migrator. Defaults to `LockBehavior::Block`, which waits for as long as it takes.
+    pub fn with_lock_behavior(mut self, behavior: LockBehavior) -> PostgresAdapter<'a, C, A> {
+        self.lock_behavior = behavior;
+        self
+    }
+
+    /// Abort any statement of a migration that runs longer than `timeout`, by setting
+    /// `statement_timeout` for the duration of each migration (with `SET LOCAL` inside its
+    /// transaction). Migrations may override the timeout with
+    /// `PostgresMigration::statement_timeout`.
+    pub fn with_statement_timeout(mut self, timeout: Duration) -> PostgresAdapter<'a, C, A> {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Abort a migration that waits longer than `timeout` to acquire a lock, e.g. an `ALTER TABLE`
+    /// queued behind a long-running transaction, instead of stalling every query queued behind it
+    /// in turn. Sets `lock_timeout` for the duration of each migration like
+    /// `with_statement_timeout`; migrations may override it with `PostgresMigration::lock_timeout`.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> PostgresAdapter<'a, C, A> {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Run each migration's transaction (or, in an atomic batch, the outer transaction) at the
+    /// given isolation level instead of the server's default, usually `READ COMMITTED`.
+    pub fn with_isolation_level(mut self, isolation_level: IsolationLevel) -> PostgresAdapter<'a, C, A> {
+        self.isolation_level = Some(isolation_level);
+        self
+    }
+
+    /// Set the `search_path` to `schemas` for the duration of each migration, so that migrations
+    /// whose objects are not schema-qualified create them in the intended schema whatever the
+    /// connection's `search_path`. The previous `search_path` is restored afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a schema name is invalid, as described for `with_metadata_table`.
+    pub fn with_search_path<I>(mut self, schemas: I) -> PostgresAdapter<'a, C, A>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let schemas: Vec<String> = schemas.into_iter().map(Into::into).collect();
+        for schema in &schemas {
+            validate_identifier(schema);
+        }
+        self.search_path = Some(schemas);
+        self
+    }
+
+    /// Run each migration as `role` (like `SET ROLE`), so that the objects it creates are owned by
+    /// that role rather than by the connection's user, e.g. a deployment account. The connection's
+    /// user must be a member of `role`. The metadata table is still written as the connection's
+    /// user.
+    pub fn with_role<T: Into<String>>(mut self, role: T) -> PostgresAdapter<'a, C, A> {
+        self.role = Some(role.into());
+        self
+    }
+
+    /// Have the server send TCP keepalive probes on the connection every `interval` (rounded to
+    /// whole seconds) while a migration runs, by setting `tcp_keepalives_idle` and
+    /// `tcp_keepalives_interval`. A connection busy with a long-running statement looks idle to
+    /// the network, and firewalls or load balancers may otherwise silently drop it. Has no effect
+    /// on Unix-domain socket connections.
+    pub fn with_keepalive(mut self, interval: Duration) -> PostgresAdapter<'a, C, A> {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// Retry a migration that fails with a transient error, such as a serialization failure or a
+    /// deadlock, according to `policy`. Hooks and progress observers see a single attempt. In an
+    /// atomic batch, only the migration's savepoint is retried. Migrations that do not run in a
+    /// transaction are never retried, as they may have been partially applied.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> PostgresAdapter<'a, C, A> {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Refuse to revert migrations, failing with `SchemamamaPostgresError::DestructiveNotAllowed`,
+    /// unless `allow_destructive` is called too. Typically enabled in production only, so that
+    /// nobody accidentally runs `down(None)` there:
+    ///
+    /// ```rust,ignore
+    /// let adapter = PostgresAdapter::new(&mut client).guard_destructive(environment == "production");
+    /// ```
+    ///
+    /// Dry runs are not affected.
+    pub fn guard_destructive(mut self, enabled: bool) -> PostgresAdapter<'a, C, A> {
+        self.guard_destructive = enabled;
+        self
+    }
+
+    /// Opt in to reverting migrations despite `guard_destructive`.
+    pub fn allow_destructive(mut self) -> PostgresAdapter<'a, C, A> {
+        self.allow_destructive = true;
+        self
+    }
+
+    /// Choose what happens when a migration older than the latest applied one is about to be
+    /// applied, typically after merging branches that both added migrations. Defaults to
+    /// `OutOfOrder::Ignore`, which applies it silently.
+    pub fn with_out_of_order(mut self, policy: OutOfOrder) -> PostgresAdapter<'a, C, A> {
+        self.out_of_order = policy;
+        self
+    }
+
+    /// Choose what happens when the version of a migration that was just applied is already
+    /// recorded in the metadata table, e.g. after recovering by hand from a partially applied run.
+    /// Defaults to `RecordConflict::Fail`.
+    pub fn with_record_conflict(mut self, policy: RecordConflict) -> PostgresAdapter<'a, C, A> {
+        self.record_conflict = policy;
+        self
+    }
+
+    /// Send every query on the metadata tables (and the session settings of migrations) as an
+    /// unnamed statement, in a single round trip, instead of preparing and caching named
+    /// statements. Named statements do not survive a connection pooler such as PgBouncer in
+    /// transaction pooling mode, which may hand each transaction to a different server connection.
+    pub fn with_unnamed_statements(mut self) -> PostgresAdapter<'a, C, A> {
+        self.unnamed_statements = true;
+        self
+    }
+
+    /// Choose the order in which `reset` and `revert_last` revert migrations. Defaults to
+    /// `RevertOrder::Version`; `RevertOrder::Applied` undoes them in the reverse of the order they
+    /// were applied, which differs when migrations were applied out of order.
+    pub fn with_revert_order(mut self, order: RevertOrder) -> PostgresAdapter<'a, C, A> {
+        self.revert_order = order;
+        self
+    }
+
+    /// Keep the set of applied versions in memory after reading it, so that repeated calls to
+    /// `migrated_versions` (by `status`, `plan` and `Migrator::up`, for instance) do not each scan
+    /// the metadata table. The cached set is dropped whenever this adapter changes the metadata
+    /// table; call `invalidate_cached_versions` if other processes may have changed it since.
+    pub fn cache_migrated_versions(mut self) -> PostgresAdapter<'a, C, A> {
+        self.cache_versions = true;
+        self
+    }
+
+    /// Drop the applied versions cached by `cache_migrated_versions`, so that the next call to
+    /// `migrated_versions` reads them from the metadata table again.
+    pub fn invalidate_cached_versions(&mut self) {
+        self.cached_versions = None;
+    }
+
+    /// Register callbacks to invoke around each migration this adapter applies or reverts. May be
+    /// called repeatedly; hooks run in the order they were added.
+    pub fn with_hooks<H: MigrationHooks + 'a>(mut self, hooks: H) -> PostgresAdapter<'a, C, A> {
+        self.hooks.push(Box::new(hooks));
+        self
+    }
+
+    /// Run the SQL of `callbacks`, typically loaded with `load_sql_callbacks`: `after_each_migrate`
+    /// after each migration this adapter applies, in the migration's transaction, and the others
+    /// when `before_migrate` and `after_migrate` are called around a run:
+    ///
+    /// ```rust,ignore
+    /// let mut adapter = PostgresAdapter::new(&mut client).with_sql_callbacks(load_sql_callbacks("migrations")?);
+    /// adapter.before_migrate()?;
+    /// Migrator::new(&mut adapter).up(None)?;
+    /// adapter.after_migrate()?;
+    /// ```
+    pub fn with_sql_callbacks(mut self, callbacks: SqlCallbacks) -> PostgresAdapter<'a, C, A> {
+        self.callbacks = callbacks;
+        self
+    }
+
+    /// Report a `ProgressEvent` to `observer` before and after each migration this adapter applies
+    /// or reverts. Closures taking a `&ProgressEvent` are observers too.
+    pub fn with_progress<O: ProgressObserver + 'a>(mut self, observer: O) -> PostgresAdapter<'a, C, A> {
+        self.progress = Some(Progress { observer: Box::new(observer), position: 0, total: None });
+        self
+    }
+
+    /// Announce that a run of `total` migrations is about to start, so that progress events report
+    /// positions like "3 of 17". Positions restart from 1 after each call.
+    pub fn set_progress_total(&mut self, total: usize) {
+        if let Some(ref mut progress) = self.progress {
+            progress.position = 0;
+            progress.total = Some(total);
+        }
+    }
+
+    /// Run every migration applied or reverted through this adapter inside one outer transaction,
+    /// so that a failure midway leaves the database exactly where it started. Each migration runs
+    /// in a savepoint of the outer transaction, which must be committed with `commit_batch` once
+    /// the run has finished; dropping the adapter instead rolls the whole batch back.
+    ///
+    /// To keep hold of the adapter, hand a mutable reference of it to the `Migrator`:
+    ///
+    /// ```rust,ignore
+    /// let mut adapter = PostgresAdapter::new(&mut client).atomic_batch(true);
+    /// Migrator::new(&mut adapter).up(None)?;
+    /// adapter.commit_batch()?;
+    /// ```
+    ///
+    /// Atomic batches are only supported by adapters that borrow their client, and cannot contain
+    /// migrations that do not run in a transaction; both cases fail with a dedicated
+    /// `SchemamamaPostgresError` variant.
+    pub fn atomic_batch(mut self, enabled: bool) -> PostgresAdapter<'a, C, A> {
+        self.atomic_batch = enabled;
+        self
+    }
+
+    /// Store the SQL that applies each migration (see `PostgresMigration::up_sql`) in the `sql`
+    /// column of the metadata table, so that what ran in production can still be seen after the
+    /// migration's source changed. Migrations implemented in Rust store nothing.
+    pub fn store_sql(mut self, enabled: bool) -> PostgresAdapter<'a, C, A> {
+        self.store_sql = enabled;
+        self
+    }
+
+    /// Instead of executing migrations, collect the SQL they would run into a script that can be
+    /// reviewed with `dry_run_script`. Migrations implemented in Rust rather than SQL (see
+    /// `PostgresMigration::up_sql`) appear in the script as comments. The metadata table is only
+    /// read, never written, so the adapter keeps reporting the real state of the database.
+    pub fn dry_run(mut self, enabled: bool) -> PostgresAdapter<'a, C, A> {
+        self.dry_run = if enabled { Some(String::new()) } else { None };
+        self
+    }
+
+    /// The script collected in dry-run mode, or `None` if dry-run mode is disabled.
+    pub fn dry_run_script(&self) -> Option<&str> {
+        self.dry_run.as_ref().map(|script| &**script)
+    }
+
+    /// Commit the outer transaction of an atomic batch. Does nothing if no migration was applied or
+    /// reverted since the adapter was created.
+    pub fn commit_batch(self) -> Result<(), SchemamamaPostgresError> {
+        match self.client {
+            ClientHandle::Batch(transaction) => transaction.commit().map_err(From::from),
+            _ => Ok(()),
+        }
     }
 
     /// Create the tables Schemamama requires to keep track of schema state. If the tables already
     /// exist, this function has no operation.
-    pub fn setup_schema(&mut self) -> Result<(), PostgresError> {
-        let query = format!("CREATE TABLE IF NOT EXISTS {} (version BIGINT PRIMARY KEY);", self.metadata_table);
+    pub fn setup_schema(&mut self) -> Result<(), SchemamamaPostgresError> {
+        if let Some(ref schema) = self.metadata_table.schema {
+            let query = format!("CREATE SCHEMA IF NOT EXISTS {};", quote_identifier(schema));
+            self.metadata_query(&query, &[], &[]).map_err(SchemamamaPostgresError::Setup)?;
+        }
+
+        let query = format!(
+            "CREATE TABLE IF NOT EXISTS {} (version BIGINT PRIMARY KEY, description TEXT, \
+             checksum TEXT, applied_at TIMESTAMPTZ DEFAULT now(), applied_by TEXT DEFAULT current_user, \
+             dirty BOOLEAN NOT NULL DEFAULT false, skipped BOOLEAN NOT NULL DEFAULT false, metadata JSONB, \
+             sql TEXT, applied_seq BIGSERIAL);",
+            self.metadata_table
+        );
+        self.metadata_query(&query, &[], &[]).map_err(SchemamamaPostgresError::Setup)?;
+
+        // Metadata tables created by earlier releases lack the columns added since. Their existing
+        // rows are left with NULL values rather than made-up ones, except for `applied_seq`, which
+        // numbers them in no particular order.
+        for column in ADDED_METADATA_COLUMNS {
+            let query = format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS {};", self.metadata_table, column);
+            self.metadata_query(&query, &[], &[]).map_err(SchemamamaPostgresError::Setup)?;
+        }
+
+        if let Some(ref history_table) = self.history_table {
+            if let Some(ref schema) = history_table.schema {
+                let query = format!("CREATE SCHEMA IF NOT EXISTS {};", quote_identifier(schema));
+                self.metadata_query(&query, &[], &[]).map_err(SchemamamaPostgresError::Setup)?;
+            }
+
+            let query = format!(
+                "CREATE TABLE IF NOT EXISTS {} (id BIGSERIAL PRIMARY KEY, version BIGINT NOT NULL, \
+                 direction TEXT NOT NULL, occurred_at TIMESTAMPTZ NOT NULL DEFAULT now(), \
+                 occurred_by TEXT NOT NULL DEFAULT current_user, duration_ms BIGINT NOT NULL, \
+                 outcome TEXT NOT NULL, error TEXT);",
+                history_table
+            );
+            self.metadata_query(&query, &[], &[]).map_err(SchemamamaPostgresError::Setup)?;
+        }
+
+        self.metadata_exists = true;
+        Ok(())
+    }
+
+    /// Drop the tables created by `setup_schema` (the metadata table and the history table, if
+    /// any), as well as the tables of repeatable migrations and seeds, for test environments and
+    /// ephemeral databases. The schema containing them is left in place. The migrations' own
+    /// objects are left alone too; see `reset` to revert them first.
+    pub fn drop_schema(&mut self) -> Result<(), SchemamamaPostgresError> {
+        let mut tables = vec![self.metadata_table.to_string()];
+        // Side tables whose names are too long cannot have been created.
+        tables.extend(self.repeatable_table().ok().map(|table| table.to_string()));
+        tables.extend(self.seeds_table().ok().map(|table| table.to_string()));
+        tables.extend(self.history_table.as_ref().map(ToString::to_string));
+
+        let query = format!("DROP TABLE IF EXISTS {};", tables.join(", "));
+        self.metadata_query(&query, &[], &[])?;
+        self.metadata_exists = false;
+        self.statements.clear();
+        self.invalidate_cached_versions();
+        Ok(())
+    }
+
+    /// Revert every applied migration, newest first (see `with_revert_order`), then drop the
+    /// metadata table with `drop_schema`. Fails with `SchemamamaPostgresError::UnknownVersions`
+    /// before reverting anything if some applied versions are not among `migrations`. Returns the
+    /// reverted versions, in the order they were reverted.
+    pub fn reset<'m, I>(&mut self, migrations: I) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let reverted = self.revert_last(migrations, usize::MAX)?;
+        self.drop_schema()?;
+        Ok(reverted)
+    }
+
+    /// Revert the `count` newest applied migrations, as ordered by `with_revert_order`. Fails with
+    /// `SchemamamaPostgresError::UnknownVersions` before reverting anything if some of them are
+    /// not among `migrations`. Returns the reverted versions, in the order they were reverted.
+    pub fn revert_last<'m, I>(&mut self, migrations: I, count: usize) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let migrations: BTreeMap<Version, &dyn PostgresMigration<A>> = migrations.into_iter()
+            .map(|migration| (migration.version(), migration))
+            .collect();
+        let mut order = match self.revert_order {
+            RevertOrder::Version => self.migrated_versions()?.into_iter().collect(),
+            RevertOrder::Applied => self.applied_order()?,
+        };
+        order.reverse();
+        order.truncate(count);
+        let unknown: Vec<Version> = order.iter().filter(|version| !migrations.contains_key(version)).cloned().collect();
+        if !unknown.is_empty() {
+            return Err(SchemamamaPostgresError::UnknownVersions { versions: unknown });
+        }
+
+        for version in &order {
+            self.revert_migration(migrations[version])?;
+        }
+        Ok(order)
+    }
+
+    /// Returns the applied versions in the order they were applied, oldest first.
+    pub fn applied_order(&mut self) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(vec![]);
+        }
+
+        let query = format!("SELECT version FROM {} WHERE NOT dirty ORDER BY applied_seq;", self.metadata_table);
+        let statement = self.prepare_cached(query, &[])?;
+        let rows = self.client.query_metadata(&statement, &[])?;
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
+    /// Returns when `version` was applied, or `None` if it is not applied or was applied before
+    /// application times were recorded.
+    pub fn applied_at(&mut self, version: Version) -> Result<Option<SystemTime>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(None);
+        }
+
+        let query = format!("SELECT applied_at FROM {} WHERE version = $1;", self.metadata_table);
+        let row = self.metadata_query(&query, &[Type::INT8], &[&version])?;
+        Ok(row.iter().next().and_then(|r| r.get(0)))
+    }
+
+    /// Returns the current version, the applied versions and the numbers of skipped and dirty
+    /// versions, fetched in a single round trip rather than one per question, which adds up over
+    /// high-latency connections.
+    pub fn summary(&mut self) -> Result<MetadataSummary, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(MetadataSummary::default());
+        }
+
+        let query = format!(
+            "SELECT max(version) FILTER (WHERE NOT dirty), \
+             coalesce(array_agg(version ORDER BY version) FILTER (WHERE NOT dirty), '{{}}'), \
+             count(*) FILTER (WHERE skipped AND NOT dirty), count(*) FILTER (WHERE dirty) FROM {};",
+            self.metadata_table
+        );
+        let statement = self.prepare_cached(query, &[])?;
+        let row = &self.client.query_metadata(&statement, &[])?[0];
+        let versions: Vec<Version> = row.get(1);
+        let skipped: i64 = row.get(2);
+        let dirty: i64 = row.get(3);
+        Ok(MetadataSummary {
+            current_version: row.get(0),
+            migrated_versions: versions.into_iter().collect(),
+            skipped: skipped as usize,
+            dirty: dirty as usize,
+        })
+    }
+
+    /// Returns the metadata recorded for every applied migration, ordered by version.
+    pub fn migration_log(&mut self) -> Result<Vec<MigrationRecord>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(vec![]);
+        }
+
+        let query = format!(
+            "SELECT version, description, applied_at, applied_by, skipped, metadata::TEXT, sql FROM {} \
+             WHERE NOT dirty ORDER BY version;",
+            self.metadata_table
+        );
+        let row = self.metadata_query(&query, &[], &[])?;
+        Ok(row.iter().map(|r| MigrationRecord {
+            version: r.get(0),
+            description: r.get(1),
+            applied_at: r.get(2),
+            applied_by: r.get(3),
+            skipped: r.get(4),
+            metadata: r.get(5),
+            sql: r.get(6),
+        }).collect())
+    }
+
+    /// Run the `before_migrate` SQL callback (see `with_sql_callbacks`), if any.
+    pub fn before_migrate(&mut self) -> Result<(), SchemamamaPostgresError> {
+        match self.callbacks.before_migrate {
+            Some(ref sql) => self.client.batch_execute(sql).map_err(From::from),
+            None => Ok(()),
+        }
+    }
+
+    /// Refresh the materialized views of the migrations applied so far (see
+    /// `refresh_materialized_views`), then run the `after_migrate` SQL callback (see
+    /// `with_sql_callbacks`), if any.
+    pub fn after_migrate(&mut self) -> Result<(), SchemamamaPostgresError> {
+        self.refresh_materialized_views()?;
+        match self.callbacks.after_migrate {
+            Some(ref sql) => self.client.batch_execute(sql).map_err(From::from),
+            None => Ok(()),
+        }
+    }
+
+    /// Refresh the materialized views named by `PostgresMigration::refreshes_materialized_views` of
+    /// the migrations this adapter applied since the last refresh, each once and in the order they
+    /// were first named, with `REFRESH MATERIALIZED VIEW CONCURRENTLY`, which does not block reads
+    /// of the view but requires a unique index on it. Returns the refreshed views. If a refresh
+    /// fails, the views not refreshed yet remain pending.
+    pub fn refresh_materialized_views(&mut self) -> Result<Vec<String>, SchemamamaPostgresError> {
+        let mut refreshed = Vec::with_capacity(self.pending_refreshes.len());
+        while let Some(view) = self.pending_refreshes.first().cloned() {
+            let query = format!("REFRESH MATERIALIZED VIEW CONCURRENTLY {};", quote_qualified(&view));
+            self.client.batch_execute(&query)?;
+            self.pending_refreshes.remove(0);
+            refreshed.push(view);
+        }
+
+        Ok(refreshed)
+    }
+
+    /// Returns the version of the migration that was interrupted while being applied or reverted
+    /// outside of a transaction, if any. Such a migration may be partially applied, so no migration
+    /// is applied or reverted (failing with `SchemamamaPostgresError::Dirty`) until the database is
+    /// repaired by hand and the marker cleared with `resolve_dirty`.
+    ///
+    /// Migrations running in a transaction cannot be interrupted halfway and are never dirty.
+    pub fn dirty_version(&mut self) -> Result<Option<Version>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(None);
+        }
+
+        let query = format!("SELECT version FROM {} WHERE dirty ORDER BY version LIMIT 1;", self.metadata_table);
+        let row = self.metadata_query(&query, &[], &[])?;
+        Ok(row.iter().next().map(|r| r.get(0)))
+    }
+
+    /// Clear the dirty marker after repairing the database by hand, recording the interrupted
+    /// migration as applied if `applied` is set, or as not applied otherwise. Returns the version
+    /// whose marker was cleared, if any.
+    pub fn resolve_dirty(&mut self, applied: bool) -> Result<Option<Version>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(None);
+        }
+
+        let query = if applied {
+            format!("UPDATE {} SET dirty = false WHERE dirty RETURNING version;", self.metadata_table)
+        } else {
+            format!("DELETE FROM {} WHERE dirty RETURNING version;", self.metadata_table)
+        };
+        self.invalidate_cached_versions();
+        let row = self.metadata_query(&query, &[], &[])?;
+        Ok(row.iter().next().map(|r| r.get(0)))
+    }
+
+    /// Resolves the version of the migration described as `name`, e.g. `add_billing_tables` for
+    /// the SQL migration `V7__add_billing_tables.up.sql`, so that callers can target it with
+    /// `Migrator::up` or `Migrator::down`. The description is looked up among `migrations` first,
+    /// then among the descriptions recorded in the metadata table.
+    pub fn version_named<'m, I>(&mut self, migrations: I, name: &str) -> Result<Version, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let mut versions: Vec<Version> = migrations.into_iter()
+            .filter(|migration| migration.description() == name)
+            .map(|migration| migration.version())
+            .collect();
+        if versions.is_empty() && self.initialize()? {
+            let query = format!("SELECT version FROM {} WHERE description = $1;", self.metadata_table);
+            versions = self.metadata_query(&query, &[Type::TEXT], &[&name])?.iter().map(|r| r.get(0)).collect();
+        }
+
+        versions.sort();
+        versions.dedup();
+        match versions.len() {
+            1 => Ok(versions[0]),
+            0 => Err(SchemamamaPostgresError::UnknownMigrationName { name: name.to_owned() }),
+            _ => Err(SchemamamaPostgresError::AmbiguousMigrationName { name: name.to_owned(), versions }),
+        }
+    }
+
+    /// Returns a JSON document describing every applied migration, ordered by version, for
+    /// deployment dashboards and drift detection:
+    ///
+    /// ```json
+    /// {"migrations":[{"version":10,"description":"create users","applied_at":"2024-06-01T12:30:00.000000Z",
+    ///                 "applied_by":"deploy","checksum":"9f86d08..."}]}
+    /// ```
+    ///
+    /// Fields that were not recorded are `null`.
+    pub fn export_metadata(&mut self) -> Result<String, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(export::render_json(&[]));
+        }
+
+        let query = format!(
+            "SELECT version, description, \
+             to_char(applied_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS.US\"Z\"'), applied_by, checksum \
+             FROM {} ORDER BY version;",
+            self.metadata_table
+        );
+        let migrations: Vec<ExportedMigration> = self.metadata_query(&query, &[], &[])?.iter().map(|r| ExportedMigration {
+            version: r.get(0),
+            description: r.get(1),
+            applied_at: r.get(2),
+            applied_by: r.get(3),
+            checksum: r.get(4),
+        }).collect();
+
+        Ok(export::render_json(&migrations))
+    }
+
+    /// Write the document returned by `export_metadata` to `writer`.
+    pub fn export_metadata_to<W: io::Write>(&mut self, mut writer: W) -> Result<(), SchemamamaPostgresError> {
+        let json = self.export_metadata()?;
+        writer.write_all(json.as_bytes()).map_err(SchemamamaPostgresError::Io)
+    }
+
+    /// Returns the applied migrations, as recorded in the metadata table, and the migrations among
+    /// `migrations` that are still pending, both ordered by version.
+    pub fn status<'m, I>(&mut self, migrations: I) -> Result<Status, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let applied = self.migration_log()?;
+        let migrated: BTreeSet<Version> = applied.iter().map(|record| record.version).collect();
+        let pending: BTreeMap<Version, String> = migrations.into_iter()
+            .filter(|migration| !migrated.contains(&migration.version()))
+            .map(|migration| (migration.version(), migration.description()))
+            .collect();
+
+        Ok(Status {
+            applied,
+            pending: pending.into_iter().map(|(version, description)| PendingMigration { version, description }).collect(),
+        })
+    }
+
+    /// Describe what `Migrator::up(to)` would do with `migrations`: the pending ones up to and
+    /// including version `to` (or all of them if `to` is `None`) and selected by the adapter's
+    /// tags, in the order they would be applied. Nothing is applied. `Plan::to_json` renders the
+    /// plan for CI comments or deployment gates.
+    pub fn plan<'m, I>(&mut self, migrations: I, to: Option<Version>) -> Result<Plan, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let summary = self.summary()?;
+        let pending: BTreeMap<Version, &dyn PostgresMigration<A>> = migrations.into_iter()
+            .map(|migration| (migration.version(), migration))
+            .filter(|&(version, migration)| {
+                !summary.migrated_versions.contains(&version) && to.map_or(true, |to| version <= to) && self.selects(migration)
+            })
+            .collect();
+
+        Ok(Plan {
+            current_version: summary.current_version,
+            target_version: to,
+            migrations: pending.into_iter().map(|(version, migration)| PlannedMigration {
+                version,
+                description: migration.description(),
+                transactional: migration.run_in_transaction(),
+                checksum: migration.checksum(),
+            }).collect(),
+        })
+    }
+
+    /// Apply the pending migrations among `migrations`, up to and including version `to` (or all
+    /// of them if `to` is `None`) and selected by the adapter's tags, inside a transaction that is
+    /// rolled back afterwards, and report which of them succeeded. Each runs with the session
+    /// settings it would be applied with. The simulation stops at the first failing migration.
+    /// Migrations that `Migrator::up` would record as skipped without running them, and migrations
+    /// that do not run in a transaction, which cannot be simulated, are skipped.
+    pub fn simulate_up<'m, I>(&mut self, migrations: I, to: Option<Version>) -> Result<Simulation, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let migrated = self.migrated_versions()?;
+        let pending: BTreeMap<Version, &dyn PostgresMigration<A>> = migrations.into_iter()
+            .map(|migration| (migration.version(), migration))
+            .filter(|&(version, migration)| {
+                !migrated.contains(&version) && to.map_or(true, |to| version <= to) && self.selects(migration)
+            })
+            .collect();
+        // Settled before the transaction borrows the client.
+        let pending: Vec<_> = pending.into_iter()
+            .map(|(version, migration)| (version, migration, self.skips(migration), self.settings_for(migration)))
+            .collect();
+
+        let mut simulation = Simulation { succeeded: vec![], skipped: vec![], failed: None };
+        let record = self.record_statement()?;
+        let mut transaction = self.client.transaction(self.isolation_level)?;
+        for (version, migration, skipped, settings) in pending {
+            if skipped || !migration.run_in_transaction() {
+                simulation.skipped.push(version);
+                continue;
+            }
+
+            let mut savepoint = transaction.transaction()?;
+            let previous = match settings.apply(&mut savepoint, true) {
+                Ok(previous) => previous,
+                Err(e) => {
+                    simulation.failed = Some((version, e));
+                    break;
+                }
+            };
+            let mut context = MigrationContext::new(
+                &mut savepoint,
+                self.metadata_table.schema.as_deref(),
+                self.environment.as_deref(),
+                &self.placeholders,
+                self.app_context,
+                version
+            );
+            let result = migration.up_ctx(&mut context)
+                .and_then(|_| settings.restore(&mut savepoint, previous, true))
+                .and_then(|_| record_version(&mut savepoint, &record, migration))
+                .and_then(|_| savepoint.commit());
+            match result {
+                Ok(()) => simulation.succeeded.push(version),
+                Err(e) => {
+                    simulation.failed = Some((version, e));
+                    break;
+                }
+            }
+        }
+
+        transaction.rollback()?;
+        Ok(simulation)
+    }
+
+    /// Record every migration among `migrations` up to and including version `version` as applied,
+    /// without running it, for adopting Schemamama on a database whose schema already exists.
+    /// Versions that are already recorded are left alone. All versions are recorded in a single
+    /// statement; the ones that were newly recorded are returned in ascending order.
+    pub fn baseline<'m, I>(&mut self, migrations: I, version: Version) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let migrated = self.migrated_versions()?;
+        let pending: BTreeMap<Version, &dyn PostgresMigration<A>> = migrations.into_iter()
+            .map(|migration| (migration.version(), migration))
+            .filter(|&(v, _)| !migrated.contains(&v) && v <= version)
+            .collect();
+
+        let versions: Vec<Version> = pending.keys().cloned().collect();
+        let descriptions: Vec<Option<String>> = pending.values().map(|migration| Some(migration.description())).collect();
+        let checksums: Vec<Option<String>> = pending.values().map(|migration| migration.checksum()).collect();
+        self.insert_records(&versions, &descriptions, &checksums).map_err(From::from)
+    }
+
+    /// Record `versions` as applied, without descriptions, in a single statement rather than one
+    /// round trip per version, e.g. for marker migrations that do nothing. Versions that are
+    /// already recorded are left alone; the ones that were newly recorded are returned in
+    /// ascending order.
+    pub fn record_versions(&mut self, versions: &[Version]) -> Result<Vec<Version>, SchemamamaPostgresError> {
+        let none = vec![None; versions.len()];
+        self.insert_records(versions, &none, &none).map_err(From::from)
+    }
+
+    /// Apply only the migration of `version` among `migrations`, regardless of what else is
+    /// pending, e.g. a hotfix that must land before the backlog. Returns `false` if it was already
+    /// applied. Fails with `SchemamamaPostgresError::UnknownVersion` if no migration has this
+    /// version.
+    ///
+    /// Unless `allow_out_of_order` acknowledges it, this fails with
+    /// `SchemamamaPostgresError::OutOfOrder` if a newer migration is already applied, or with
+    /// `SchemamamaPostgresError::SkipsPending` if older migrations are still pending. With the
+    /// acknowledgment, the migration is applied whatever the adapter's `OutOfOrder` policy.
+    pub fn apply_single<'m, I>(
+        &mut self,
+        migrations: I,
+        version: Version,
+        allow_out_of_order: bool
+    ) -> Result<bool, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let migrations: Vec<&dyn PostgresMigration<A>> = migrations.into_iter().collect();
+        let migration = match migrations.iter().find(|migration| migration.version() == version) {
+            Some(&migration) => migration,
+            None => return Err(SchemamamaPostgresError::UnknownVersion { version }),
+        };
+
+        let migrated = self.migrated_versions()?;
+        if migrated.contains(&version) {
+            return Ok(false);
+        }
+
+        if !allow_out_of_order {
+            if let Some(&latest) = migrated.iter().next_back().filter(|&&latest| latest > version) {
+                return Err(SchemamamaPostgresError::OutOfOrder { version, latest });
+            }
+
+            let mut pending: Vec<Version> = migrations.iter()
+                .map(|migration| migration.version())
+                .filter(|&other| other < version && !migrated.contains(&other))
+                .collect();
+            if !pending.is_empty() {
+                pending.sort();
+                return Err(SchemamamaPostgresError::SkipsPending { version, pending });
+            }
+        }
+
+        let policy = std::mem::replace(&mut self.out_of_order, OutOfOrder::Ignore);
+        let result = self.apply_migration(migration);
+        self.out_of_order = policy;
+        result.map(|_| true)
+    }
+
+    /// Erase `version` from the metadata table without running its migration's `down` step, e.g.
+    /// after its changes were already undone by hand. Returns whether the version was recorded.
+    pub fn unrecord_version(&mut self, version: Version) -> Result<bool, SchemamamaPostgresError> {
+        self.invalidate_cached_versions();
+        let erase = self.erase_statement()?;
+        Ok(!self.client.query_metadata(&erase, &[&version])?.is_empty())
+    }
+
+    /// Reconcile the metadata table with `migrations` after manual interventions: rows for versions
+    /// that are not among `migrations` are deleted, and if `insert_missing` is set, migrations
+    /// older than the latest recorded version but missing from the table are recorded as applied
+    /// without being run. All changes are made in a single transaction and returned in the order
+    /// they were made.
+    pub fn repair<'m, I>(&mut self, migrations: I, insert_missing: bool) -> Result<Vec<Repair>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let migrated = self.migrated_versions()?;
+        let known: BTreeMap<Version, &dyn PostgresMigration<A>> = migrations.into_iter()
+            .map(|migration| (migration.version(), migration))
+            .collect();
+
+        self.invalidate_cached_versions();
+        let mut repairs = vec![];
+        let erase = self.erase_statement()?;
+        let record = self.record_statement()?;
+        let mut transaction = self.client.transaction(self.isolation_level)?;
+        for &version in migrated.iter().filter(|version| !known.contains_key(version)) {
+            erase_version(&mut transaction, &erase, version)?;
+            repairs.push(Repair::Removed(version));
+        }
+
+        if let (true, Some(&latest)) = (insert_missing, migrated.iter().next_back()) {
+            for (&version, &migration) in known.range(..latest).filter(|&(version, _)| !migrated.contains(version)) {
+                record_version(&mut transaction, &record, migration)?;
+                repairs.push(Repair::Inserted(version));
+            }
+        }
+        transaction.commit()?;
+
+        Ok(repairs)
+    }
+
+    /// Returns the versions recorded in the metadata table that are not among `migrations`, in
+    /// ascending order. See `validate` for a version that fails instead.
+    pub fn unknown_versions<'m, I>(&mut self, migrations: I) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let known: BTreeSet<Version> = migrations.into_iter().map(|migration| migration.version()).collect();
+        Ok(self.migrated_versions()?.difference(&known).cloned().collect())
+    }
+
+    /// Fail with `SchemamamaPostgresError::UnknownVersions` if the metadata table records versions
+    /// that are not among `migrations`. Callers that would rather warn can use `unknown_versions`.
+    pub fn validate<'m, I>(&mut self, migrations: I) -> Result<(), SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let versions = self.unknown_versions(migrations)?;
+        if versions.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemamamaPostgresError::UnknownVersions { versions })
+        }
+    }
+
+    /// Write a single SQL script applying the pending migrations among `migrations` in version
+    /// order, with their transaction markers and the statements recording their versions, for
+    /// DBAs who execute changes by hand. Every pending migration must have an SQL representation
+    /// (see `PostgresMigration::up_sql`); otherwise nothing is written and the first one without
+    /// fails with `SchemamamaPostgresError::NoSqlRepresentation`. Returns the versions the script
+    /// applies.
+    pub fn script_pending<'m, I, W>(&mut self, migrations: I, mut writer: W) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+        W: io::Write,
+    {
+        let migrated = self.migrated_versions()?;
+        let pending: BTreeMap<Version, &dyn PostgresMigration<A>> = migrations.into_iter()
+            .map(|migration| (migration.version(), migration))
+            .filter(|&(version, _)| !migrated.contains(&version))
+            .collect();
+
+        if let Some(migration) = pending.values().find(|migration| migration.up_sql().is_none()) {
+            return Err(SchemamamaPostgresError::NoSqlRepresentation { version: migration.version() });
+        }
+
+        let script: String = pending.values()
+            .map(|&migration| script::apply_script(migration, &self.metadata_table))
+            .collect();
+        writer.write_all(script.as_bytes()).map_err(SchemamamaPostgresError::Io)?;
+        Ok(pending.keys().cloned().collect())
+    }
+
+    /// Compare the checksums stored for applied migrations against the checksums of `migrations`,
+    /// failing with `SchemamamaPostgresError::ChecksumMismatch` if any applied migration changed
+    /// since it was applied. Migrations without a checksum, and versions applied before checksums
+    /// were recorded, are not verified.
+    pub fn verify_checksums<'m, I>(&mut self, migrations: I) -> Result<(), SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let mismatches = self.checksum_mismatches(migrations)?;
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemamamaPostgresError::ChecksumMismatch { mismatches })
+        }
+    }
+
+    /// Like `verify_checksums`, but return every applied migration whose contents changed since it
+    /// was applied instead of failing, e.g. to report them all.
+    pub fn checksum_mismatches<'m, I>(&mut self, migrations: I) -> Result<Vec<ChecksumMismatch>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        if !self.initialize()? {
+            return Ok(vec![]);
+        }
+
+        let query = format!("SELECT version, checksum FROM {} WHERE checksum IS NOT NULL;", self.metadata_table);
+        let stored: BTreeMap<Version, String> = self.metadata_query(&query, &[], &[])?
+            .iter()
+            .map(|r| (r.get(0), r.get(1)))
+            .collect();
+
+        let mut mismatches = vec![];
+        for migration in migrations {
+            let version = migration.version();
+            if let (Some(expected), Some(actual)) = (stored.get(&version), migration.checksum()) {
+                if *expected != actual {
+                    mismatches.push(ChecksumMismatch { version, expected: expected.clone(), actual });
+                }
+            }
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Whether `migration` is to be recorded as skipped rather than run, because its version is
+    /// skipped or it does not apply to the adapter's environment.
+    fn skips(&self, migration: &dyn PostgresMigration<A>) -> bool {
+        if self.skipped_versions.contains(&migration.version()) {
+            return true;
+        }
+        let environments = migration.environments();
+        match self.environment {
+            Some(ref environment) => !environments.is_empty() && !environments.contains(&&**environment),
+            None => false,
+        }
+    }
+
+    /// Whether `migration` is selected by the tags of `with_tags` and `without_tags`.
+    fn selects(&self, migration: &dyn PostgresMigration<A>) -> bool {
+        let tags = migration.tags();
+        if tags.iter().any(|&tag| self.excluded_tags.contains(tag)) {
+            return false;
+        }
+        self.included_tags.as_ref().map_or(true, |included| tags.iter().any(|&tag| included.contains(tag)))
+    }
+
+    /// Enforces the out-of-order policy before `migration` is applied.
+    fn check_order(&mut self, migration: &dyn PostgresMigration<A>) -> Result<(), SchemamamaPostgresError> {
+        if self.out_of_order == OutOfOrder::Ignore {
+            return Ok(());
+        }
+
+        match self.current_version()? {
+            Some(latest) if latest > migration.version() => match self.out_of_order {
+                OutOfOrder::Reject => Err(SchemamamaPostgresError::OutOfOrder { version: migration.version(), latest }),
+                _ => {
+                    logging::out_of_order(migration, latest);
+                    Ok(())
+                }
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the metadata table exists, creating it first if the adapter should `ensure_schema`.
+    /// The table is looked up rather than queried, as a failed query would abort a surrounding
+    /// transaction.
+    fn initialize(&mut self) -> Result<bool, SchemamamaPostgresError> {
+        if !self.metadata_exists {
+            let table = self.metadata_table.to_string();
+            let row = self.metadata_query("SELECT to_regclass($1) IS NOT NULL;", &[Type::TEXT], &[&table])?;
+            self.metadata_exists = row[0].get(0);
+            if !self.metadata_exists && self.ensure_schema {
+                self.setup_schema()?;
+            }
+        }
+
+        Ok(self.metadata_exists)
+    }
+
+    /// Refuses to run migrations while a version is marked as dirty.
+    fn check_clean(&mut self) -> Result<(), SchemamamaPostgresError> {
+        match self.dirty_version()? {
+            Some(version) => Err(SchemamamaPostgresError::Dirty { version }),
+            None => Ok(()),
+        }
+    }
+
+    /// Clears the dirty marker of `version` after its migration failed without crashing: the
+    /// marker row is deleted if it was inserted to apply the migration, or reset otherwise.
+    fn clear_marker(&mut self, version: Version, inserted: bool) -> Result<(), PostgresError> {
+        let query = if inserted {
+            format!("DELETE FROM {} WHERE version = $1 AND dirty;", self.metadata_table)
+        } else {
+            format!("UPDATE {} SET dirty = false WHERE version = $1;", self.metadata_table)
+        };
+        self.metadata_query(&query, &[Type::INT8], &[&version]).map(|_| ())
+    }
+
+    /// Prepares `query`, whose parameters have the given `types`, or returns the statement
+    /// prepared for it earlier on the same connection. With `with_unnamed_statements`, nothing is
+    /// prepared.
+    fn prepare_cached(&mut self, query: String, types: &[Type]) -> Result<MetadataStatement, PostgresError> {
+        if self.unnamed_statements {
+            return Ok(MetadataStatement::Unnamed(query, types.to_vec()));
+        }
+        if let Some(statement) = self.statements.get(&query) {
+            return Ok(MetadataStatement::Prepared(statement.clone()));
+        }
+
         let statement = self.client.prepare(&query)?;
-        self.client.execute(&statement, &[]).map(|_| ())
+        self.statements.insert(query, statement.clone());
+        Ok(MetadataStatement::Prepared(statement))
+    }
+
+    /// The statement running `query`, whose parameters have the given `types`, without caching it.
+    fn statement(&self, query: &str, types: &[Type]) -> MetadataStatement {
+        MetadataStatement::new(query, types, self.unnamed_statements)
+    }
+
+    /// Runs `query`, whose parameters have the given `types`, with `params`.
+    fn metadata_query(&mut self, query: &str, types: &[Type], params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, PostgresError> {
+        let statement = self.statement(query, types);
+        self.client.query_metadata(&statement, params)
+    }
+
+    /// Inserts one metadata row per element of the given slices, which must have the same length,
+    /// in a single statement. Returns the versions that were not recorded already, in ascending
+    /// order.
+    fn insert_records(
+        &mut self,
+        versions: &[Version],
+        descriptions: &[Option<String>],
+        checksums: &[Option<String>]
+    ) -> Result<Vec<Version>, PostgresError> {
+        self.invalidate_cached_versions();
+        let statement = self.prepare_cached(format!(
+            "INSERT INTO {} (version, description, checksum, applied_at, applied_by) \
+             SELECT *, now(), current_user FROM unnest($1::BIGINT[], $2::TEXT[], $3::TEXT[]) \
+             ON CONFLICT (version) DO NOTHING RETURNING version;",
+            self.metadata_table
+        ), &[Type::INT8_ARRAY, Type::TEXT_ARRAY, Type::TEXT_ARRAY])?;
+        let mut recorded: Vec<Version> = self.client.query_metadata(&statement, &[&versions, &descriptions, &checksums])?
+            .iter()
+            .map(|r| r.get(0))
+            .collect();
+        recorded.sort();
+        Ok(recorded)
+    }
+
+    /// The statement recording a migration in the metadata table, for `record_version`.
+    fn record_statement(&mut self) -> Result<MetadataStatement, PostgresError> {
+        self.prepare_cached(format!(
+            "INSERT INTO {} (version, description, checksum, metadata, applied_at, applied_by) \
+             VALUES ($1, $2, $3, $4::TEXT::JSONB, now(), current_user){};",
+            self.metadata_table,
+            self.record_conflict.clause()
+        ), &RECORD_TYPES)
+    }
+
+    /// The statement appending a `HistoryEvent` to the history table, if there is one.
+    fn history_statement(&mut self) -> Option<Result<MetadataStatement, PostgresError>> {
+        let history_table = self.history_table.as_ref()?.to_string();
+        Some(self.prepare_cached(format!(
+            "INSERT INTO {} (version, direction, duration_ms, outcome, error) VALUES ($1, $2, $3, $4, $5);",
+            history_table
+        ), &[Type::INT8, Type::TEXT, Type::INT8, Type::TEXT, Type::TEXT]))
+    }
+
+    /// Records in the history table that a migration failed. Failing to do so is not reported, as
+    /// it usually means that the connection was lost along with the migration.
+    fn record_failure(&mut self, direction: Direction, version: Version, started: Instant, error: &SchemamamaPostgresError) {
+        if let Some(Ok(history)) = self.history_statement() {
+            let event = HistoryEvent::new(direction, version, started, Some(error.to_string()));
+            let _ = self.client.query_metadata(&history, &event.params());
+        }
+    }
+
+    /// The statement erasing a version from the metadata table, for `erase_version`.
+    fn erase_statement(&mut self) -> Result<MetadataStatement, PostgresError> {
+        self.prepare_cached(format!("DELETE FROM {} WHERE version = $1 RETURNING version;", self.metadata_table), &[Type::INT8])
+    }
+
+    fn report_progress(
+        &mut self,
+        direction: Direction,
+        status: ProgressStatus,
+        version: Version,
+        description: &str
+    ) {
+        if let Some(ref mut progress) = self.progress {
+            if status == ProgressStatus::Started {
+                progress.position += 1;
+            }
+
+            progress.observer.on_progress(&ProgressEvent {
+                direction,
+                status,
+                position: progress.position,
+                total: progress.total,
+                version,
+                description: description.to_owned(),
+            });
+        }
+    }
+
+    /// The session settings to apply while `migration` runs. They are scoped to the migration's
+    /// transaction, or restored afterwards for migrations that do not run in one.
+    fn settings_for(&self, migration: &dyn PostgresMigration<A>) -> MigrationSettings {
+        let mut settings = MigrationSettings { unnamed_statements: self.unnamed_statements, ..MigrationSettings::default() };
+        if let Some(timeout) = migration.statement_timeout().or(self.statement_timeout) {
+            settings.push("statement_timeout", format!("{}ms", timeout.as_millis()));
+        }
+        if let Some(timeout) = migration.lock_timeout().or(self.lock_timeout) {
+            settings.push("lock_timeout", format!("{}ms", timeout.as_millis()));
+        }
+        if let Some(ref search_path) = self.search_path {
+            settings.push_identifiers("search_path", search_path);
+        }
+        if let Some(ref role) = self.role {
+            settings.push("role", role.clone());
+        }
+        if let Some(interval) = self.keepalive {
+            let seconds = format!("{}s", interval.as_secs().max(1));
+            settings.push("tcp_keepalives_idle", seconds.clone());
+            settings.push("tcp_keepalives_interval", seconds);
+        }
+        for &(name, value) in migration.session_params() {
+            settings.push(name, value.to_owned());
+        }
+
+        settings
+    }
+
+    fn acquire_lock(&mut self) -> Result<(), SchemamamaPostgresError> {
+        let key = match self.advisory_lock {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+
+        let deadline = match self.lock_behavior {
+            LockBehavior::Block => {
+                return self.metadata_query("SELECT pg_advisory_lock($1);", &[Type::INT8], &[&key]).map(|_| ()).map_err(From::from);
+            }
+            LockBehavior::Timeout(timeout) => Some(Instant::now() + timeout),
+            LockBehavior::FailFast => None,
+        };
+
+        loop {
+            let row = self.metadata_query("SELECT pg_try_advisory_lock($1);", &[Type::INT8], &[&key])?;
+            if row[0].get(0) {
+                return Ok(());
+            }
+
+            match deadline {
+                Some(deadline) if Instant::now() < deadline => {
+                    thread::sleep(LOCK_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+                }
+                _ => return Err(SchemamamaPostgresError::AlreadyLocked { key }),
+            }
+        }
+    }
+
+    fn release_lock(&mut self) -> Result<(), SchemamamaPostgresError> {
+        match self.advisory_lock {
+            Some(key) => self.metadata_query("SELECT pg_advisory_unlock($1);", &[Type::INT8], &[&key]).map(|_| ()).map_err(From::from),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `f`, and runs it again according to the retry policy while it fails with a transient
+    /// error. If the connection was lost and the adapter can reconnect, `f` is resumed once on a
+    /// new connection, unless the metadata table shows that it succeeded before the connection
+    /// died.
+    fn retrying<F>(
+        &mut self,
+        direction: Direction,
+        migration: &dyn PostgresMigration<A>,
+        mut f: F
+    ) -> Result<(), SchemamamaPostgresError>
+    where
+        F: FnMut(&mut Self) -> Result<(), SchemamamaPostgresError>,
+    {
+        let mut attempt = 1;
+        let mut resumed = false;
+        loop {
+            // Whether or not `f` succeeded, it may have changed the metadata table.
+            let result = f(self);
+            self.invalidate_cached_versions();
+            let error = match result {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+
+            if !resumed && migration.run_in_transaction() && self.reconnect_if_lost(&error)? {
+                logging::reconnected(migration);
+                let applied = self.migrated_versions()?.contains(&migration.version());
+                if applied == (direction == Direction::Apply) {
+                    return Ok(());
+                }
+                resumed = true;
+                continue;
+            }
+
+            match self.retry {
+                Some(policy)
+                    if attempt < policy.max_attempts
+                        && migration.run_in_transaction()
+                        && error.postgres_error().map_or(false, is_transient) =>
+                {
+                    let backoff = policy.backoff(attempt);
+                    logging::retrying(migration, attempt, &error, backoff);
+                    thread::sleep(backoff);
+                    attempt += 1;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+
+    /// Replaces the client with a new connection if `error` shows that the connection was lost
+    /// and the adapter knows how to reconnect. Returns whether it reconnected.
+    fn reconnect_if_lost(&mut self, error: &SchemamamaPostgresError) -> Result<bool, SchemamamaPostgresError> {
+        let connect = match self.reconnect {
+            Some(ref mut connect) => connect,
+            None => return Ok(false),
+        };
+
+        let lost = self.client.client().map_or(false, |client| client.is_closed())
+            || error.postgres_error().map_or(false, |error| {
+                error.is_closed() || error.code() == Some(&SqlState::ADMIN_SHUTDOWN)
+            });
+        if lost {
+            self.client = connect()?;
+            self.statements.clear();
+        }
+
+        Ok(lost)
+    }
+
+    /// Runs `f` while holding the configured advisory lock, if any. The lock is released even if
+    /// `f` fails.
+    fn locked<F>(&mut self, f: F) -> Result<(), SchemamamaPostgresError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), SchemamamaPostgresError>,
+    {
+        // Begin the batch before taking the lock: a failure to begin it leaves the client unusable,
+        // so the lock could not be released anymore.
+        if self.atomic_batch {
+            self.client.begin_batch(self.isolation_level)?;
+        }
+        self.acquire_lock()?;
+        let result = f(self);
+        let released = self.release_lock();
+        result.and(released)
     }
 }
 
-fn record_version(transaction: &mut Transaction, version: Version, metadata_table: &str) -> Result<(), PostgresError> {
-    let query = format!("INSERT INTO {} (version) VALUES ($1);", metadata_table);
-    let statement = transaction.prepare(&query)?;
-    transaction.execute(&statement, &[&version]).map(|_| ())
+impl<'a> PostgresAdapter<'a> {
+    /// Create a new migrator that owns the client returned by `connect`, and calls `connect` again
+    /// to replace it if the connection is lost while a migration runs. The interrupted migration is
+    /// then resumed on the new connection, unless the metadata table shows that it completed just
+    /// before the connection died. Migrations that do not run in a transaction are not resumed,
+    /// as they may have been partially applied. The metadata table is `schemamama` unless changed
+    /// with `with_metadata_table_name`.
+    ///
+    /// ```rust,ignore
+    /// let adapter = PostgresAdapter::reconnecting(|| Client::connect(&url, NoTls))?
+    ///     .with_metadata_table_name("app.migrations");
+    /// ```
+    pub fn reconnecting<F>(mut connect: F) -> Result<PostgresAdapter<'a>, PostgresError>
+    where
+        F: FnMut() -> Result<Client, PostgresError> + 'a,
+    {
+        let client = connect()?;
+        let mut adapter = Self::from_handle(ClientHandle::Owned(Box::new(client)), MetadataTable::parse("schemamama"));
+        adapter.reconnect = Some(Box::new(move || connect().map(|client| ClientHandle::Owned(Box::new(client)))));
+        Ok(adapter)
+    }
 }
 
-fn erase_version(transaction: &mut Transaction, version: Version, metadata_table: &str) -> Result<(), PostgresError> {
-    let query = format!("DELETE FROM {} WHERE version = $1;", metadata_table);
-    let statement = transaction.prepare(&query)?;
-    transaction.execute(&statement, &[&version]).map(|_| ())
+impl<'a, 't: 'a> PostgresAdapter<'a, Transaction<'t>> {
+    /// Create a new migrator running in `transaction`, which is owned by the caller: each migration
+    /// runs in a savepoint, and nothing is committed until the caller commits the transaction. This
+    /// is `new` for transactions, spelled out for tests that migrate a database and roll
+    /// everything back afterwards, instead of cleaning up schemas:
+    ///
+    /// ```rust,ignore
+    /// let mut transaction = client.transaction()?;
+    /// Migrator::new(PostgresAdapter::from_transaction(&mut transaction)).up(None)?;
+    /// // ... exercise the migrated schema ...
+    /// transaction.rollback()?;
+    /// ```
+    pub fn from_transaction(transaction: &'a mut Transaction<'t>) -> PostgresAdapter<'a, Transaction<'t>> {
+        Self::new(transaction)
+    }
 }
 
-impl<'a> Adapter for PostgresAdapter<'a> {
-    type MigrationType = dyn PostgresMigration;
-    type Error = PostgresError;
+#[cfg(feature = "r2d2")]
+impl PostgresAdapter<'static> {
+    /// Create a new migrator that owns a connection checked out of an `r2d2` pool. The connection
+    /// is returned to the pool when the adapter is dropped. The metadata table is `schemamama`
+    /// unless changed with `with_metadata_table_name`.
+    pub fn from_pooled_connection<M>(connection: r2d2::PooledConnection<M>) -> PostgresAdapter<'static>
+    where
+        M: r2d2::ManageConnection<Connection = Client>,
+    {
+        Self::from_handle(ClientHandle::Owned(Box::new(connection)), MetadataTable::parse("schemamama"))
+    }
 
-    fn current_version(&mut self) -> Result<Option<Version>, PostgresError> {
-        let query = format!("SELECT version FROM {} ORDER BY version DESC LIMIT 1;", self.metadata_table);
-        let statement = self.client.prepare(&query)?;
-        let row = self.client.query(&statement, &[])?;
-        Ok(row.iter().next().map(|r| r.get(0)))
+    /// Create a new migrator that checks a connection out of an `r2d2` pool for the lifetime of
+    /// the adapter.
+    pub fn from_pool<M>(pool: &r2d2::Pool<M>) -> Result<PostgresAdapter<'static>, r2d2::Error>
+    where
+        M: r2d2::ManageConnection<Connection = Client>,
+    {
+        Ok(Self::from_pooled_connection(pool.get()?))
     }
+}
 
-    fn migrated_versions(&mut self) -> Result<BTreeSet<Version>, PostgresError> {
-        let query = format!("SELECT version FROM {};", self.metadata_table);
-        let statement = self.client.prepare(&query)?;
-        let row = self.client.query(&statement, &[])?;
-        Ok(row.iter().map(|r| r.get(0)).collect())
+/// Columns of the metadata table that did not exist in its first release, with the definitions
+/// used to add them to existing tables.
+const ADDED_METADATA_COLUMNS: &[&str] = &[
+    "checksum TEXT",
+    "applied_at TIMESTAMPTZ",
+    "description TEXT",
+    "applied_by TEXT",
+    "dirty BOOLEAN NOT NULL DEFAULT false",
+    "skipped BOOLEAN NOT NULL DEFAULT false",
+    "metadata JSONB",
+    "sql TEXT",
+    "applied_seq BIGSERIAL",
+];
+
+/// A row of the metadata table, describing an applied migration. Fields that were not recorded
+/// when the migration was applied (by an earlier release) are `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationRecord {
+    /// The version of the migration.
+    pub version: Version,
+    /// The description of the migration at the time it was applied.
+    pub description: Option<String>,
+    /// When the migration was applied.
+    pub applied_at: Option<SystemTime>,
+    /// The database user (`current_user`) that applied the migration.
+    pub applied_by: Option<String>,
+    /// Whether the migration was recorded as applied without being run, because its version was
+    /// skipped or it did not apply to the adapter's environment (see
+    /// `PostgresAdapter::with_skipped_versions` and `with_environment`).
+    pub skipped: bool,
+    /// The metadata recorded with the migration (see `PostgresMigration::metadata`), as JSON text.
+    pub metadata: Option<String>,
+    /// The SQL that applied the migration, if the adapter was configured to `store_sql`.
+    pub sql: Option<String>,
+}
+
+/// The state of the database relative to the registered migrations, as reported by
+/// `PostgresAdapter::status`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// The applied migrations, including versions no registered migration knows about.
+    pub applied: Vec<MigrationRecord>,
+    /// The registered migrations that are not applied yet.
+    pub pending: Vec<PendingMigration>,
+}
+
+/// The state of the metadata table, as returned by `PostgresAdapter::summary`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataSummary {
+    /// The latest applied version, as returned by `current_version`.
+    pub current_version: Option<Version>,
+    /// The applied versions, as returned by `migrated_versions`.
+    pub migrated_versions: BTreeSet<Version>,
+    /// How many of the applied versions were skipped rather than run.
+    pub skipped: usize,
+    /// How many versions were interrupted while running outside of a transaction.
+    pub dirty: usize,
+}
+
+/// A registered migration that is not applied yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingMigration {
+    /// The version of the migration.
+    pub version: Version,
+    /// The description of the migration.
+    pub description: String,
+}
+
+/// The migrations that `Migrator::up` would apply, as reported by `PostgresAdapter::plan`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plan {
+    /// The latest applied version before the run.
+    pub current_version: Option<Version>,
+    /// The version the run would stop at, or `None` to apply every pending migration.
+    pub target_version: Option<Version>,
+    /// The migrations to apply, in order.
+    pub migrations: Vec<PlannedMigration>,
+}
+
+impl Plan {
+    /// Renders the plan as a JSON document of the form
+    /// `{"current_version": 3, "target_version": null, "migrations": [...]}`, with one object per
+    /// migration holding its `version`, `description`, `transactional` flag and `checksum`.
+    pub fn to_json(&self) -> String {
+        export::render_plan_json(self)
     }
+}
 
-    fn apply_migration(&mut self, migration: &dyn PostgresMigration) -> Result<(), PostgresError> {
-        let mut transaction = self.client.transaction()?;
-        migration.up(&mut transaction)?;
-        record_version(&mut transaction, migration.version(), self.metadata_table)?;
-        transaction.commit()?;
-        Ok(())
+/// A migration that a `Plan` would apply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedMigration {
+    /// The version of the migration.
+    pub version: Version,
+    /// The description of the migration.
+    pub description: String,
+    /// Whether the migration runs in a transaction, and so cannot be left partially applied.
+    pub transactional: bool,
+    /// The checksum of the migration, if it has one.
+    pub checksum: Option<String>,
+}
+
+/// The outcome of `PostgresAdapter::simulate_up`.
+#[derive(Debug)]
+pub struct Simulation {
+    /// Versions of the migrations that were applied successfully before being rolled back.
+    pub succeeded: Vec<Version>,
+    /// Versions of the migrations that were skipped: those that would be recorded as skipped
+    /// without running, and those that do not run in a transaction and could not be simulated.
+    pub skipped: Vec<Version>,
+    /// The version of the migration that failed, along with its error.
+    pub failed: Option<(Version, PostgresError)>,
+}
+
+/// What a `PostgresAdapter` does when the advisory lock of `with_advisory_lock` is held by
+/// another migrator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockBehavior {
+    /// Wait until the lock is released.
+    Block,
+    /// Wait until the lock is released, for at most the given duration, then fail with
+    /// `SchemamamaPostgresError::AlreadyLocked`.
+    Timeout(Duration),
+    /// Fail immediately with `SchemamamaPostgresError::AlreadyLocked`.
+    FailFast,
+}
+
+/// How often a `LockBehavior::Timeout` checks whether the advisory lock was released.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a `PostgresAdapter` does when asked to apply a migration older than the latest applied
+/// one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfOrder {
+    /// Fail with `SchemamamaPostgresError::OutOfOrder` without applying the migration.
+    Reject,
+    /// Apply the migration, logging a warning (with the `logging` feature).
+    WarnAndApply,
+    /// Apply the migration silently.
+    Ignore,
+}
+
+/// What a `PostgresAdapter` does when the version of a migration it applied is already recorded
+/// in the metadata table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordConflict {
+    /// Fail with `SchemamamaPostgresError::AlreadyRecorded`. Migrations running in a transaction
+    /// check their version under a lock before they run, so that one applied concurrently by
+    /// another migrator is not run twice.
+    Fail,
+    /// Keep the existing row as it is.
+    Ignore,
+    /// Overwrite the description, checksum and metadata of the existing row, and record it as
+    /// applied now by the current user, after every other migration.
+    Update,
+}
+
+impl RecordConflict {
+    /// The `ON CONFLICT` clause of the statements recording a version.
+    fn clause(self) -> &'static str {
+        match self {
+            RecordConflict::Fail => "",
+            RecordConflict::Ignore => " ON CONFLICT (version) DO NOTHING",
+            RecordConflict::Update => {
+                " ON CONFLICT (version) DO UPDATE SET description = excluded.description, \
+                 checksum = excluded.checksum, metadata = excluded.metadata, dirty = excluded.dirty, \
+                 applied_at = now(), applied_by = current_user, applied_seq = excluded.applied_seq"
+            }
+        }
     }
+}
+
+/// The order in which `PostgresAdapter::reset` and `PostgresAdapter::revert_last` revert
+/// migrations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevertOrder {
+    /// Newest version first.
+    Version,
+    /// Most recently applied first.
+    Applied,
+}
+
+/// A change made to the metadata table by `PostgresAdapter::repair`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repair {
+    /// The row of a version that is not among the registered migrations was deleted.
+    Removed(Version),
+    /// A missing row was recorded for a registered migration, without running it.
+    Inserted(Version),
+}
+
+/// An applied migration whose checksum no longer matches the one recorded when it was applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// The version of the edited migration.
+    pub version: Version,
+    /// The checksum recorded in the metadata table.
+    pub expected: String,
+    /// The checksum of the migration as currently registered.
+    pub actual: String,
+}
+
+/// Records `migration` as applied with the statement returned by
+/// `PostgresAdapter::record_statement`.
+fn record_version<A>(
+    transaction: &mut Transaction,
+    statement: &MetadataStatement,
+    migration: &dyn PostgresMigration<A>
+) -> Result<(), PostgresError> {
+    let params: [&(dyn ToSql + Sync); 4] = [
+        &migration.version(),
+        &migration.description(),
+        &migration.checksum(),
+        &metadata_json(migration),
+    ];
+    statement.query(transaction, &params).map(|_| ())
+}
+
+/// The types of the parameters of `record_version`.
+const RECORD_TYPES: [Type; 4] = [Type::INT8, Type::TEXT, Type::TEXT, Type::TEXT];
+
+/// Makes sure, before `migration` runs in `transaction`, that no other migrator recorded its
+/// version since it was found pending. The metadata table stays locked against writes by other
+/// migrators until the transaction ends, so that two of them cannot both run the migration.
+/// (Migrations running outside of a transaction claim their version by recording it as dirty
+/// first.)
+fn claim_version<A>(
+    transaction: &mut Transaction,
+    metadata_table: &MetadataTable,
+    unnamed_statements: bool,
+    migration: &dyn PostgresMigration<A>
+) -> Result<(), SchemamamaPostgresError> {
+    let query = format!("LOCK TABLE {} IN SHARE ROW EXCLUSIVE MODE;", metadata_table);
+    transaction.batch_execute(&query).map_err(in_phase(migration, Phase::Record))?;
+    let query = format!("SELECT EXISTS (SELECT 1 FROM {} WHERE version = $1);", metadata_table);
+    let statement = MetadataStatement::new(&query, &[Type::INT8], unnamed_statements);
+    let rows = statement.query(transaction, &[&migration.version()]).map_err(in_phase(migration, Phase::Record))?;
+    if rows[0].get(0) {
+        return Err(SchemamamaPostgresError::AlreadyRecorded { version: migration.version() });
+    }
+    Ok(())
+}
+
+/// The metadata of `migration` as JSON text, or `None` if it has none.
+#[cfg(feature = "json")]
+fn metadata_json<A>(migration: &dyn PostgresMigration<A>) -> Option<String> {
+    match migration.metadata() {
+        serde_json::Value::Null => None,
+        metadata => Some(metadata.to_string()),
+    }
+}
+
+#[cfg(not(feature = "json"))]
+fn metadata_json<A>(_migration: &dyn PostgresMigration<A>) -> Option<String> {
+    None
+}
+
+/// Erases `version` with the statement returned by `PostgresAdapter::erase_statement`.
+fn erase_version(transaction: &mut Transaction, statement: &MetadataStatement, version: Version) -> Result<(), PostgresError> {
+    statement.query(transaction, &[&version]).map(|_| ())
+}
+
+/// A row of the history table.
+struct HistoryEvent {
+    version: Version,
+    direction: &'static str,
+    duration_ms: i64,
+    outcome: &'static str,
+    error: Option<String>,
+}
+
+impl HistoryEvent {
+    /// Describes a migration of `version` in `direction` that started at `started` and failed with
+    /// `error`, if any.
+    fn new(direction: Direction, version: Version, started: Instant, error: Option<String>) -> HistoryEvent {
+        HistoryEvent {
+            version,
+            direction: match direction {
+                Direction::Apply => "apply",
+                Direction::Revert => "revert",
+            },
+            duration_ms: started.elapsed().as_millis() as i64,
+            outcome: if error.is_some() { "failed" } else { "succeeded" },
+            error,
+        }
+    }
+
+    /// The parameters of the statement returned by `PostgresAdapter::history_statement`.
+    fn params(&self) -> [&(dyn ToSql + Sync); 5] {
+        [&self.version, &self.direction, &self.duration_ms, &self.outcome, &self.error]
+    }
+}
+
+/// Session settings applied while a migration runs, with their values in the text form accepted
+/// by `SET`.
+#[derive(Default)]
+struct MigrationSettings {
+    settings: Vec<(String, String)>,
+    unnamed_statements: bool,
+}
+
+impl MigrationSettings {
+    fn push(&mut self, name: &str, value: String) {
+        self.settings.push((name.to_owned(), value));
+    }
+
+    /// Adds a setting whose value is a list of identifiers, such as `search_path`.
+    fn push_identifiers(&mut self, name: &str, identifiers: &[String]) {
+        let value = identifiers.iter().map(|identifier| quote_identifier(identifier)).collect::<Vec<_>>().join(", ");
+        self.push(name, value);
+    }
+
+    /// Applies the settings to the current transaction if `local` is set, or to the session
+    /// otherwise, returning their previous values so that `restore` can put them back.
+    fn apply<G: MigrationClient>(&self, client: &mut G, local: bool) -> Result<Vec<(String, String)>, PostgresError> {
+        let current = MetadataStatement::new("SELECT current_setting($1);", &[Type::TEXT], self.unnamed_statements);
+        let mut previous = Vec::with_capacity(self.settings.len());
+        for (name, value) in &self.settings {
+            previous.push((name.clone(), current.query(client, &[name])?[0].get(0)));
+            self.set_config(client, name, value, local)?;
+        }
+
+        Ok(previous)
+    }
+
+    /// Puts back the values returned by `apply`, in reverse order since a setting may have been
+    /// applied more than once.
+    fn restore<G: MigrationClient>(&self, client: &mut G, previous: Vec<(String, String)>, local: bool) -> Result<(), PostgresError> {
+        for (name, value) in previous.into_iter().rev() {
+            self.set_config(client, &name, &value, local)?;
+        }
 
-    fn revert_migration(&mut self, migration: &dyn PostgresMigration) -> Result<(), PostgresError> {
-        let mut transaction = self.client.transaction()?;
-        migration.down(&mut transaction)?;
-        erase_version(&mut transaction, migration.version(), self.metadata_table)?;
-        transaction.commit()?;
         Ok(())
     }
+
+    fn set_config<G: MigrationClient>(&self, client: &mut G, name: &str, value: &str, local: bool) -> Result<(), PostgresError> {
+        let statement = MetadataStatement::new(
+            "SELECT set_config($1, $2, $3);",
+            &[Type::TEXT, Type::TEXT, Type::BOOL],
+            self.unnamed_statements
+        );
+        statement.query(client, &[&name, &value, &local]).map(|_| ())
+    }
+}
+
+/// Wraps an error of `migration` during `phase` with the migration's version and description.
+fn in_phase<'m, A: 'm>(
+    migration: &'m dyn PostgresMigration<A>,
+    phase: Phase
+) -> impl FnOnce(PostgresError) -> SchemamamaPostgresError + 'm {
+    in_statement(migration, phase, None)
+}
+
+/// Like `in_phase`, but also reports the failing `statement` that the migration ran with
+/// `MigrationContext::execute_statements`, if any.
+fn in_statement<'m, A: 'm>(
+    migration: &'m dyn PostgresMigration<A>,
+    phase: Phase,
+    statement: Option<(usize, String)>
+) -> impl FnOnce(PostgresError) -> SchemamamaPostgresError + 'm {
+    move |error| SchemamamaPostgresError::Migration {
+        version: migration.version(),
+        description: migration.description(),
+        phase,
+        error,
+        statement,
+    }
+}
+
+/// Like `in_phase` for `Phase::Record`, but reports versions that are already recorded as
+/// `SchemamamaPostgresError::AlreadyRecorded`.
+fn recording<'m, A: 'm>(migration: &'m dyn PostgresMigration<A>) -> impl FnOnce(PostgresError) -> SchemamamaPostgresError + 'm {
+    move |error| match error.code() {
+        Some(code) if *code == SqlState::UNIQUE_VIOLATION => {
+            SchemamamaPostgresError::AlreadyRecorded { version: migration.version() }
+        }
+        _ => in_phase(migration, Phase::Record)(error),
+    }
+}
+
+impl<'a, 'b, C: MigrationClient + 'a, A: 'a> Adapter for &'b mut PostgresAdapter<'a, C, A> {
+    type MigrationType = dyn PostgresMigration<A>;
+    type Error = SchemamamaPostgresError;
+
+    fn current_version(&mut self) -> Result<Option<Version>, SchemamamaPostgresError> {
+        (**self).current_version()
+    }
+
+    fn migrated_versions(&mut self) -> Result<BTreeSet<Version>, SchemamamaPostgresError> {
+        (**self).migrated_versions()
+    }
+
+    fn apply_migration(&mut self, migration: &dyn PostgresMigration<A>) -> Result<(), SchemamamaPostgresError> {
+        (**self).apply_migration(migration)
+    }
+
+    fn revert_migration(&mut self, migration: &dyn PostgresMigration<A>) -> Result<(), SchemamamaPostgresError> {
+        (**self).revert_migration(migration)
+    }
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> Adapter for PostgresAdapter<'a, C, A> {
+    type MigrationType = dyn PostgresMigration<A>;
+    type Error = SchemamamaPostgresError;
+
+    fn current_version(&mut self) -> Result<Option<Version>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(None);
+        }
+
+        let query = format!("SELECT version FROM {} WHERE NOT dirty ORDER BY version DESC LIMIT 1;", self.metadata_table);
+        let statement = self.prepare_cached(query, &[])?;
+        let row = self.client.query_metadata(&statement, &[])?;
+        Ok(row.iter().next().map(|r| r.get(0)))
+    }
+
+    fn migrated_versions(&mut self) -> Result<BTreeSet<Version>, SchemamamaPostgresError> {
+        if !self.initialize()? {
+            return Ok(BTreeSet::new());
+        }
+
+        if let Some(ref versions) = self.cached_versions {
+            return Ok(versions.clone());
+        }
+
+        let query = format!("SELECT version FROM {} WHERE NOT dirty;", self.metadata_table);
+        let statement = self.prepare_cached(query, &[])?;
+        let row = self.client.query_metadata(&statement, &[])?;
+        let versions: BTreeSet<Version> = row.iter().map(|r| r.get(0)).collect();
+        if self.cache_versions {
+            self.cached_versions = Some(versions.clone());
+        }
+        Ok(versions)
+    }
+
+    fn apply_migration(&mut self, migration: &dyn PostgresMigration<A>) -> Result<(), SchemamamaPostgresError> {
+        if !self.selects(migration) {
+            logging::passed_over(migration);
+            return Ok(());
+        }
+        self.check_order(migration)?;
+        if self.dry_run.is_some() {
+            let script = if self.skips(migration) {
+                script::skip_script(migration, &self.metadata_table)
+            } else {
+                script::apply_script(migration, &self.metadata_table)
+            };
+            self.dry_run.as_mut().expect("checked above").push_str(&script);
+            return Ok(());
+        }
+        self.initialize()?;
+        if self.skips(migration) {
+            self.invalidate_cached_versions();
+            let query = format!(
+                "INSERT INTO {} (version, description, checksum, metadata, skipped) VALUES ($1, $2, $3, $4::TEXT::JSONB, true);",
+                self.metadata_table
+            );
+            let params: [&(dyn ToSql + Sync); 4] =
+                [&migration.version(), &migration.description(), &migration.checksum(), &metadata_json(migration)];
+            return self.metadata_query(&query, &RECORD_TYPES, &params).map(|_| ()).map_err(in_phase(migration, Phase::Record));
+        }
+
+        logging::starting("applying", migration);
+        let description = migration.description();
+        for hooks in &mut self.hooks {
+            hooks.before_apply(migration.version(), &description);
+        }
+        self.report_progress(Direction::Apply, ProgressStatus::Started, migration.version(), &description);
+
+        let started = Instant::now();
+        let result = self.retrying(Direction::Apply, migration, |adapter| adapter.locked(|adapter| {
+            adapter.check_clean()?;
+            let settings = adapter.settings_for(migration);
+            if !migration.run_in_transaction() {
+                if adapter.client.client().is_none() {
+                    return Err(SchemamamaPostgresError::NonTransactionalInBatch { version: migration.version() });
+                }
+                // Mark the version as dirty until it is recorded, so that a crash in between is
+                // detected by `check_clean` rather than leaving a partially applied migration.
+                let query = format!(
+                    "INSERT INTO {} (version, description, checksum, metadata, dirty) VALUES ($1, $2, $3, $4::TEXT::JSONB, true){};",
+                    adapter.metadata_table,
+                    adapter.record_conflict.clause()
+                );
+                let params: [&(dyn ToSql + Sync); 4] =
+                    [&migration.version(), &description, &migration.checksum(), &metadata_json(migration)];
+                adapter.metadata_query(&query, &RECORD_TYPES, &params).map_err(recording(migration))?;
+
+                let client = adapter.client.client().expect("checked above");
+                let previous = settings.apply(client, false).map_err(in_phase(migration, Phase::Up))?;
+                let mut context = MigrationContext::new(
+                    &mut *client,
+                    adapter.metadata_table.schema.as_deref(),
+                    adapter.environment.as_deref(),
+                    &adapter.placeholders,
+                    adapter.app_context,
+                    migration.version()
+                );
+                let result = migration.up_without_transaction_ctx(&mut context);
+                let failed = context.failed_statement();
+                let restored = settings.restore(client, previous, false);
+                if let Err(error) = result.and(restored) {
+                    // The failure is reported, so the marker is only left behind if the connection
+                    // is lost.
+                    let _ = adapter.clear_marker(migration.version(), true);
+                    return Err(in_statement(migration, Phase::Up, failed)(error));
+                }
+            }
+
+            let record = adapter.record_statement().map_err(in_phase(migration, Phase::Record))?;
+            let history = adapter.history_statement().transpose().map_err(in_phase(migration, Phase::Record))?;
+            let mut transaction = adapter.client.transaction(adapter.isolation_level).map_err(in_phase(migration, Phase::Up))?;
+            if migration.run_in_transaction() {
+                if adapter.record_conflict == RecordConflict::Fail {
+                    claim_version(&mut transaction, &adapter.metadata_table, adapter.unnamed_statements, migration)?;
+                }
+                let previous = settings.apply(&mut transaction, true).map_err(in_phase(migration, Phase::Up))?;
+                let mut context = MigrationContext::new(
+                    &mut transaction,
+                    adapter.metadata_table.schema.as_deref(),
+                    adapter.environment.as_deref(),
+                    &adapter.placeholders,
+                    adapter.app_context,
+                    migration.version()
+                );
+                let result = migration.up_ctx(&mut context);
+                result.map_err(in_statement(migration, Phase::Up, context.failed_statement()))?;
+                // Restore the settings before touching the metadata table, whose name may depend on
+                // the `search_path`. In a savepoint of an outer transaction, they would otherwise
+                // outlive the migration.
+                settings.restore(&mut transaction, previous, true).map_err(in_phase(migration, Phase::Up))?;
+                record_version(&mut transaction, &record, migration).map_err(recording(migration))?;
+            } else {
+                let query = format!(
+                    "UPDATE {} SET dirty = false, applied_at = now(), applied_by = current_user WHERE version = $1;",
+                    adapter.metadata_table
+                );
+                MetadataStatement::new(&query, &[Type::INT8], adapter.unnamed_statements)
+                    .query(&mut transaction, &[&migration.version()])
+                    .map_err(in_phase(migration, Phase::Record))?;
+            }
+            if let (true, Some(sql)) = (adapter.store_sql, migration.up_sql()) {
+                let query = format!("UPDATE {} SET sql = $2 WHERE version = $1;", adapter.metadata_table);
+                MetadataStatement::new(&query, &[Type::INT8, Type::TEXT], adapter.unnamed_statements)
+                    .query(&mut transaction, &[&migration.version(), &sql])
+                    .map_err(in_phase(migration, Phase::Record))?;
+            }
+            if let Some(ref sql) = adapter.callbacks.after_each_migrate {
+                transaction.batch_execute(sql).map_err(in_phase(migration, Phase::Up))?;
+            }
+            if let Some(history) = history {
+                let event = HistoryEvent::new(Direction::Apply, migration.version(), started, None);
+                history.query(&mut transaction, &event.params()).map_err(in_phase(migration, Phase::Record))?;
+            }
+            if let Some(ref channel) = adapter.notify_channel {
+                let payload = migration.version().to_string();
+                MetadataStatement::new("SELECT pg_notify($1, $2);", &[Type::TEXT, Type::TEXT], adapter.unnamed_statements)
+                    .query(&mut transaction, &[channel, &payload])
+                    .map_err(in_phase(migration, Phase::Record))?;
+            }
+            transaction.commit().map_err(in_phase(migration, Phase::Record))
+        }));
+        logging::finished("applied", migration, started, &result);
+        if let Err(ref error) = result {
+            self.record_failure(Direction::Apply, migration.version(), started, error);
+        }
+        let status = match result {
+            Ok(()) => ProgressStatus::Finished(started.elapsed()),
+            Err(_) => ProgressStatus::Failed(started.elapsed()),
+        };
+        self.report_progress(Direction::Apply, status, migration.version(), &description);
+        if result.is_ok() {
+            for view in migration.refreshes_materialized_views() {
+                if !self.pending_refreshes.iter().any(|pending| pending == view) {
+                    self.pending_refreshes.push((*view).to_owned());
+                }
+            }
+            for hooks in &mut self.hooks {
+                hooks.after_apply(migration.version(), &description);
+            }
+        }
+
+        result
+    }
+
+    fn revert_migration(&mut self, migration: &dyn PostgresMigration<A>) -> Result<(), SchemamamaPostgresError> {
+        if !self.selects(migration) {
+            logging::passed_over(migration);
+            return Ok(());
+        }
+        if let Some(ref mut dry_run) = self.dry_run {
+            dry_run.push_str(&script::revert_script(migration, &self.metadata_table));
+            return Ok(());
+        }
+        if self.guard_destructive && !self.allow_destructive {
+            return Err(SchemamamaPostgresError::DestructiveNotAllowed { version: migration.version() });
+        }
+        if self.skips(migration) {
+            self.invalidate_cached_versions();
+            // Only versions that were actually skipped go without running `down`.
+            let query = format!("DELETE FROM {} WHERE version = $1 AND skipped RETURNING version;", self.metadata_table);
+            let erased = self.metadata_query(&query, &[Type::INT8], &[&migration.version()]).map_err(in_phase(migration, Phase::Record))?;
+            if !erased.is_empty() {
+                return Ok(());
+            }
+        }
+
+        logging::starting("reverting", migration);
+        let description = migration.description();
+        for hooks in &mut self.hooks {
+            hooks.before_revert(migration.version(), &description);
+        }
+        self.report_progress(Direction::Revert, ProgressStatus::Started, migration.version(), &description);
+
+        let started = Instant::now();
+        let result = self.retrying(Direction::Revert, migration, |adapter| adapter.locked(|adapter| {
+            adapter.check_clean()?;
+            let settings = adapter.settings_for(migration);
+            if !migration.run_in_transaction() {
+                if adapter.client.client().is_none() {
+                    return Err(SchemamamaPostgresError::NonTransactionalInBatch { version: migration.version() });
+                }
+                let query = format!("UPDATE {} SET dirty = true WHERE version = $1;", adapter.metadata_table);
+                adapter.metadata_query(&query, &[Type::INT8], &[&migration.version()]).map_err(in_phase(migration, Phase::Record))?;
+
+                let client = adapter.client.client().expect("checked above");
+                let previous = settings.apply(client, false).map_err(in_phase(migration, Phase::Down))?;
+                let mut context = MigrationContext::new(
+                    &mut *client,
+                    adapter.metadata_table.schema.as_deref(),
+                    adapter.environment.as_deref(),
+                    &adapter.placeholders,
+                    adapter.app_context,
+                    migration.version()
+                );
+                let result = migration.down_without_transaction_ctx(&mut context);
+                let failed = context.failed_statement();
+                let restored = settings.restore(client, previous, false);
+                if let Err(error) = result.and(restored) {
+                    let _ = adapter.clear_marker(migration.version(), false);
+                    return Err(in_statement(migration, Phase::Down, failed)(error));
+                }
+            }
+
+            let erase = adapter.erase_statement().map_err(in_phase(migration, Phase::Record))?;
+            let history = adapter.history_statement().transpose().map_err(in_phase(migration, Phase::Record))?;
+            let mut transaction = adapter.client.transaction(adapter.isolation_level).map_err(in_phase(migration, Phase::Down))?;
+            if migration.run_in_transaction() {
+                let previous = settings.apply(&mut transaction, true).map_err(in_phase(migration, Phase::Down))?;
+                let mut context = MigrationContext::new(
+                    &mut transaction,
+                    adapter.metadata_table.schema.as_deref(),
+                    adapter.environment.as_deref(),
+                    &adapter.placeholders,
+                    adapter.app_context,
+                    migration.version()
+                );
+                let result = migration.down_ctx(&mut context);
+                result.map_err(in_statement(migration, Phase::Down, context.failed_statement()))?;
+                // Restore the settings before touching the metadata table, whose name may depend on
+                // the `search_path`. In a savepoint of an outer transaction, they would otherwise
+                // outlive the migration.
+                settings.restore(&mut transaction, previous, true).map_err(in_phase(migration, Phase::Down))?;
+            }
+            erase_version(&mut transaction, &erase, migration.version()).map_err(in_phase(migration, Phase::Record))?;
+            if let Some(history) = history {
+                let event = HistoryEvent::new(Direction::Revert, migration.version(), started, None);
+                history.query(&mut transaction, &event.params()).map_err(in_phase(migration, Phase::Record))?;
+            }
+            if let Some(ref channel) = adapter.notify_channel {
+                let payload = migration.version().to_string();
+                MetadataStatement::new("SELECT pg_notify($1, $2);", &[Type::TEXT, Type::TEXT], adapter.unnamed_statements)
+                    .query(&mut transaction, &[channel, &payload])
+                    .map_err(in_phase(migration, Phase::Record))?;
+            }
+            transaction.commit().map_err(in_phase(migration, Phase::Record))
+        }));
+        logging::finished("reverted", migration, started, &result);
+        if let Err(ref error) = result {
+            self.record_failure(Direction::Revert, migration.version(), started, error);
+        }
+        let status = match result {
+            Ok(()) => ProgressStatus::Finished(started.elapsed()),
+            Err(_) => ProgressStatus::Failed(started.elapsed()),
+        };
+        self.report_progress(Direction::Revert, status, migration.version(), &description);
+        if result.is_ok() {
+            for hooks in &mut self.hooks {
+                hooks.after_revert(migration.version(), &description);
+            }
+        }
+
+        result
+    }
 }
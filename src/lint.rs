@@ -0,0 +1,118 @@
+//! A pre-flight check of migration SQL for statements that lock large tables against reads and
+//! writes for as long as they run.
+
+use postgres::types::Type;
+use schemamama::{Adapter, Migration, Version};
+
+use crate::{logging, split_statements, MigrationClient, PostgresAdapter, PostgresMigration, SchemamamaPostgresError};
+
+/// A statement of a pending migration that takes an `ACCESS EXCLUSIVE` lock on a large table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockWarning {
+    /// The version of the migration containing the statement.
+    pub version: Version,
+    /// The offending statement, as written in the migration.
+    pub statement: String,
+    /// The locked table, as written in the statement.
+    pub table: String,
+    /// The number of rows in the table, as estimated by the planner's statistics.
+    pub estimated_rows: i64,
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Inspect the SQL of the pending migrations among `migrations` for statements taking an
+    /// `ACCESS EXCLUSIVE` lock, which blocks even reads, on a table the planner estimates to hold at
+    /// least `min_rows` rows. Such statements usually scan or rewrite the whole table while holding
+    /// the lock. Each one found is returned, and logged as a warning with the `logging` feature.
+    ///
+    /// The check recognizes `ALTER TABLE` (except `VALIDATE CONSTRAINT`), `DROP TABLE`, `TRUNCATE`,
+    /// `LOCK TABLE`, `CLUSTER`, `VACUUM FULL` and `REFRESH MATERIALIZED VIEW` without
+    /// `CONCURRENTLY`. Migrations implemented in Rust have no SQL to inspect and are skipped, as are
+    /// tables that do not exist yet.
+    pub fn lint_locks<'m, I>(&mut self, migrations: I, min_rows: i64) -> Result<Vec<LockWarning>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let migrated = self.migrated_versions()?;
+        let mut pending: Vec<&dyn PostgresMigration<A>> = migrations.into_iter()
+            .filter(|migration| !migrated.contains(&migration.version()))
+            .collect();
+        pending.sort_by_key(|migration| migration.version());
+
+        let statement = self.prepare_cached(
+            "SELECT greatest(reltuples, 0)::BIGINT FROM pg_class WHERE oid = to_regclass($1);".to_owned(),
+            &[Type::TEXT]
+        )?;
+        let mut warnings = vec![];
+        for migration in pending {
+            let sql = match migration.up_sql() {
+                Some(sql) => sql,
+                None => continue,
+            };
+
+            for (statement_sql, table) in access_exclusive_statements(sql) {
+                let rows = self.client.query_metadata(&statement, &[&table])?;
+                let estimated_rows: i64 = match rows.first() {
+                    Some(row) => row.get(0),
+                    None => continue,
+                };
+
+                if estimated_rows >= min_rows {
+                    let warning = LockWarning {
+                        version: migration.version(),
+                        statement: statement_sql.to_owned(),
+                        table,
+                        estimated_rows,
+                    };
+                    logging::dangerous_lock(&warning);
+                    warnings.push(warning);
+                }
+            }
+        }
+
+        Ok(warnings)
+    }
+}
+
+/// Finds the statements of `sql` that take an `ACCESS EXCLUSIVE` lock on a table, along with the
+/// name of that table.
+fn access_exclusive_statements(sql: &str) -> Vec<(&str, String)> {
+    let mut found = vec![];
+
+    for statement in split_statements(sql) {
+        let words: Vec<String> = statement.split_whitespace().map(|word| word.to_uppercase()).collect();
+        let words: Vec<&str> = words.iter().map(|word| &**word).collect();
+
+        let position = match words.as_slice() {
+            ["ALTER", "TABLE", ..] if !contains(&words, &["VALIDATE", "CONSTRAINT"]) => 2,
+            ["DROP", "TABLE", ..] | ["LOCK", "TABLE", ..] | ["VACUUM", "FULL", ..] => 2,
+            ["TRUNCATE", "TABLE", ..] => 2,
+            ["TRUNCATE", ..] | ["LOCK", ..] | ["CLUSTER", ..] => 1,
+            ["REFRESH", "MATERIALIZED", "VIEW", next, ..] if *next != "CONCURRENTLY" => 3,
+            _ => continue,
+        };
+
+        // `LOCK` in a weaker mode than the default `ACCESS EXCLUSIVE` is harmless here.
+        if words[0] == "LOCK" && contains(&words, &["IN"]) && !contains(&words, &["ACCESS", "EXCLUSIVE"]) {
+            continue;
+        }
+
+        let original: Vec<&str> = statement.split_whitespace().collect();
+        let name = original[position..].iter()
+            .zip(&words[position..])
+            .find(|&(_, word)| !matches!(*word, "IF" | "EXISTS" | "ONLY"))
+            .map(|(name, _)| name.trim_end_matches(|c| c == ',' || c == '(' || c == '*'));
+
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            found.push((statement, name.to_owned()));
+        }
+    }
+
+    found
+}
+
+/// Whether `words` contains the consecutive words of `needle`.
+fn contains(words: &[&str], needle: &[&str]) -> bool {
+    words.windows(needle.len()).any(|window| window == needle)
+}
@@ -0,0 +1,142 @@
+//! Logging of migration activity through the `log` crate, enabled by the `logging` feature. Without
+//! the feature these functions compile to nothing.
+
+use schemamama::{Migration, Version};
+use std::time::{Duration, Instant};
+
+use crate::{LockWarning, PostgresMigration, SchemamamaPostgresError, StatementError};
+
+/// Logs that `action` (e.g. "applying") is starting for `migration`.
+#[cfg(feature = "logging")]
+pub fn starting<A>(action: &str, migration: &dyn PostgresMigration<A>) {
+    log::debug!("{} migration {} ({})", action, migration.version(), migration.description());
+}
+
+/// Logs the outcome of `action` (e.g. "applied") for `migration`, which started at `started`.
+#[cfg(feature = "logging")]
+pub fn finished<A>(
+    action: &str,
+    migration: &dyn PostgresMigration<A>,
+    started: Instant,
+    result: &Result<(), SchemamamaPostgresError>
+) {
+    match *result {
+        Ok(()) => log::info!(
+            "{} migration {} ({}) in {:?}",
+            action,
+            migration.version(),
+            migration.description(),
+            started.elapsed()
+        ),
+        Err(ref error) => log::error!(
+            "migration {} ({}) was not {} after {:?}: {}",
+            migration.version(),
+            migration.description(),
+            action,
+            started.elapsed(),
+            error
+        ),
+    }
+}
+
+/// Warns that `migration` is being applied although `latest` is already applied.
+#[cfg(feature = "logging")]
+pub fn out_of_order<A>(migration: &dyn PostgresMigration<A>, latest: Version) {
+    log::warn!(
+        "applying migration {} ({}) out of order: migration {} is already applied",
+        migration.version(),
+        migration.description(),
+        latest
+    );
+}
+
+/// Warns that `migration` failed with a transient `error` on attempt `attempt` and will be retried
+/// after `backoff`.
+#[cfg(feature = "logging")]
+pub fn retrying<A>(migration: &dyn PostgresMigration<A>, attempt: u32, error: &SchemamamaPostgresError, backoff: Duration) {
+    log::warn!(
+        "attempt {} of migration {} ({}) failed, retrying in {:?}: {}",
+        attempt,
+        migration.version(),
+        migration.description(),
+        backoff,
+        error
+    );
+}
+
+/// Warns that the connection was lost while running `migration`, and replaced.
+#[cfg(feature = "logging")]
+pub fn reconnected<A>(migration: &dyn PostgresMigration<A>) {
+    log::warn!(
+        "reconnected after losing the connection during migration {} ({})",
+        migration.version(),
+        migration.description()
+    );
+}
+
+/// Logs that `migration` is passed over because of its tags.
+#[cfg(feature = "logging")]
+pub fn passed_over<A>(migration: &dyn PostgresMigration<A>) {
+    log::debug!("passing over migration {} ({}) because of its tags", migration.version(), migration.description());
+}
+
+/// Warns that a statement of a pending migration locks a large table.
+#[cfg(feature = "logging")]
+pub fn dangerous_lock(warning: &LockWarning) {
+    log::warn!(
+        "migration {} locks table {} (about {} rows) against reads and writes: {}",
+        warning.version,
+        warning.table,
+        warning.estimated_rows,
+        warning.statement
+    );
+}
+
+/// Logs the statement of a batch that failed.
+#[cfg(feature = "logging")]
+pub fn statement_failed(error: &StatementError) {
+    log::error!("{}", error);
+}
+
+/// Logs a `message` of a migration, at the warning level if `warning` is set.
+#[cfg(feature = "logging")]
+pub fn migration_message(version: Version, warning: bool, message: &str) {
+    if warning {
+        log::warn!("migration {}: {}", version, message);
+    } else {
+        log::info!("migration {}: {}", version, message);
+    }
+}
+
+#[cfg(not(feature = "logging"))]
+pub fn starting<A>(_action: &str, _migration: &dyn PostgresMigration<A>) {}
+
+#[cfg(not(feature = "logging"))]
+pub fn finished<A>(
+    _action: &str,
+    _migration: &dyn PostgresMigration<A>,
+    _started: Instant,
+    _result: &Result<(), SchemamamaPostgresError>
+) {
+}
+
+#[cfg(not(feature = "logging"))]
+pub fn out_of_order<A>(_migration: &dyn PostgresMigration<A>, _latest: Version) {}
+
+#[cfg(not(feature = "logging"))]
+pub fn retrying<A>(_migration: &dyn PostgresMigration<A>, _attempt: u32, _error: &SchemamamaPostgresError, _backoff: Duration) {}
+
+#[cfg(not(feature = "logging"))]
+pub fn reconnected<A>(_migration: &dyn PostgresMigration<A>) {}
+
+#[cfg(not(feature = "logging"))]
+pub fn passed_over<A>(_migration: &dyn PostgresMigration<A>) {}
+
+#[cfg(not(feature = "logging"))]
+pub fn dangerous_lock(_warning: &LockWarning) {}
+
+#[cfg(not(feature = "logging"))]
+pub fn statement_failed(_error: &StatementError) {}
+
+#[cfg(not(feature = "logging"))]
+pub fn migration_message(_version: Version, _warning: bool, _message: &str) {}
@@ -0,0 +1,111 @@
+use postgres::Client;
+use schemamama::{Adapter, Migration, Version};
+
+use crate::{MigrationClient, PostgresAdapter, PostgresMigration, SchemamamaPostgresError};
+
+/// Applies the same migrations to many schemas of one database, such as one schema per tenant.
+/// Each schema gets its own metadata table, and the `search_path` is set to the schema while its
+/// migrations run (see `PostgresAdapter::with_search_path`), so migrations should not qualify the
+/// objects they create.
+///
+/// ```rust,ignore
+/// let mut runner = MultiSchemaRunner::new(&mut client).with_schemas(vec!["tenant_a", "tenant_b"]);
+/// runner.register(Box::new(CreateUsers));
+/// for outcome in runner.up(None) {
+///     println!("{}: {:?}", outcome.schema, outcome.result);
+/// }
+/// ```
+pub struct MultiSchemaRunner<'a, C: MigrationClient + 'a = Client> {
+    client: &'a mut C,
+    metadata_table: String,
+    schemas: Vec<String>,
+    migrations: Vec<Box<dyn PostgresMigration>>,
+}
+
+/// The outcome of migrating one schema with a `MultiSchemaRunner`.
+#[derive(Debug)]
+pub struct SchemaOutcome {
+    /// The migrated schema.
+    pub schema: String,
+    /// The schema's version after the run, or the error that stopped its migrations.
+    pub result: Result<Option<Version>, SchemamamaPostgresError>,
+}
+
+impl<'a, C: MigrationClient + 'a> MultiSchemaRunner<'a, C> {
+    /// Create a runner without any schema or migration, keeping its metadata tables in tables
+    /// named `schemamama`. Like `PostgresAdapter`, the runner accepts a `Client` or a
+    /// `Transaction`.
+    pub fn new(client: &'a mut C) -> MultiSchemaRunner<'a, C> {
+        MultiSchemaRunner {
+            client,
+            metadata_table: "schemamama".to_owned(),
+            schemas: vec![],
+            migrations: vec![],
+        }
+    }
+
+    /// Name the metadata table created in each schema.
+    pub fn with_metadata_table<T: Into<String>>(mut self, name: T) -> MultiSchemaRunner<'a, C> {
+        self.metadata_table = name.into();
+        self
+    }
+
+    /// Add schemas to migrate, in the order they will be migrated. Schemas that do not exist are
+    /// created.
+    pub fn with_schemas<I>(mut self, schemas: I) -> MultiSchemaRunner<'a, C>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.schemas.extend(schemas.into_iter().map(Into::into));
+        self
+    }
+
+    /// Register a migration to apply to every schema.
+    pub fn register(&mut self, migration: Box<dyn PostgresMigration>) {
+        self.migrations.push(migration);
+    }
+
+    /// Apply the pending migrations of every schema, up to and including version `to` (or all of
+    /// them if `to` is `None`). A failure in one schema does not stop the others; it is reported
+    /// in that schema's outcome.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a schema name is not a valid identifier (see
+    /// `PostgresAdapter::with_metadata_table`).
+    pub fn up(&mut self, to: Option<Version>) -> Vec<SchemaOutcome> {
+        self.migrations.sort_by_key(|migration| migration.version());
+
+        let mut outcomes = Vec::with_capacity(self.schemas.len());
+        for schema in &self.schemas {
+            let result = migrate_schema(&mut *self.client, &self.metadata_table, schema, &self.migrations, to);
+            outcomes.push(SchemaOutcome { schema: schema.clone(), result });
+        }
+
+        outcomes
+    }
+}
+
+fn migrate_schema<C: MigrationClient>(
+    client: &mut C,
+    metadata_table: &str,
+    schema: &str,
+    migrations: &[Box<dyn PostgresMigration>],
+    to: Option<Version>
+) -> Result<Option<Version>, SchemamamaPostgresError> {
+    let mut adapter = PostgresAdapter::with_metadata_table(&mut *client, metadata_table)
+        .with_schema(schema)
+        .with_search_path(vec![schema]);
+    adapter.setup_schema()?;
+
+    let migrated = adapter.migrated_versions()?;
+    for migration in migrations {
+        let version = migration.version();
+        if !migrated.contains(&version) && to.map_or(true, |to| version <= to) {
+            adapter.apply_migration(&**migration)?;
+        }
+    }
+
+    adapter.current_version()
+}
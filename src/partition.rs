@@ -0,0 +1,151 @@
+use postgres::error::Error as PostgresError;
+
+use crate::script::quote_literal;
+use crate::table::{quote_identifier, quote_qualified};
+use crate::MigrationClient;
+
+/// The length of the time range covered by each partition of a `TimePartitions` table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionInterval {
+    /// Partitions named `{table}_p{YYYY}_{MM}_{DD}`.
+    Day,
+    /// ISO weeks, starting on Monday, in partitions named `{table}_p{IYYY}_w{IW}`.
+    Week,
+    /// Partitions named `{table}_p{YYYY}_{MM}`.
+    Month,
+    /// Partitions named `{table}_p{YYYY}`.
+    Year,
+}
+
+impl PartitionInterval {
+    /// The field `date_trunc` aligns partition bounds to.
+    fn unit(self) -> &'static str {
+        match self {
+            PartitionInterval::Day => "day",
+            PartitionInterval::Week => "week",
+            PartitionInterval::Month => "month",
+            PartitionInterval::Year => "year",
+        }
+    }
+
+    /// The `to_char` pattern of partition name suffixes.
+    fn suffix_format(self) -> &'static str {
+        match self {
+            PartitionInterval::Day => "YYYY_MM_DD",
+            PartitionInterval::Week => "IYYY_\"w\"IW",
+            PartitionInterval::Month => "YYYY_MM",
+            PartitionInterval::Year => "YYYY",
+        }
+    }
+}
+
+/// Manages the partitions of a table partitioned by range on a date or timestamp column, with one
+/// partition per day, week, month or year. Every operation can be repeated safely, so it can run
+/// from a migration as well as from a scheduled job.
+///
+/// ```rust,ignore
+/// let partitions = TimePartitions::new("events", PartitionInterval::Month);
+/// partitions.premake(client, 3)?;
+/// partitions.detach_before(client, "2023-01-01")?;
+/// ```
+///
+/// Partitions are created in the schema of the parent table, which may be schema-qualified.
+#[derive(Clone, Debug)]
+pub struct TimePartitions<'q> {
+    parent: &'q str,
+    interval: PartitionInterval,
+}
+
+impl<'q> TimePartitions<'q> {
+    /// Manage the partitions of `parent`, each covering one `interval`.
+    pub fn new(parent: &'q str, interval: PartitionInterval) -> TimePartitions<'q> {
+        TimePartitions { parent, interval }
+    }
+
+    /// The statement creating the partition `name` for the range from `start` (inclusive) to `end`
+    /// (exclusive), both dates such as `2024-01-01`, unless it exists.
+    pub fn create_statement(&self, name: &str, start: &str, end: &str) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} PARTITION OF {} FOR VALUES FROM ({}) TO ({});",
+            quote_qualified(name),
+            quote_qualified(self.parent),
+            quote_literal(start),
+            quote_literal(end)
+        )
+    }
+
+    /// Create the partitions covering the dates from `from` (inclusive) to `to` (exclusive), e.g.
+    /// `2024-01-01` and `2025-01-01`, that do not exist yet. Returns the names of the partitions
+    /// created, oldest first.
+    pub fn create<C: MigrationClient>(&self, client: &mut C, from: &str, to: &str) -> Result<Vec<String>, PostgresError> {
+        let rows = client.query(
+            "SELECT $1::TEXT || '_p' || to_char(s, $2::TEXT), s::DATE::TEXT, (s + ('1 ' || $3::TEXT)::INTERVAL)::DATE::TEXT \
+             FROM generate_series(date_trunc($3::TEXT, $4::TEXT::TIMESTAMP), $5::TEXT::TIMESTAMP, ('1 ' || $3::TEXT)::INTERVAL) s \
+             WHERE s < $5::TEXT::TIMESTAMP;",
+            &[&self.parent, &self.interval.suffix_format(), &self.interval.unit(), &from, &to]
+        )?;
+
+        let mut created = Vec::with_capacity(rows.len());
+        for row in rows {
+            let name: String = row.get(0);
+            let exists = client.query_one("SELECT to_regclass($1::TEXT) IS NOT NULL;", &[&quote_qualified(&name)])?;
+            if exists.get(0) {
+                continue;
+            }
+
+            let statement = self.create_statement(&name, row.get(1), row.get(2));
+            let mut transaction = client.begin(None)?;
+            transaction.batch_execute(&statement)?;
+            transaction.commit()?;
+            created.push(name);
+        }
+
+        Ok(created)
+    }
+
+    /// Create the partition covering today, and those covering the `ahead` following intervals,
+    /// that do not exist yet. Returns the names of the partitions created, oldest first.
+    pub fn premake<C: MigrationClient>(&self, client: &mut C, ahead: u32) -> Result<Vec<String>, PostgresError> {
+        let row = client.query_one(
+            "SELECT current_date::TEXT, \
+             (date_trunc($1::TEXT, current_date::TIMESTAMP) + ($2::TEXT || ' ' || $1::TEXT)::INTERVAL)::DATE::TEXT;",
+            &[&self.interval.unit(), &(i64::from(ahead) + 1).to_string()]
+        )?;
+        let from: String = row.get(0);
+        let to: String = row.get(1);
+        self.create(client, &from, &to)
+    }
+
+    /// Detach the partitions whose range ends on or before `before`, a date such as `2024-01-01`,
+    /// so that they can be archived or dropped without affecting the parent table. Detached
+    /// partitions are kept as standalone tables. Returns their names, oldest first.
+    pub fn detach_before<C: MigrationClient>(&self, client: &mut C, before: &str) -> Result<Vec<String>, PostgresError> {
+        let rows = client.query(
+            "SELECT n.nspname::TEXT, c.relname::TEXT FROM pg_inherits i \
+             JOIN pg_class c ON c.oid = i.inhrelid \
+             JOIN pg_namespace n ON n.oid = c.relnamespace \
+             CROSS JOIN LATERAL substring(pg_get_expr(c.relpartbound, c.oid) FROM 'TO \\(''([^'']*)''\\)') AS bound(upper) \
+             WHERE i.inhparent = to_regclass($1::TEXT) AND bound.upper::TIMESTAMP <= $2::TEXT::DATE \
+             ORDER BY bound.upper::TIMESTAMP;",
+            &[&quote_qualified(self.parent), &before]
+        )?;
+
+        let mut detached = Vec::with_capacity(rows.len());
+        for row in rows {
+            let schema: String = row.get(0);
+            let name: String = row.get(1);
+            let statement = format!(
+                "ALTER TABLE {} DETACH PARTITION {}.{};",
+                quote_qualified(self.parent),
+                quote_identifier(&schema),
+                quote_identifier(&name)
+            );
+            let mut transaction = client.begin(None)?;
+            transaction.batch_execute(&statement)?;
+            transaction.commit()?;
+            detached.push(name);
+        }
+
+        Ok(detached)
+    }
+}
@@ -0,0 +1,67 @@
+use postgres::types::Type;
+use schemamama::{Adapter, Migration, Version};
+
+use crate::shadow::apply_pending;
+use crate::{MigrationClient, PostgresAdapter, PostgresMigration, SchemamamaPostgresError};
+
+/// What `PostgresAdapter::up_or_wait_for_peer` did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeerOutcome {
+    /// This instance held the lock and applied the given versions, if any were pending.
+    Migrated(Vec<Version>),
+    /// Another instance held the lock; this one waited for it and found every migration applied.
+    WaitedForPeer,
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Apply the pending migrations among `migrations`, up to and including version `to` (or all
+    /// of them if `to` is `None`), unless another instance holds the advisory lock of
+    /// `with_advisory_lock`: then wait for it to finish, as configured by `with_lock_behavior`, and
+    /// only check that it applied them. This suits deployments where several replicas start at
+    /// once and only one of them should migrate. If the other instance did not apply them all,
+    /// fails with `SchemamamaPostgresError::PeerIncomplete` rather than applying them itself.
+    /// Migrations passed over by this adapter's tags are neither applied nor expected of the other
+    /// instance.
+    ///
+    /// Without an advisory lock, the migrations are simply applied.
+    pub fn up_or_wait_for_peer<'m, I>(&mut self, migrations: I, to: Option<Version>) -> Result<PeerOutcome, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let mut migrations: Vec<&dyn PostgresMigration<A>> = migrations.into_iter()
+            .filter(|migration| to.map_or(true, |to| migration.version() <= to))
+            .collect();
+        migrations.sort_by_key(|migration| migration.version());
+
+        let key = match self.advisory_lock {
+            Some(key) => key,
+            None => return apply_pending(self, &migrations).map(PeerOutcome::Migrated),
+        };
+
+        let row = self.metadata_query("SELECT pg_try_advisory_lock($1);", &[Type::INT8], &[&key])?;
+        if row[0].get(0) {
+            // Session-level advisory locks nest, so applying each migration takes the lock again.
+            let result = apply_pending(self, &migrations);
+            let released = self.release_lock();
+            return result.and_then(|applied| released.map(|_| PeerOutcome::Migrated(applied)));
+        }
+
+        self.acquire_lock()?;
+        self.release_lock()?;
+
+        // The peer has changed the metadata table since the versions were cached, if they were.
+        self.invalidate_cached_versions();
+        let migrated = self.migrated_versions()?;
+        let pending: Vec<Version> = migrations.iter()
+            .filter(|&&migration| self.selects(migration))
+            .map(|migration| migration.version())
+            .filter(|version| !migrated.contains(version))
+            .collect();
+        if pending.is_empty() {
+            Ok(PeerOutcome::WaitedForPeer)
+        } else {
+            Err(SchemamamaPostgresError::PeerIncomplete { pending })
+        }
+    }
+}
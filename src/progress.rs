@@ -0,0 +1,77 @@
+use schemamama::Version;
+use std::fmt;
+use std::time::Duration;
+
+/// Whether a migration is being applied or reverted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Apply,
+    Revert,
+}
+
+/// The stage of a migration that a `ProgressEvent` reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressStatus {
+    /// The migration is about to run.
+    Started,
+    /// The migration finished successfully after the given duration.
+    Finished(Duration),
+    /// The migration failed after the given duration.
+    Failed(Duration),
+}
+
+/// Reports progress of a migration run to a `ProgressObserver`. Formats as e.g.
+/// `applying migration 3 of 17: 20240101 add users`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// Whether the migration is being applied or reverted.
+    pub direction: Direction,
+    /// How far the migration got.
+    pub status: ProgressStatus,
+    /// The 1-based position of the migration within the current run.
+    pub position: usize,
+    /// The number of migrations in the current run, if announced with
+    /// `PostgresAdapter::set_progress_total`.
+    pub total: Option<usize>,
+    /// The version of the migration.
+    pub version: Version,
+    /// The description of the migration.
+    pub description: String,
+}
+
+impl fmt::Display for ProgressEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verb = match (self.direction, self.status) {
+            (Direction::Apply, ProgressStatus::Started) => "applying",
+            (Direction::Apply, ProgressStatus::Finished(_)) => "applied",
+            (Direction::Apply, ProgressStatus::Failed(_)) => "failed to apply",
+            (Direction::Revert, ProgressStatus::Started) => "reverting",
+            (Direction::Revert, ProgressStatus::Finished(_)) => "reverted",
+            (Direction::Revert, ProgressStatus::Failed(_)) => "failed to revert",
+        };
+
+        write!(f, "{} migration {}", verb, self.position)?;
+        if let Some(total) = self.total {
+            write!(f, " of {}", total)?;
+        }
+        write!(f, ": {} {}", self.version, self.description)?;
+        match self.status {
+            ProgressStatus::Started => Ok(()),
+            ProgressStatus::Finished(duration) | ProgressStatus::Failed(duration) => {
+                write!(f, " ({:?})", duration)
+            }
+        }
+    }
+}
+
+/// Receives `ProgressEvent`s for every migration a `PostgresAdapter` applies or reverts, e.g. to
+/// drive a progress bar.
+pub trait ProgressObserver {
+    fn on_progress(&mut self, event: &ProgressEvent);
+}
+
+impl<F: FnMut(&ProgressEvent)> ProgressObserver for F {
+    fn on_progress(&mut self, event: &ProgressEvent) {
+        self(event)
+    }
+}
@@ -0,0 +1,117 @@
+use postgres::error::Error as PostgresError;
+use postgres::types::{ToSql, Type};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::side_table::SideScript;
+use crate::table::MetadataTable;
+use crate::{sha256_hex, MigrationClient, PostgresAdapter, SchemamamaPostgresError};
+
+/// A migration without a version that is run again whenever its SQL changes, typically to
+/// (re)define views, functions and triggers kept in one canonical file each. Its SQL should
+/// therefore be idempotent, e.g. `CREATE OR REPLACE VIEW`.
+#[derive(Clone, Debug)]
+pub struct RepeatableMigration {
+    name: String,
+    sql: String,
+}
+
+impl RepeatableMigration {
+    /// Create a repeatable migration identified by `name` from SQL text.
+    pub fn new<N, S>(name: N, sql: S) -> RepeatableMigration
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        RepeatableMigration { name: name.into(), sql: sql.into() }
+    }
+
+    /// The name identifying this migration in the metadata.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The SQL run by this migration.
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// The SHA-256 checksum of the SQL, which decides whether the migration runs again.
+    pub fn checksum(&self) -> String {
+        sha256_hex(self.sql.as_bytes())
+    }
+}
+
+/// Discover `R__{name}.sql` files in `directory` and turn each one into a `RepeatableMigration`,
+/// ordered by name. Files not matching the pattern are ignored.
+pub fn load_repeatable_migrations<P: AsRef<Path>>(directory: P) -> io::Result<Vec<RepeatableMigration>> {
+    let mut migrations = BTreeMap::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) if file_name.starts_with("R__") && file_name.ends_with(".sql") => {
+                file_name["R__".len()..file_name.len() - ".sql".len()].to_owned()
+            }
+            _ => continue,
+        };
+
+        let sql = fs::read_to_string(&path)?;
+        migrations.insert(name.clone(), RepeatableMigration::new(name, sql));
+    }
+
+    Ok(migrations.into_iter().map(|(_, migration)| migration).collect())
+}
+
+impl SideScript for RepeatableMigration {
+    type Key = String;
+
+    const SUFFIX: &'static str = "_repeatable";
+    const COLUMNS: &'static [(&'static str, &'static str)] = &[("name", "TEXT")];
+    const TYPES: &'static [Type] = &[Type::TEXT];
+
+    fn key(&self) -> String {
+        self.name.clone()
+    }
+
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![&self.name]
+    }
+
+    fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    fn checksum(&self) -> String {
+        RepeatableMigration::checksum(self)
+    }
+
+    fn failed(&self, error: PostgresError) -> SchemamamaPostgresError {
+        SchemamamaPostgresError::Repeatable { name: self.name.clone(), error }
+    }
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Run each of `migrations` that has never run, or whose checksum changed since it last ran,
+    /// in name order and each in its own transaction. Their checksums are kept in a table named
+    /// after the metadata table with a `_repeatable` suffix, which is created if necessary. Returns
+    /// the names of the migrations that ran. Fails with `SchemamamaPostgresError::SideTableNameTooLong`
+    /// if the suffixed name is longer than the 63 bytes PostgreSQL allows.
+    ///
+    /// Like Flyway's repeatable migrations, these are meant to run after all versioned migrations
+    /// are applied.
+    pub fn apply_repeatable<'m, I>(&mut self, migrations: I) -> Result<Vec<String>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m RepeatableMigration>,
+    {
+        let applied = self.run_side_scripts(migrations, false)?;
+        Ok(applied.into_iter().map(|migration| migration.name().to_owned()).collect())
+    }
+
+    /// The table recording the checksums of repeatable migrations.
+    pub(crate) fn repeatable_table(&self) -> Result<MetadataTable, SchemamamaPostgresError> {
+        self.side_table(RepeatableMigration::SUFFIX)
+    }
+}
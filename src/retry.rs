@@ -0,0 +1,48 @@
+use postgres::error::{Error as PostgresError, SqlState};
+use std::time::Duration;
+
+/// How a `PostgresAdapter` retries a migration that failed with a transient error (see
+/// `is_transient`). The delay before each retry doubles, starting at `initial_backoff` and never
+/// exceeding `max_backoff`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of times a migration is attempted in total, including the first attempt.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+    /// The longest delay between two attempts.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Attempt each migration up to `max_attempts` times, waiting 100ms before the first retry and
+    /// at most 10s between attempts.
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+
+    /// The delay before attempt `attempt + 1`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.checked_mul(factor).map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// Whether `error` is likely to go away if the failed operation is simply tried again: a
+/// serialization failure, a deadlock, a lock that could not be acquired within `lock_timeout`, or
+/// a connection error reported by the server.
+pub fn is_transient(error: &PostgresError) -> bool {
+    match error.code() {
+        Some(code) => {
+            *code == SqlState::T_R_SERIALIZATION_FAILURE
+                || *code == SqlState::T_R_DEADLOCK_DETECTED
+                || *code == SqlState::LOCK_NOT_AVAILABLE
+                || code.code().starts_with("08")
+        }
+        None => false,
+    }
+}
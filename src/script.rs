@@ -0,0 +1,71 @@
+use schemamama::Migration;
+
+use crate::table::MetadataTable;
+use crate::PostgresMigration;
+
+/// Renders the SQL that applying `migration` would execute, followed by the statement recording
+/// its version in `metadata_table`.
+pub fn apply_script<A>(migration: &dyn PostgresMigration<A>, metadata_table: &MetadataTable) -> String {
+    let record = format!(
+        "INSERT INTO {} (version, description, checksum, applied_at, applied_by) \
+         VALUES ({}, {}, {}, now(), current_user);",
+        metadata_table,
+        migration.version(),
+        quote_literal(&migration.description()),
+        checksum_literal(migration)
+    );
+
+    render(migration, "up", migration.up_sql(), &record)
+}
+
+/// Renders the statement recording `migration` as skipped in `metadata_table`, which is all that
+/// applying a skipped migration executes.
+pub fn skip_script<A>(migration: &dyn PostgresMigration<A>, metadata_table: &MetadataTable) -> String {
+    format!(
+        "-- skip {}: {}\nINSERT INTO {} (version, description, checksum, skipped) VALUES ({}, {}, {}, true);\n\n",
+        migration.version(),
+        migration.description(),
+        metadata_table,
+        migration.version(),
+        quote_literal(&migration.description()),
+        checksum_literal(migration)
+    )
+}
+
+/// Renders the SQL that reverting `migration` would execute, followed by the statement erasing
+/// its version from `metadata_table`.
+pub fn revert_script<A>(migration: &dyn PostgresMigration<A>, metadata_table: &MetadataTable) -> String {
+    let erase = format!("DELETE FROM {} WHERE version = {};", metadata_table, migration.version());
+    render(migration, "down", migration.down_sql(), &erase)
+}
+
+fn render<A>(migration: &dyn PostgresMigration<A>, direction: &str, sql: Option<&str>, bookkeeping: &str) -> String {
+    let mut script = format!("-- {} {}: {}\n", direction, migration.version(), migration.description());
+    let body = match sql {
+        Some(sql) => format!("{}\n", sql.trim_end()),
+        None => format!("-- (no SQL representation; `{}` is implemented in Rust)\n", direction),
+    };
+
+    if migration.run_in_transaction() {
+        script.push_str("BEGIN;\n");
+        script.push_str(&body);
+        script.push_str(bookkeeping);
+        script.push_str("\nCOMMIT;\n\n");
+    } else {
+        script.push_str(&body);
+        script.push_str(bookkeeping);
+        script.push_str("\n\n");
+    }
+
+    script
+}
+
+/// The checksum of `migration` as a SQL string literal, or `NULL` if it has none.
+fn checksum_literal<A>(migration: &dyn PostgresMigration<A>) -> String {
+    migration.checksum().as_ref().map_or("NULL".to_owned(), |checksum| quote_literal(checksum))
+}
+
+/// Quotes `value` as a SQL string literal.
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
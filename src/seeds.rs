@@ -0,0 +1,145 @@
+use postgres::error::Error as PostgresError;
+use postgres::types::{ToSql, Type};
+use schemamama::Version;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::side_table::SideScript;
+use crate::table::MetadataTable;
+use crate::{sha256_hex, MigrationClient, PostgresAdapter, SchemamamaPostgresError};
+
+/// A versioned set of reference data, such as countries or permission names, loaded separately
+/// from schema migrations. Seeds are recorded in their own table, so their versions do not mix
+/// with migration versions, and a seed runs again whenever its SQL changes. Its SQL should
+/// therefore be idempotent, e.g. `INSERT ... ON CONFLICT DO UPDATE`.
+#[derive(Clone, Debug)]
+pub struct Seed {
+    version: Version,
+    name: String,
+    sql: String,
+}
+
+impl Seed {
+    /// Create a seed identified by `version` from SQL text.
+    pub fn new<N, S>(version: Version, name: N, sql: S) -> Seed
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        Seed { version, name: name.into(), sql: sql.into() }
+    }
+
+    /// The version identifying this seed in the seeds table.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// A description of this seed.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The SQL run by this seed.
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// The SHA-256 checksum of the SQL, which decides whether the seed runs again.
+    pub fn checksum(&self) -> String {
+        sha256_hex(self.sql.as_bytes())
+    }
+}
+
+/// Discover `S{version}__{name}.sql` files in `directory` and turn each one into a `Seed`, ordered
+/// by version. Files not matching the pattern are ignored.
+pub fn load_seeds<P: AsRef<Path>>(directory: P) -> io::Result<Vec<Seed>> {
+    let mut seeds = BTreeMap::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let (version, name) = match path.file_name().and_then(|name| name.to_str()).and_then(parse_file_name) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        let sql = fs::read_to_string(&path)?;
+        if seeds.insert(version, Seed::new(version, name, sql)).is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("duplicate seed version {}", version)));
+        }
+    }
+
+    Ok(seeds.into_iter().map(|(_, seed)| seed).collect())
+}
+
+/// Splits a file name of the form `S{version}__{name}.sql` into its version and name.
+fn parse_file_name(file_name: &str) -> Option<(Version, String)> {
+    if !file_name.starts_with('S') || !file_name.ends_with(".sql") {
+        return None;
+    }
+
+    let stem = &file_name[1..file_name.len() - ".sql".len()];
+    let separator = stem.find("__")?;
+    let version = stem[..separator].parse().ok()?;
+    Some((version, stem[separator + 2..].to_owned()))
+}
+
+impl SideScript for Seed {
+    type Key = Version;
+
+    const SUFFIX: &'static str = "_seeds";
+    const COLUMNS: &'static [(&'static str, &'static str)] = &[("version", "BIGINT"), ("name", "TEXT")];
+    const TYPES: &'static [Type] = &[Type::INT8, Type::TEXT];
+
+    fn key(&self) -> Version {
+        self.version
+    }
+
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+        vec![&self.version, &self.name]
+    }
+
+    fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    fn checksum(&self) -> String {
+        Seed::checksum(self)
+    }
+
+    fn failed(&self, error: PostgresError) -> SchemamamaPostgresError {
+        SchemamamaPostgresError::Seed { version: self.version, error }
+    }
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Run each of `seeds` that has never run, or whose checksum changed since it last ran, in
+    /// version order and each in its own transaction. Seeds are recorded in a table named after
+    /// the metadata table with a `_seeds` suffix, which is created if necessary, and are
+    /// independent of the migrations recorded in the metadata table. Returns the versions of the
+    /// seeds that ran. Fails with `SchemamamaPostgresError::SideTableNameTooLong` if the suffixed
+    /// name is longer than the 63 bytes PostgreSQL allows.
+    pub fn apply_seeds<'m, I>(&mut self, seeds: I) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m Seed>,
+    {
+        let applied = self.run_side_scripts(seeds, false)?;
+        Ok(applied.into_iter().map(Seed::version).collect())
+    }
+
+    /// Like `apply_seeds`, but run every one of `seeds` again, whether or not it changed, e.g. to
+    /// restore reference data that was edited by hand.
+    pub fn rerun_seeds<'m, I>(&mut self, seeds: I) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m Seed>,
+    {
+        let applied = self.run_side_scripts(seeds, true)?;
+        Ok(applied.into_iter().map(Seed::version).collect())
+    }
+
+    /// The table recording the seeds that ran.
+    pub(crate) fn seeds_table(&self) -> Result<MetadataTable, SchemamamaPostgresError> {
+        self.side_table(Seed::SUFFIX)
+    }
+}
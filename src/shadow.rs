@@ -0,0 +1,77 @@
+use schemamama::{Adapter, Migration, Version};
+
+use crate::client::ClientHandle;
+use crate::{MigrationClient, PostgresAdapter, PostgresMigration, SchemamamaPostgresError};
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Apply the pending migrations among `migrations`, up to and including version `to` (or all
+    /// of them if `to` is `None`), after first applying them to the shadow database `shadow`. If
+    /// the shadow run fails, with `SchemamamaPostgresError::Shadow`, nothing is applied to this
+    /// adapter's database, so syntax and dependency errors are caught before they reach it.
+    /// Returns the versions applied to this adapter's database.
+    ///
+    /// The shadow database should mirror this one, e.g. a copy of production, or start out empty:
+    /// migrations pending there are applied from the start. A fresh connection with
+    /// `search_path TO pg_temp` makes a throwaway shadow for migrations that do not qualify their
+    /// names with a schema. The shadow uses a metadata table of the same name, and the same
+    /// selection of migrations (skipped versions, environment and tags), placeholders, session
+    /// settings and SQL callbacks as this adapter, so that it runs what this adapter would run, the
+    /// way it would run it.
+    pub fn up_with_shadow<'m, I, S>(
+        &mut self,
+        shadow: &mut S,
+        migrations: I,
+        to: Option<Version>
+    ) -> Result<Vec<Version>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+        S: MigrationClient,
+    {
+        let mut migrations: Vec<&dyn PostgresMigration<A>> = migrations.into_iter()
+            .filter(|migration| to.map_or(true, |to| migration.version() <= to))
+            .collect();
+        migrations.sort_by_key(|migration| migration.version());
+
+        let mut shadow = PostgresAdapter::from_handle(ClientHandle::Borrowed(shadow), self.metadata_table.clone())
+            .with_app_context(self.app_context);
+        shadow.skipped_versions = self.skipped_versions.clone();
+        shadow.environment = self.environment.clone();
+        shadow.placeholders = self.placeholders.clone();
+        shadow.included_tags = self.included_tags.clone();
+        shadow.excluded_tags = self.excluded_tags.clone();
+        shadow.statement_timeout = self.statement_timeout;
+        shadow.lock_timeout = self.lock_timeout;
+        shadow.isolation_level = self.isolation_level;
+        shadow.search_path = self.search_path.clone();
+        shadow.role = self.role.clone();
+        shadow.keepalive = self.keepalive;
+        shadow.unnamed_statements = self.unnamed_statements;
+        shadow.out_of_order = self.out_of_order;
+        shadow.record_conflict = self.record_conflict;
+        shadow.callbacks = self.callbacks.clone();
+        apply_pending(&mut shadow, &migrations).map_err(|error| SchemamamaPostgresError::Shadow(Box::new(error)))?;
+
+        apply_pending(self, &migrations)
+    }
+}
+
+/// Applies the migrations among `migrations`, which are sorted by version, that `adapter` has not
+/// applied yet and selects by its tags, returning their versions.
+pub(crate) fn apply_pending<'a, C: MigrationClient + 'a, A: 'a>(
+    adapter: &mut PostgresAdapter<'a, C, A>,
+    migrations: &[&dyn PostgresMigration<A>]
+) -> Result<Vec<Version>, SchemamamaPostgresError> {
+    adapter.setup_schema()?;
+    let migrated = adapter.migrated_versions()?;
+
+    let mut applied = vec![];
+    for &migration in migrations {
+        if !migrated.contains(&migration.version()) && adapter.selects(migration) {
+            adapter.apply_migration(migration)?;
+            applied.push(migration.version());
+        }
+    }
+
+    Ok(applied)
+}
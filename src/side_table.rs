@@ -0,0 +1,115 @@
+use postgres::error::Error as PostgresError;
+use postgres::types::{FromSql, ToSql, Type};
+use std::collections::BTreeMap;
+
+use crate::client::MetadataStatement;
+use crate::table::MetadataTable;
+use crate::{MigrationClient, PostgresAdapter, SchemamamaPostgresError};
+
+/// SQL kept apart from the versioned migrations, such as a seed or a repeatable migration, which
+/// runs again whenever its checksum changes. Scripts of a kind are recorded in a table of their own
+/// beside the metadata table (see `PostgresAdapter::side_table`).
+pub(crate) trait SideScript {
+    /// The value identifying a script in its table.
+    type Key: Ord + for<'r> FromSql<'r>;
+
+    /// Appended to the name of the metadata table to name the table of these scripts.
+    const SUFFIX: &'static str;
+    /// The names and SQL types of the columns identifying a script, its key first.
+    const COLUMNS: &'static [(&'static str, &'static str)];
+    /// The types of `COLUMNS`, as bound to the statement recording a script.
+    const TYPES: &'static [Type];
+
+    /// The key of this script.
+    fn key(&self) -> Self::Key;
+
+    /// The values of `COLUMNS` for this script.
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)>;
+
+    /// The SQL run by this script.
+    fn sql(&self) -> &str;
+
+    /// The checksum of the SQL, which decides whether the script runs again.
+    fn checksum(&self) -> String;
+
+    /// Wraps an `error` that occurred while running this script.
+    fn failed(&self, error: PostgresError) -> SchemamamaPostgresError;
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// The table named after the metadata table with `suffix`, in the same schema. Fails if the
+    /// name is too long to be a PostgreSQL identifier, which would otherwise be truncated
+    /// silently, possibly into the name of another table.
+    pub(crate) fn side_table(&self, suffix: &str) -> Result<MetadataTable, SchemamamaPostgresError> {
+        self.metadata_table.with_suffix(suffix).ok_or_else(|| SchemamamaPostgresError::SideTableNameTooLong {
+            name: format!("{}{}", self.metadata_table.name, suffix),
+        })
+    }
+
+    /// Run each of `scripts` that has never run, or whose checksum changed since it last ran (or
+    /// all of them if `force` is set), in key order and each in its own transaction, recording
+    /// them in their side table, which is created if necessary. Returns the scripts that ran.
+    pub(crate) fn run_side_scripts<'m, S, I>(&mut self, scripts: I, force: bool) -> Result<Vec<&'m S>, SchemamamaPostgresError>
+    where
+        S: SideScript + 'm,
+        I: IntoIterator<Item = &'m S>,
+    {
+        let table = self.side_table(S::SUFFIX)?;
+        let key = S::COLUMNS[0].0;
+        let definitions: Vec<String> = S::COLUMNS.iter().enumerate()
+            .map(|(i, &(name, sql_type))| format!("{} {} {}", name, sql_type, if i == 0 { "PRIMARY KEY" } else { "NOT NULL" }))
+            .collect();
+        let query = format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}, checksum TEXT NOT NULL, \
+             applied_at TIMESTAMPTZ NOT NULL DEFAULT now(), applied_by TEXT NOT NULL DEFAULT current_user);",
+            table,
+            definitions.join(", ")
+        );
+        self.metadata_query(&query, &[], &[]).map_err(SchemamamaPostgresError::Setup)?;
+
+        let query = format!("SELECT {}, checksum FROM {};", key, table);
+        let stored: BTreeMap<S::Key, String> = self.metadata_query(&query, &[], &[])?
+            .iter()
+            .map(|r| (r.get(0), r.get(1)))
+            .collect();
+
+        let mut pending: Vec<&S> = scripts.into_iter()
+            .filter(|script| force || stored.get(&script.key()) != Some(&script.checksum()))
+            .collect();
+        pending.sort_by_key(|script| script.key());
+
+        let names: Vec<&str> = S::COLUMNS.iter().map(|&(name, _)| name).collect();
+        let params: Vec<String> = (1..=names.len() + 1).map(|i| format!("${}", i)).collect();
+        let updates: String = names[1..].iter().map(|name| format!("{0} = excluded.{0}, ", name)).collect();
+        let record = format!(
+            "INSERT INTO {} ({}, checksum) VALUES ({}) ON CONFLICT ({}) DO UPDATE \
+             SET {}checksum = excluded.checksum, applied_at = now(), applied_by = current_user;",
+            table,
+            names.join(", "),
+            params.join(", "),
+            key,
+            updates
+        );
+        let mut types = S::TYPES.to_vec();
+        types.push(Type::TEXT);
+        let record = self.prepare_cached(record, &types)?;
+        let mut applied = Vec::with_capacity(pending.len());
+        for script in pending {
+            self.locked(|adapter| adapter.run_side_script(script, &record).map_err(|error| script.failed(error)))?;
+            applied.push(script);
+        }
+
+        Ok(applied)
+    }
+
+    /// Runs `script` and records its checksum with the `record` statement, in one transaction.
+    fn run_side_script<S: SideScript>(&mut self, script: &S, record: &MetadataStatement) -> Result<(), PostgresError> {
+        let mut transaction = self.client.transaction(self.isolation_level)?;
+        transaction.batch_execute(script.sql())?;
+        let checksum = script.checksum();
+        let mut params = script.values();
+        params.push(&checksum);
+        record.query(&mut transaction, &params)?;
+        transaction.commit()
+    }
+}
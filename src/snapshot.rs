@@ -0,0 +1,108 @@
+use postgres::types::Type;
+use schemamama::{Adapter, Migration, Version};
+use std::collections::BTreeMap;
+
+use crate::{MigrationClient, PostgresAdapter, PostgresMigration, SchemamamaPostgresError};
+
+/// A migration whose `down` step does not restore the schema `up` started from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IrreversibleMigration {
+    /// The version of the migration.
+    pub version: Version,
+    /// The schema snapshot taken before applying the migration.
+    pub expected: String,
+    /// The schema snapshot taken after applying and reverting the migration.
+    pub actual: String,
+}
+
+/// The tables of the current schema, other than the adapter's own tables, listed in `$1`.
+const TABLES: &str = "
+    SELECT c.oid, c.relname FROM pg_class c
+    WHERE c.relnamespace = (SELECT oid FROM pg_namespace WHERE nspname = current_schema())
+      AND c.relkind IN ('r', 'p')
+      AND c.oid NOT IN (SELECT to_regclass(t) FROM unnest($1::TEXT[]) t WHERE to_regclass(t) IS NOT NULL)";
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Describe the tables of the current schema (the first schema of the search path that
+    /// exists) in a deterministic text form suited to comparing the migrated schema against a
+    /// checked-in golden file. Each table lists its columns in order, then its constraints and
+    /// indexes by name. The adapter's own tables (metadata, history and repeatable migrations) are
+    /// left out, and names are not qualified by the schema, so the snapshot does not depend on the
+    /// schema it was taken in.
+    pub fn schema_snapshot(&mut self) -> Result<String, SchemamamaPostgresError> {
+        let query = format!(
+            "WITH t AS ({tables})
+             SELECT t.relname, 1, a.attnum::INT, a.attname::TEXT,
+                    concat_ws(' ', 'column', a.attname, format_type(a.atttypid, a.atttypmod),
+                              CASE WHEN a.attnotnull THEN 'NOT NULL' END,
+                              'DEFAULT ' || pg_get_expr(d.adbin, d.adrelid))
+             FROM t JOIN pg_attribute a ON a.attrelid = t.oid
+             LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+             WHERE a.attnum > 0 AND NOT a.attisdropped
+             UNION ALL
+             SELECT t.relname, 2, 0, con.conname::TEXT,
+                    concat_ws(' ', 'constraint', con.conname, pg_get_constraintdef(con.oid))
+             FROM t JOIN pg_constraint con ON con.conrelid = t.oid
+             UNION ALL
+             SELECT t.relname, 3, 0, i.relname::TEXT,
+                    concat_ws(' ', 'index', replace(pg_get_indexdef(i.oid),
+                                                    quote_ident(current_schema()) || '.', ''))
+             FROM t JOIN pg_index x ON x.indrelid = t.oid JOIN pg_class i ON i.oid = x.indexrelid
+             ORDER BY 1, 2, 3, 4 COLLATE \"C\";",
+            tables = TABLES
+        );
+        let mut own_tables = vec![self.metadata_table.to_string()];
+        own_tables.extend(self.repeatable_table().ok().map(|table| table.to_string()));
+        own_tables.extend(self.seeds_table().ok().map(|table| table.to_string()));
+        own_tables.extend(self.history_table.as_ref().map(ToString::to_string));
+        let rows = self.metadata_query(&query, &[Type::TEXT_ARRAY], &[&own_tables])?;
+
+        let mut tables: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for row in rows {
+            tables.entry(row.get(0)).or_insert_with(Vec::new).push(row.get(4));
+        }
+
+        let mut snapshot = String::new();
+        for (table, lines) in tables {
+            snapshot.push_str(&format!("table {}\n", table));
+            for line in lines {
+                snapshot.push_str(&format!("  {}\n", line));
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Check that every pending migration among `migrations` can be reverted: in version order,
+    /// each one is applied, reverted, and applied again to set up the next, comparing the schema
+    /// snapshot (see `schema_snapshot`) taken after reverting it with the one taken before applying
+    /// it. Returns the migrations whose snapshots differ, leaving all of them applied.
+    ///
+    /// Migrations that are already applied are skipped. Run against a scratch database, such as the
+    /// `pg_temp` schema of a test connection.
+    pub fn verify_reversibility<'m, I>(&mut self, migrations: I) -> Result<Vec<IrreversibleMigration>, SchemamamaPostgresError>
+    where
+        I: IntoIterator<Item = &'m dyn PostgresMigration<A>>,
+        A: 'm,
+    {
+        let migrated = self.migrated_versions()?;
+        let mut pending: Vec<&dyn PostgresMigration<A>> = migrations.into_iter()
+            .filter(|migration| !migrated.contains(&migration.version()))
+            .collect();
+        pending.sort_by_key(|migration| migration.version());
+
+        let mut irreversible = vec![];
+        for migration in pending {
+            let expected = self.schema_snapshot()?;
+            self.apply_migration(migration)?;
+            self.revert_migration(migration)?;
+            let actual = self.schema_snapshot()?;
+            self.apply_migration(migration)?;
+
+            if actual != expected {
+                irreversible.push(IrreversibleMigration { version: migration.version(), expected, actual });
+            }
+        }
+
+        Ok(irreversible)
+    }
+}
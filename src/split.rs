@@ -0,0 +1,127 @@
+//! Splitting of SQL scripts into their statements, for the parts of the crate that inspect or run
+//! migration SQL one statement at a time.
+
+/// Split `sql` into its statements, without their terminating semicolons. Semicolons inside
+/// string literals (including `E'...'` escape strings), quoted identifiers, dollar-quoted bodies
+/// such as PL/pgSQL functions, and comments do not end a statement. Comments and whitespace before
+/// a statement are not part of it, and statements made up of nothing else are dropped.
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = vec![];
+    let mut start = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = sql[i..].find('\n').map_or(bytes.len(), |end| i + end + 1);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = end_of_block_comment(bytes, i);
+                continue;
+            }
+            b';' => {
+                if let Some(start) = start.take() {
+                    statements.push(sql[start..i].trim_end());
+                }
+                i += 1;
+                continue;
+            }
+            byte if byte.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if start.is_none() {
+            start = Some(i);
+        }
+        i = match bytes[i] {
+            b'\'' => {
+                let escapes = i > 0 && (bytes[i - 1] == b'E' || bytes[i - 1] == b'e')
+                    && (i < 2 || !is_identifier_byte(bytes[i - 2]));
+                end_of_quoted(bytes, i, b'\'', escapes)
+            }
+            b'"' => end_of_quoted(bytes, i, b'"', false),
+            b'$' if i == 0 || !is_identifier_byte(bytes[i - 1]) => match dollar_tag(sql, i) {
+                Some(tag) => {
+                    let body = i + tag.len();
+                    sql[body..].find(tag).map_or(bytes.len(), |end| body + end + tag.len())
+                }
+                None => i + 1,
+            },
+            _ => i + 1,
+        };
+    }
+
+    if let Some(start) = start {
+        statements.push(sql[start..].trim_end());
+    }
+    statements
+}
+
+/// Returns the position after the quote closing the literal or identifier opened at `start`. A
+/// doubled quote stands for the quote itself, as does a backslash-escaped one if `escapes` is set.
+fn end_of_quoted(bytes: &[u8], start: usize, quote: u8, escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the position after the end of the block comment opened at `start`. Block comments nest.
+fn end_of_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Returns the dollar-quote tag (such as `$$` or `$body$`) starting at `start`, if any. Positional
+/// parameters such as `$1` are not tags.
+fn dollar_tag(sql: &str, start: usize) -> Option<&str> {
+    let bytes = sql.as_bytes();
+    let mut end = start + 1;
+    while end < bytes.len() && is_identifier_byte(bytes[end]) && bytes[end] != b'$' {
+        end += 1;
+    }
+
+    let starts_with_digit = end > start + 1 && bytes[start + 1].is_ascii_digit();
+    if end < bytes.len() && bytes[end] == b'$' && !starts_with_digit {
+        Some(&sql[start..=end])
+    } else {
+        None
+    }
+}
+
+/// Whether `byte` can be part of an unquoted identifier. Non-ASCII bytes are, as PostgreSQL allows
+/// letters outside of ASCII in identifiers.
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || !byte.is_ascii()
+}
@@ -0,0 +1,324 @@
+use postgres::error::Error as PostgresError;
+use postgres::{Client, Transaction};
+use schemamama::{Adapter, Migration, Migrator, Version};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::includes::read_with_includes;
+use crate::{split_statements, ChecksumAlgorithm, PostgresMigration};
+
+/// A migration whose `up` and `down` steps are plain SQL, typically loaded from files by
+/// `load_sql_migrations`.
+#[derive(Clone, Debug)]
+pub struct SqlMigration {
+    version: Version,
+    description: String,
+    up: String,
+    down: Option<String>,
+    checksum_algorithm: ChecksumAlgorithm,
+}
+
+impl SqlMigration {
+    /// Create a migration from SQL text. A `None` down step makes reverting this migration a no-op.
+    pub fn new<D, U>(version: Version, description: D, up: U, down: Option<String>) -> SqlMigration
+    where
+        D: Into<String>,
+        U: Into<String>,
+    {
+        SqlMigration {
+            version,
+            description: description.into(),
+            up: up.into(),
+            down,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+        }
+    }
+
+    /// Compute this migration's checksum with `algorithm` instead of SHA-256, e.g. to keep
+    /// verifying the checksums recorded by Flyway (see `ChecksumAlgorithm::Crc32`).
+    pub fn with_checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> SqlMigration {
+        self.checksum_algorithm = algorithm;
+        self
+    }
+
+    /// The statements of the `up` step, as split by `split_statements`.
+    pub fn up_statements(&self) -> Vec<&str> {
+        split_statements(&self.up)
+    }
+
+    /// The statements of the `down` step, as split by `split_statements`.
+    pub fn down_statements(&self) -> Vec<&str> {
+        self.down.as_ref().map_or(vec![], |down| split_statements(down))
+    }
+
+    // `SqlMigration` implements `PostgresMigration` for every application context, which makes
+    // calls to the trait's methods ambiguous; these inherent methods answer the common ones.
+
+    /// The SQL of the `up` step (see `PostgresMigration::up_sql`).
+    pub fn up_sql(&self) -> Option<&str> {
+        Some(&self.up)
+    }
+
+    /// The SQL of the `down` step, if any (see `PostgresMigration::down_sql`).
+    pub fn down_sql(&self) -> Option<&str> {
+        self.down.as_ref().map(|down| &**down)
+    }
+
+    /// The checksum of the `up` step, computed with the migration's checksum algorithm (see
+    /// `PostgresMigration::checksum`).
+    pub fn checksum(&self) -> Option<String> {
+        Some(self.checksum_algorithm.checksum(&self.up))
+    }
+
+    /// Whether the `up` step contains an `ALTER TYPE ... ADD VALUE` statement, in which case the
+    /// migration runs its statements one at a time, outside of a transaction.
+    pub fn alters_enums(&self) -> bool {
+        self.up_statements().iter().any(|statement| {
+            let words: Vec<String> = statement.split_whitespace().take(6).map(str::to_uppercase).collect();
+            words.starts_with(&["ALTER".to_owned(), "TYPE".to_owned()])
+                && words.windows(2).any(|pair| pair[0] == "ADD" && pair[1] == "VALUE")
+        })
+    }
+
+    /// Whether the migration runs in a transaction (see `PostgresMigration::run_in_transaction`).
+    pub fn run_in_transaction(&self) -> bool {
+        !self.alters_enums()
+    }
+}
+
+impl Migration for SqlMigration {
+    fn version(&self) -> Version {
+        self.version
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+}
+
+impl<X> PostgresMigration<X> for SqlMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute(&self.up)
+    }
+
+    fn down(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        match self.down {
+            Some(ref down) => transaction.batch_execute(down),
+            None => Ok(()),
+        }
+    }
+
+    fn up_sql(&self) -> Option<&str> {
+        SqlMigration::up_sql(self)
+    }
+
+    fn down_sql(&self) -> Option<&str> {
+        SqlMigration::down_sql(self)
+    }
+
+    fn checksum(&self) -> Option<String> {
+        SqlMigration::checksum(self)
+    }
+
+    fn alters_enums(&self) -> bool {
+        SqlMigration::alters_enums(self)
+    }
+
+    fn up_without_transaction(&self, client: &mut Client) -> Result<(), PostgresError> {
+        for statement in self.up_statements() {
+            client.batch_execute(statement)?;
+        }
+        Ok(())
+    }
+
+    fn down_without_transaction(&self, client: &mut Client) -> Result<(), PostgresError> {
+        for statement in self.down_statements() {
+            client.batch_execute(statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// Discover `V{version}__{name}.up.sql` and `V{version}__{name}.down.sql` files in `directory`
+/// and turn each pair into a `SqlMigration`, ordered by version. The down file is optional; files
+/// not matching the pattern are ignored.
+///
+/// A line of the form `-- include: shared/functions.sql` is replaced by the contents of that file,
+/// resolved relative to `directory`, so that common fragments need not be copied into every
+/// migration. Included files must lie inside `directory`, and may include others in turn, but not
+/// themselves.
+pub fn load_sql_migrations<P: AsRef<Path>>(directory: P) -> io::Result<Vec<SqlMigration>> {
+    load(directory.as_ref(), None, &ChecksumAlgorithm::default())
+}
+
+/// Like `load_sql_migrations`, but compute the checksums of the migrations with `algorithm`, e.g.
+/// `ChecksumAlgorithm::Crc32` for a directory of migrations previously applied by Flyway.
+pub fn load_sql_migrations_with_checksum_algorithm<P: AsRef<Path>>(
+    directory: P,
+    algorithm: ChecksumAlgorithm
+) -> io::Result<Vec<SqlMigration>> {
+    load(directory.as_ref(), None, &algorithm)
+}
+
+/// Like `load_sql_migrations`, but replace each `${name}` in the SQL files with the value of the
+/// placeholder `name`, e.g. a schema name or tablespace that differs between environments. Fails
+/// with `io::ErrorKind::InvalidData` if a file uses a placeholder missing from `placeholders`.
+/// Checksums are computed after substitution.
+pub fn load_sql_migrations_with_placeholders<P: AsRef<Path>>(
+    directory: P,
+    placeholders: &HashMap<String, String>
+) -> io::Result<Vec<SqlMigration>> {
+    load(directory.as_ref(), Some(placeholders), &ChecksumAlgorithm::default())
+}
+
+fn load(
+    directory: &Path,
+    placeholders: Option<&HashMap<String, String>>,
+    algorithm: &ChecksumAlgorithm
+) -> io::Result<Vec<SqlMigration>> {
+    let mut ups = BTreeMap::new();
+    let mut downs = BTreeMap::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name.to_owned(),
+            None => continue,
+        };
+
+        let (stem, is_up) = if file_name.ends_with(".up.sql") {
+            (&file_name[..file_name.len() - ".up.sql".len()], true)
+        } else if file_name.ends_with(".down.sql") {
+            (&file_name[..file_name.len() - ".down.sql".len()], false)
+        } else {
+            continue;
+        };
+
+        let (version, name) = match parse_stem(stem) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        let mut sql = read_with_includes(directory, &path, &mut vec![])?;
+        if let Some(placeholders) = placeholders {
+            sql = substitute(&sql, placeholders).map_err(|name| {
+                invalid_data(format!("unknown placeholder {:?} in {}", name, path.display()))
+            })?;
+        }
+        let files = if is_up { &mut ups } else { &mut downs };
+        if files.insert(version, (name, sql)).is_some() {
+            return Err(invalid_data(format!("duplicate migration version {}", version)));
+        }
+    }
+
+    let mut migrations = Vec::with_capacity(ups.len());
+    for (version, (name, up)) in ups {
+        let down = downs.remove(&version).map(|(_, down)| down);
+        migrations.push(SqlMigration::new(version, name, up, down).with_checksum_algorithm(algorithm.clone()));
+    }
+
+    if let Some(version) = downs.keys().next() {
+        return Err(invalid_data(format!("migration version {} has no up file", version)));
+    }
+
+    Ok(migrations)
+}
+
+/// SQL run around migrations, loaded from Flyway-style callback files by `load_sql_callbacks`,
+/// e.g. to refresh grants or comments after every run without a fake migration.
+#[derive(Clone, Debug, Default)]
+pub struct SqlCallbacks {
+    /// Run by `PostgresAdapter::before_migrate`, before a migration run.
+    pub before_migrate: Option<String>,
+    /// Run by `PostgresAdapter::after_migrate`, after a migration run.
+    pub after_migrate: Option<String>,
+    /// Run after each migration is applied, in the migration's transaction.
+    pub after_each_migrate: Option<String>,
+}
+
+/// Read the `beforeMigrate.sql`, `afterMigrate.sql` and `afterEachMigrate.sql` callback files in
+/// `directory`, any of which may be missing. Pass the result to `PostgresAdapter::with_sql_callbacks`.
+pub fn load_sql_callbacks<P: AsRef<Path>>(directory: P) -> io::Result<SqlCallbacks> {
+    let read = |file_name: &str| match fs::read_to_string(directory.as_ref().join(file_name)) {
+        Ok(sql) => Ok(Some(sql)),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    };
+
+    Ok(SqlCallbacks {
+        before_migrate: read("beforeMigrate.sql")?,
+        after_migrate: read("afterMigrate.sql")?,
+        after_each_migrate: read("afterEachMigrate.sql")?,
+    })
+}
+
+/// Load the SQL migrations in `directory` with `load_sql_migrations` and register them with
+/// `migrator`, whatever the application context of its adapter.
+pub fn register_sql_migrations<A, X, P>(migrator: &mut Migrator<A>, directory: P) -> io::Result<()>
+where
+    A: Adapter<MigrationType = dyn PostgresMigration<X>>,
+    P: AsRef<Path>,
+{
+    for migration in load_sql_migrations(directory)? {
+        migrator.register(Box::new(migration));
+    }
+
+    Ok(())
+}
+
+/// Load the SQL migrations in `directory` with `load_sql_migrations_with_placeholders` and
+/// register them with `migrator`.
+pub fn register_sql_migrations_with_placeholders<A, X, P>(
+    migrator: &mut Migrator<A>,
+    directory: P,
+    placeholders: &HashMap<String, String>
+) -> io::Result<()>
+where
+    A: Adapter<MigrationType = dyn PostgresMigration<X>>,
+    P: AsRef<Path>,
+{
+    for migration in load_sql_migrations_with_placeholders(directory, placeholders)? {
+        migrator.register(Box::new(migration));
+    }
+
+    Ok(())
+}
+
+/// Replaces each `${name}` in `sql` with the value of placeholder `name`, failing with the name of
+/// the first placeholder that has no value. A `${` without a closing brace is left alone.
+fn substitute(sql: &str, placeholders: &HashMap<String, String>) -> Result<String, String> {
+    let mut result = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        let value = placeholders.get(name).ok_or_else(|| name.to_owned())?;
+        result.push_str(&rest[..start]);
+        result.push_str(value);
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Splits a file stem of the form `V{version}__{name}` into its version and name.
+fn parse_stem(stem: &str) -> Option<(Version, String)> {
+    if !stem.starts_with('V') {
+        return None;
+    }
+
+    let separator = stem.find("__")?;
+    let version = stem[1..separator].parse().ok()?;
+    Some((version, stem[separator + 2..].to_owned()))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
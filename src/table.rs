@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// The longest identifier PostgreSQL keeps without truncating it (`NAMEDATALEN - 1` bytes).
+const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// The name of the metadata table, optionally qualified by the schema it lives in. Formats as a
+/// quoted reference that is safe to interpolate into SQL.
+#[derive(Clone, Debug)]
+pub struct MetadataTable {
+    pub schema: Option<String>,
+    pub name: String,
+}
+
+impl MetadataTable {
+    /// Parses `name`, treating a `schema.table` name as schema-qualified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either part of the name is not a valid identifier (see `validate_identifier`).
+    pub fn parse(name: &str) -> MetadataTable {
+        let table = match name.find('.') {
+            Some(dot) => MetadataTable {
+                schema: Some(name[..dot].to_owned()),
+                name: name[dot + 1..].to_owned(),
+            },
+            None => MetadataTable { schema: None, name: name.to_owned() },
+        };
+
+        if let Some(ref schema) = table.schema {
+            validate_identifier(schema);
+        }
+        validate_identifier(&table.name);
+        table
+    }
+
+    /// The table in the same schema whose name is this table's followed by `suffix`, or `None` if
+    /// that name is longer than PostgreSQL allows.
+    pub fn with_suffix(&self, suffix: &str) -> Option<MetadataTable> {
+        let name = format!("{}{}", self.name, suffix);
+        if name.len() > MAX_IDENTIFIER_LENGTH {
+            return None;
+        }
+
+        Some(MetadataTable { schema: self.schema.clone(), name })
+    }
+}
+
+impl fmt::Display for MetadataTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.schema {
+            Some(ref schema) => write!(f, "{}.{}", quote_identifier(schema), quote_identifier(&self.name)),
+            None => write!(f, "{}", quote_identifier(&self.name)),
+        }
+    }
+}
+
+/// Quotes `identifier` so that it is used verbatim, whatever its case or characters.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Quotes a possibly schema-qualified name (`schema.name`), quoting each part separately.
+pub fn quote_qualified(name: &str) -> String {
+    match name.find('.') {
+        Some(dot) => format!("{}.{}", quote_identifier(&name[..dot]), quote_identifier(&name[dot + 1..])),
+        None => quote_identifier(name),
+    }
+}
+
+/// Checks that `identifier` can be used as a quoted PostgreSQL identifier: it must be non-empty,
+/// free of NUL characters, and short enough not to be truncated.
+///
+/// # Panics
+///
+/// Panics if `identifier` is invalid.
+pub fn validate_identifier(identifier: &str) {
+    assert!(!identifier.is_empty(), "metadata table identifiers must not be empty");
+    assert!(!identifier.contains('\0'), "metadata table identifiers must not contain NUL characters");
+    assert!(
+        identifier.len() <= MAX_IDENTIFIER_LENGTH,
+        "metadata table identifier {:?} is longer than {} bytes",
+        identifier,
+        MAX_IDENTIFIER_LENGTH
+    );
+}
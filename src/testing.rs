@@ -0,0 +1,128 @@
+//! Helpers for testing code that depends on migrations, enabled by the `testing` feature. Each
+//! client works in its session's temporary schema, `pg_temp`, so that nothing outlives the
+//! connection and tests running in parallel do not see each other's tables. `FakeAdapter` needs
+//! no database at all.
+
+use postgres::error::Error as PostgresError;
+use postgres::{Client, NoTls};
+use schemamama::{Adapter, Migration, Migrator, Version};
+use std::collections::BTreeSet;
+use std::error::Error;
+
+use crate::{Direction, PostgresAdapter, PostgresMigration, SchemamamaPostgresError};
+
+/// Connect to the database at `url` and set `search_path TO pg_temp`, so that every table created
+/// through the client is temporary.
+pub fn temp_schema_client(url: &str) -> Result<Client, PostgresError> {
+    let mut client = Client::connect(url, NoTls)?;
+    client.batch_execute("SET search_path TO pg_temp;")?;
+    Ok(client)
+}
+
+/// Connect to the database at `url` with `temp_schema_client`, apply all of `migrations` and call
+/// `f` with the migrated client. The temporary schema is dropped with the connection once `f`
+/// returns.
+pub fn with_migrated_db<F, T>(url: &str, migrations: Vec<Box<dyn PostgresMigration>>, f: F) -> Result<T, Box<dyn Error>>
+where
+    F: FnOnce(&mut Client) -> T,
+{
+    let mut client = temp_schema_client(url)?;
+    migrate(&mut client, migrations)?;
+    Ok(f(&mut client))
+}
+
+/// Creates the metadata table and applies all of `migrations`.
+pub(crate) fn migrate(client: &mut Client, migrations: Vec<Box<dyn PostgresMigration>>) -> Result<(), Box<dyn Error>> {
+    let mut adapter = PostgresAdapter::new(client);
+    adapter.setup_schema()?;
+    let mut migrator = Migrator::new(adapter);
+    for migration in migrations {
+        migrator.register(migration);
+    }
+    migrator.up(None)?;
+    Ok(())
+}
+
+/// An in-memory stand-in for `PostgresAdapter`, for unit tests of code that orchestrates
+/// migrations (e.g. decides which migrator to run and up to which version) without a live
+/// database. It keeps the applied versions in memory and records every migration it is asked to
+/// apply or revert, without running them.
+///
+/// ```rust,ignore
+/// let mut adapter = FakeAdapter::with_applied(vec![1]);
+/// deploy(&mut Migrator::new(&mut adapter))?;
+/// assert_eq!(adapter.calls(), &[(Direction::Apply, 2)]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FakeAdapter {
+    applied: BTreeSet<Version>,
+    calls: Vec<(Direction, Version)>,
+}
+
+impl FakeAdapter {
+    /// Create an adapter with no migration applied.
+    pub fn new() -> FakeAdapter {
+        FakeAdapter::default()
+    }
+
+    /// Create an adapter on which `versions` are already applied.
+    pub fn with_applied<I: IntoIterator<Item = Version>>(versions: I) -> FakeAdapter {
+        FakeAdapter { applied: versions.into_iter().collect(), calls: vec![] }
+    }
+
+    /// The applied versions, in ascending order.
+    pub fn applied(&self) -> &BTreeSet<Version> {
+        &self.applied
+    }
+
+    /// The migrations applied and reverted so far, in order.
+    pub fn calls(&self) -> &[(Direction, Version)] {
+        &self.calls
+    }
+}
+
+impl Adapter for FakeAdapter {
+    type MigrationType = dyn PostgresMigration;
+    type Error = SchemamamaPostgresError;
+
+    fn current_version(&mut self) -> Result<Option<Version>, SchemamamaPostgresError> {
+        Ok(self.applied.iter().next_back().cloned())
+    }
+
+    fn migrated_versions(&mut self) -> Result<BTreeSet<Version>, SchemamamaPostgresError> {
+        Ok(self.applied.clone())
+    }
+
+    fn apply_migration(&mut self, migration: &dyn PostgresMigration) -> Result<(), SchemamamaPostgresError> {
+        self.calls.push((Direction::Apply, migration.version()));
+        self.applied.insert(migration.version());
+        Ok(())
+    }
+
+    fn revert_migration(&mut self, migration: &dyn PostgresMigration) -> Result<(), SchemamamaPostgresError> {
+        self.calls.push((Direction::Revert, migration.version()));
+        self.applied.remove(&migration.version());
+        Ok(())
+    }
+}
+
+impl<'a> Adapter for &'a mut FakeAdapter {
+    type MigrationType = dyn PostgresMigration;
+    type Error = SchemamamaPostgresError;
+
+    fn current_version(&mut self) -> Result<Option<Version>, SchemamamaPostgresError> {
+        (**self).current_version()
+    }
+
+    fn migrated_versions(&mut self) -> Result<BTreeSet<Version>, SchemamamaPostgresError> {
+        (**self).migrated_versions()
+    }
+
+    fn apply_migration(&mut self, migration: &dyn PostgresMigration) -> Result<(), SchemamamaPostgresError> {
+        (**self).apply_migration(migration)
+    }
+
+    fn revert_migration(&mut self, migration: &dyn PostgresMigration) -> Result<(), SchemamamaPostgresError> {
+        (**self).revert_migration(migration)
+    }
+}
@@ -0,0 +1,71 @@
+use schemamama::Version;
+
+/// Converts a timestamp such as `"2024-06-01 12:30"` into the version `202406011230`, the
+/// `YYYYMMDDHHMM` convention used by most teams. Separators are ignored, so `"202406011230"` and
+/// `"2024-06-01T12:30"` work too, and a timestamp with seconds (`"2024-06-01 12:30:15"`) becomes
+/// a `YYYYMMDDHHMMSS` version.
+///
+/// # Panics
+///
+/// Panics if `timestamp` does not have 12 or 14 digits, or if its month, day, hour, minute or
+/// second is out of range. When called in a constant, as `timestamp_migration!` does, this is a
+/// compile-time error.
+pub const fn timestamp_version(timestamp: &str) -> Version {
+    let bytes = timestamp.as_bytes();
+    let mut digits = [0u8; 14];
+    let mut count = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if byte.is_ascii_digit() {
+            assert!(count < 14, "timestamps must have 12 or 14 digits");
+            digits[count] = byte - b'0';
+            count += 1;
+        } else {
+            assert!(matches!(byte, b'-' | b' ' | b':' | b'T'), "unexpected character in timestamp");
+        }
+        i += 1;
+    }
+    assert!(count == 12 || count == 14, "timestamps must have 12 or 14 digits");
+
+    let month = digits[4] * 10 + digits[5];
+    let day = digits[6] * 10 + digits[7];
+    let hour = digits[8] * 10 + digits[9];
+    let minute = digits[10] * 10 + digits[11];
+    let second = digits[12] * 10 + digits[13];
+    assert!(matches!(month, 1..=12), "month out of range in timestamp");
+    assert!(matches!(day, 1..=31), "day out of range in timestamp");
+    assert!(hour < 24 && minute < 60 && second < 60, "time out of range in timestamp");
+
+    let mut version: Version = 0;
+    let mut i = 0;
+    while i < count {
+        version = version * 10 + digits[i] as Version;
+        i += 1;
+    }
+    version
+}
+
+/// Implements `schemamama::Migration` for a type, like `schemamama`'s `migration!`, with a version
+/// derived from a timestamp by `timestamp_version`. Invalid timestamps fail to compile.
+///
+/// ```rust,ignore
+/// struct CreateUsers;
+/// timestamp_migration!(CreateUsers, "2024-06-01 12:30", "create users table");
+/// assert_eq!(CreateUsers.version(), 202406011230);
+/// ```
+#[macro_export]
+macro_rules! timestamp_migration {
+    ($ty:ident, $timestamp:expr, $description:expr) => {
+        impl ::schemamama::Migration for $ty {
+            fn version(&self) -> ::schemamama::Version {
+                const VERSION: ::schemamama::Version = $crate::timestamp_version($timestamp);
+                VERSION
+            }
+
+            fn description(&self) -> String {
+                $description.into()
+            }
+        }
+    };
+}
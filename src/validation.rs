@@ -0,0 +1,55 @@
+//! Tracking of constraints added as `NOT VALID` whose existing rows are still to be validated.
+
+use crate::table::{quote_identifier, quote_qualified};
+use crate::{MigrationClient, PostgresAdapter, SchemamamaPostgresError};
+
+/// A constraint added as `NOT VALID` (see `add_constraint_not_valid`) that has not been validated
+/// yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingValidation {
+    /// The table the constraint belongs to, qualified with its schema.
+    pub table: String,
+    /// The name of the constraint.
+    pub constraint: String,
+    /// The definition of the constraint, as reported by `pg_get_constraintdef`.
+    pub definition: String,
+}
+
+impl<'a, C: MigrationClient + 'a, A: 'a> PostgresAdapter<'a, C, A> {
+    /// Returns the constraints of the tables visible through the `search_path` that are still
+    /// `NOT VALID`, ordered by table and constraint name.
+    pub fn pending_validations(&mut self) -> Result<Vec<PendingValidation>, SchemamamaPostgresError> {
+        let rows = self.metadata_query(
+            "SELECT n.nspname || '.' || c.relname, con.conname::TEXT, pg_get_constraintdef(con.oid) \
+             FROM pg_constraint con \
+             JOIN pg_class c ON c.oid = con.conrelid \
+             JOIN pg_namespace n ON n.oid = c.relnamespace \
+             WHERE NOT con.convalidated AND pg_table_is_visible(c.oid) \
+             ORDER BY 1, 2;",
+            &[],
+            &[]
+        )?;
+        Ok(rows.iter().map(|r| PendingValidation {
+            table: r.get(0),
+            constraint: r.get(1),
+            definition: r.get(2),
+        }).collect())
+    }
+
+    /// Validate every constraint listed by `pending_validations`, each in its own transaction, as a
+    /// non-blocking second phase after the migrations that added them. Returns the constraints
+    /// that were validated.
+    pub fn validate_pending_constraints(&mut self) -> Result<Vec<PendingValidation>, SchemamamaPostgresError> {
+        let pending = self.pending_validations()?;
+        for validation in &pending {
+            let query = format!(
+                "ALTER TABLE {} VALIDATE CONSTRAINT {};",
+                quote_qualified(&validation.table),
+                quote_identifier(&validation.constraint)
+            );
+            self.locked(|adapter| adapter.client.batch_execute(&query).map_err(SchemamamaPostgresError::Postgres))?;
+        }
+
+        Ok(pending)
+    }
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "async")]
+
+#[macro_use]
+extern crate schemamama;
+extern crate schemamama_postgres;
+extern crate tokio_postgres;
+
+use async_trait::async_trait;
+use schemamama_postgres::{AsyncMigrator, AsyncPostgresAdapter, AsyncPostgresMigration};
+use tokio_postgres::{Client, NoTls, Transaction};
+use tokio_postgres::error::Error as PostgresError;
+
+async fn make_database_connection() -> Client {
+    let (client, connection) = tokio_postgres::connect("postgres://postgres@localhost", NoTls)
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    client.batch_execute("SET search_path TO pg_temp;").await.unwrap();
+    client
+}
+
+struct FirstMigration;
+migration!(FirstMigration, 10, "first migration");
+
+#[async_trait]
+impl AsyncPostgresMigration for FirstMigration {
+    async fn up(&self, transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+        transaction.execute("CREATE TABLE first (id BIGINT PRIMARY KEY);", &[]).await.map(|_| ())
+    }
+
+    async fn down(&self, transaction: &mut Transaction<'_>) -> Result<(), PostgresError> {
+        transaction.execute("DROP TABLE first;", &[]).await.map(|_| ())
+    }
+}
+
+struct SecondMigration;
+migration!(SecondMigration, 20, "second migration");
+
+impl AsyncPostgresMigration for SecondMigration {
+}
+
+#[tokio::test]
+async fn test_async_migration_count() {
+    let mut client = make_database_connection().await;
+    let mut adapter = AsyncPostgresAdapter::new(&mut client);
+    adapter.setup_schema().await.unwrap();
+    let mut migrator = AsyncMigrator::new(adapter);
+    migrator.register(Box::new(FirstMigration));
+    migrator.register(Box::new(SecondMigration));
+
+    migrator.up(Some(1337)).await.unwrap();
+    assert_eq!(migrator.current_version().await.unwrap(), Some(20));
+    migrator.down(None).await.unwrap();
+    assert_eq!(migrator.current_version().await.unwrap(), None);
+}
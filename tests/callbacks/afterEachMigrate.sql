@@ -0,0 +1 @@
+INSERT INTO runs VALUES ('each');
@@ -0,0 +1,2 @@
+CREATE TABLE IF NOT EXISTS runs (event TEXT);
+INSERT INTO runs VALUES ('before');
@@ -0,0 +1,24 @@
+#![cfg(feature = "embed")]
+
+extern crate schemamama;
+extern crate schemamama_postgres;
+
+use schemamama::Migration;
+use schemamama_postgres::embed_postgres_migrations;
+
+#[test]
+fn test_embedded_migrations() {
+    let migrations = embed_postgres_migrations!("tests/migrations");
+    let versions: Vec<_> = migrations.iter().map(|m| m.version()).collect();
+    assert_eq!(versions, vec![1, 2]);
+}
+
+#[test]
+fn test_embedded_includes() {
+    let migrations = embed_postgres_migrations!("tests/includes");
+    assert_eq!(migrations.len(), 1);
+    let sql = migrations[0].up_sql().unwrap();
+    assert!(sql.starts_with("CREATE FUNCTION touch()"));
+    assert!(!sql.contains("-- include:"));
+    assert_eq!(sql, schemamama_postgres::load_sql_migrations("tests/includes").unwrap()[0].up_sql().unwrap());
+}
@@ -0,0 +1,3 @@
+-- include: shared/touch.sql
+CREATE TABLE notes (body TEXT, updated_at TIMESTAMPTZ);
+CREATE TRIGGER notes_touch BEFORE UPDATE ON notes FOR EACH ROW EXECUTE FUNCTION touch();
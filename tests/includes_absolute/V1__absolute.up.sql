@@ -0,0 +1,2 @@
+-- include: /etc/passwd
+SELECT 1;
@@ -0,0 +1,2 @@
+-- include: shared/../shared/loop.sql
+SELECT 1;
@@ -0,0 +1 @@
+-- include: shared/../shared/loop.sql
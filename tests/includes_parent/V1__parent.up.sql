@@ -0,0 +1,2 @@
+-- include: ../includes/shared/touch.sql
+SELECT 1;
@@ -0,0 +1 @@
+CREATE OR REPLACE VIEW user_count AS SELECT count(*) AS users FROM users;
@@ -0,0 +1,2 @@
+CREATE TABLE products (id BIGINT PRIMARY KEY);
+CREATE INDEX products_id_idx ON products (id);
@@ -0,0 +1 @@
+CREATE TABLE ${table} (id BIGINT) ${tablespace};
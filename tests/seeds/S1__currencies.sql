@@ -0,0 +1,3 @@
+CREATE TABLE IF NOT EXISTS currencies (code TEXT PRIMARY KEY, name TEXT NOT NULL);
+INSERT INTO currencies (code, name) VALUES ('EUR', 'Euro'), ('USD', 'US Dollar')
+ON CONFLICT (code) DO UPDATE SET name = excluded.name;
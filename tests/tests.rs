@@ -1,11 +1,24 @@
 #[macro_use]
 extern crate schemamama;
+#[macro_use]
 extern crate schemamama_postgres;
 extern crate postgres;
 
-use schemamama::Migrator;
-use schemamama_postgres::{PostgresAdapter, PostgresMigration};
-use postgres::{Client, Transaction, NoTls};
+use schemamama::{Adapter, Migrator};
+use schemamama_postgres::{register_sql_migrations, LockBehavior, OutOfOrder, Phase, PostgresAdapter, PostgresMigration};
+use schemamama_postgres::{MetadataSummary, MigrationContext, MigrationHooks, ProgressEvent, ProgressStatus, RecordConflict, Repair, RevertOrder, SchemamamaPostgresError, SqlMigration};
+use schemamama_postgres::{add_column_with_backfill, copy_csv, copy_rows, create_index_concurrently, execute_statements, timestamp_version};
+use schemamama_postgres::{drop_rename_shim, rename_table_with_shim, set_not_null, split_statements, ColumnTypeChange};
+use schemamama_postgres::{add_constraint_not_valid, add_enum_value, validate_constraint, PartitionInterval, PendingValidation, TimePartitions};
+use schemamama_postgres::{Backfill, BackfillProgress, IndexOptions, IrreversibleMigration, LockWarning, MultiSchemaRunner, PeerOutcome, RetryPolicy};
+use schemamama_postgres::{load_repeatable_migrations, load_sql_callbacks, load_sql_migrations, load_sql_migrations_with_placeholders};
+use schemamama_postgres::{load_seeds, load_sql_migrations_with_checksum_algorithm, sha256_hex, ChecksumAlgorithm, Drift, DriftedChecksum, RepeatableMigration, Seed};
+use schemamama::Version;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+use postgres::{Client, SimpleQueryMessage, Transaction, NoTls};
 use postgres::error::Error as PostgresError;
 
 fn make_database_connection() -> Client {
@@ -40,6 +53,41 @@ migration!(SecondMigration, 20, "second migration");
 impl PostgresMigration for SecondMigration {
 }
 
+struct FailingMigration;
+migration!(FailingMigration, 40, "failing migration");
+
+impl PostgresMigration for FailingMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.execute("SELECT * FROM missing_table;", &[]).map(|_| ())
+    }
+}
+
+struct SlowMigration;
+migration!(SlowMigration, 50, "slow migration");
+
+impl PostgresMigration for SlowMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.execute("SELECT pg_sleep(1);", &[]).map(|_| ())
+    }
+}
+
+struct ConcurrentIndexMigration;
+migration!(ConcurrentIndexMigration, 30, "concurrent index migration");
+
+impl PostgresMigration for ConcurrentIndexMigration {
+    fn run_in_transaction(&self) -> bool {
+        false
+    }
+
+    fn up_without_transaction(&self, client: &mut Client) -> Result<(), PostgresError> {
+        client.execute("CREATE INDEX CONCURRENTLY first_id_idx ON first (id);", &[]).map(|_| ())
+    }
+
+    fn down_without_transaction(&self, client: &mut Client) -> Result<(), PostgresError> {
+        client.execute("DROP INDEX CONCURRENTLY first_id_idx;", &[]).map(|_| ())
+    }
+}
+
 #[test]
 fn test_setup() {
     let mut client = make_database_connection();
@@ -72,6 +120,59 @@ fn test_setup_with_custom_metadata_table() {
     }
 }
 
+#[test]
+fn test_unnamed_statements() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client)
+        .with_history_table("schemamama_history")
+        .with_statement_timeout(Duration::from_secs(60))
+        .with_unnamed_statements();
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+    adapter.revert_migration(&SecondMigration).unwrap();
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10]);
+    assert_eq!(adapter.migration_log().unwrap().len(), 1);
+    drop(adapter);
+
+    // Nothing was left prepared on the connection for a pooler to trip over.
+    let prepared = client.simple_query("SELECT name FROM pg_prepared_statements;").unwrap();
+    assert!(!prepared.iter().any(|message| matches!(message, SimpleQueryMessage::Row(_))));
+}
+
+#[test]
+fn test_setup_with_runtime_metadata_table() {
+    let mut client = make_database_connection();
+    let tenant = 42;
+    let mut adapter = PostgresAdapter::with_metadata_table(&mut client, format!("schemamama_{}", tenant));
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(20));
+}
+
+#[test]
+fn test_setup_with_quoted_metadata_table() {
+    let mut client = make_database_connection();
+    let schema_name = current_schema_name(&mut client);
+    let mut adapter = PostgresAdapter::with_metadata_table(&mut client, "Select \"Migrations\"");
+    let mut client = make_database_connection();
+    let query = "SELECT * FROM pg_catalog.pg_tables WHERE schemaname = $1 AND \
+                 tablename = 'Select \"Migrations\"';";
+
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(20));
+    let statement = client.prepare(query).unwrap();
+    assert_eq!(client.execute(&statement, &[&schema_name]).unwrap(), 1);
+}
+
+#[test]
+#[should_panic]
+fn test_invalid_metadata_table() {
+    let mut client = make_database_connection();
+    PostgresAdapter::with_metadata_table(&mut client, "");
+}
+
 #[test]
 fn test_migration_count() {
     let mut client = make_database_connection();
@@ -106,3 +207,2040 @@ fn test_migration_up_and_down() {
     migrator.down(None).unwrap();
     assert_eq!(client.execute(&statement, &[&schema_name]).unwrap(), 0);
 }
+
+#[test]
+fn test_migration_with_advisory_lock() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_advisory_lock(4242);
+    adapter.setup_schema().unwrap();
+    let mut migrator = Migrator::new(adapter);
+    migrator.register(Box::new(SecondMigration));
+
+    migrator.up(None).unwrap();
+    assert_eq!(migrator.current_version().unwrap(), Some(20));
+
+    let mut client = make_database_connection();
+    let statement = client.prepare("SELECT pg_try_advisory_lock(4242);").unwrap();
+    let locked: bool = client.query(&statement, &[]).unwrap()[0].get(0);
+    assert!(locked);
+}
+
+#[test]
+fn test_migration_without_transaction() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let mut migrator = Migrator::new(adapter);
+    migrator.register(Box::new(FirstMigration));
+    migrator.register(Box::new(ConcurrentIndexMigration));
+
+    migrator.up(None).unwrap();
+    assert_eq!(migrator.current_version().unwrap(), Some(30));
+    migrator.down(None).unwrap();
+    assert_eq!(migrator.current_version().unwrap(), None);
+}
+
+#[test]
+fn test_atomic_batch() {
+    let mut client = make_database_connection();
+    let schema_name = current_schema_name(&mut client);
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let mut adapter = adapter.atomic_batch(true);
+    {
+        let mut migrator = Migrator::new(&mut adapter);
+        migrator.register(Box::new(FirstMigration));
+        migrator.register(Box::new(SecondMigration));
+        migrator.register(Box::new(FailingMigration));
+        assert!(migrator.up(None).is_err());
+    }
+    drop(adapter);
+
+    assert_eq!(PostgresAdapter::new(&mut client).current_version().unwrap(), None);
+    let query = "SELECT * FROM pg_catalog.pg_tables WHERE schemaname = $1 AND \
+                 tablename = 'first';";
+    let statement = client.prepare(query).unwrap();
+    assert_eq!(client.execute(&statement, &[&schema_name]).unwrap(), 0);
+}
+
+#[test]
+fn test_sql_migrations() {
+    let mut client = make_database_connection();
+    let schema_name = current_schema_name(&mut client);
+    let mut adapter = PostgresAdapter::new(&mut client);
+    let mut client = make_database_connection();
+    adapter.setup_schema().unwrap();
+    let mut migrator = Migrator::new(adapter);
+    register_sql_migrations(&mut migrator, "tests/migrations").unwrap();
+
+    migrator.up(None).unwrap();
+    assert_eq!(migrator.current_version().unwrap(), Some(2));
+    let query = "SELECT * FROM pg_catalog.pg_tables WHERE schemaname = $1 AND \
+                 tablename IN ('users', 'products');";
+    let statement = client.prepare(query).unwrap();
+    assert_eq!(client.execute(&statement, &[&schema_name]).unwrap(), 2);
+
+    migrator.down(None).unwrap();
+    assert_eq!(client.execute(&statement, &[&schema_name]).unwrap(), 0);
+}
+
+#[test]
+fn test_verify_checksums() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let original = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None);
+    adapter.apply_migration(&original).unwrap();
+    adapter.verify_checksums(vec![&original as &dyn PostgresMigration]).unwrap();
+
+    let edited = SqlMigration::new(1, "create users", "CREATE TABLE users (id INT);", None);
+    match adapter.verify_checksums(vec![&edited as &dyn PostgresMigration]) {
+        Err(SchemamamaPostgresError::ChecksumMismatch { mismatches }) => {
+            assert_eq!(mismatches.len(), 1);
+            assert_eq!(mismatches[0].version, 1);
+            assert_eq!(mismatches[0].expected, original.checksum().unwrap());
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(adapter.checksum_mismatches(vec![&edited as &dyn PostgresMigration]).unwrap().len(), 1);
+}
+
+#[test]
+fn test_applied_at() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    assert_eq!(adapter.applied_at(20).unwrap(), None);
+
+    adapter.apply_migration(&SecondMigration).unwrap();
+    assert!(adapter.applied_at(20).unwrap().is_some());
+}
+
+#[test]
+fn test_migration_log() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+
+    let log = adapter.migration_log().unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].version, 20);
+    assert_eq!(log[0].description, Some("second migration".to_owned()));
+    assert!(log[0].applied_at.is_some());
+    assert_eq!(log[0].applied_by, Some("postgres".to_owned()));
+}
+
+#[test]
+fn test_dry_run() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let mut adapter = adapter.dry_run(true);
+    let migration = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None);
+    adapter.apply_migration(&migration).unwrap();
+
+    assert_eq!(adapter.current_version().unwrap(), None);
+    let script = adapter.dry_run_script().unwrap();
+    assert!(script.contains("CREATE TABLE users (id BIGINT);"));
+    assert!(script.contains("INSERT INTO schemamama"));
+}
+
+#[test]
+fn test_simulate_up() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+
+    let migrations: Vec<&dyn PostgresMigration> = vec![&FirstMigration, &SecondMigration, &FailingMigration];
+    let simulation = adapter.simulate_up(migrations, None).unwrap();
+    assert_eq!(simulation.succeeded, vec![10, 20]);
+    assert_eq!(simulation.failed.map(|(version, _)| version), Some(40));
+    assert_eq!(adapter.current_version().unwrap(), None);
+}
+
+#[test]
+fn test_simulate_up_filters() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).without_tags(vec!["seed"]).with_skipped_versions(vec![20]);
+    adapter.setup_schema().unwrap();
+
+    let migrations: Vec<&dyn PostgresMigration> = vec![&FirstMigration, &SecondMigration, &TunedMigration, &DemoDataMigration];
+    let simulation = adapter.simulate_up(migrations, None).unwrap();
+    assert_eq!(simulation.succeeded, vec![10, 90]);
+    assert_eq!(simulation.skipped, vec![20]);
+    assert!(simulation.failed.is_none());
+    assert_eq!(adapter.current_version().unwrap(), None);
+
+    let mut adapter = adapter.dry_run(true);
+    adapter.apply_migration(&SecondMigration).unwrap();
+    let script = adapter.dry_run_script().unwrap();
+    assert!(script.starts_with("-- skip 20: second migration\n"));
+    assert!(script.contains("skipped) VALUES (20, 'second migration', NULL, true);"));
+}
+
+#[test]
+fn test_migration_error_context() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+
+    match adapter.apply_migration(&FailingMigration) {
+        Err(SchemamamaPostgresError::Migration { version, description, phase, .. }) => {
+            assert_eq!(version, 40);
+            assert_eq!(description, "failing migration");
+            assert_eq!(phase, Phase::Up);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+struct RecordingHooks(Rc<RefCell<Vec<String>>>);
+
+impl MigrationHooks for RecordingHooks {
+    fn before_apply(&mut self, version: Version, _description: &str) {
+        self.0.borrow_mut().push(format!("before apply {}", version));
+    }
+
+    fn after_apply(&mut self, version: Version, _description: &str) {
+        self.0.borrow_mut().push(format!("after apply {}", version));
+    }
+
+    fn after_revert(&mut self, version: Version, _description: &str) {
+        self.0.borrow_mut().push(format!("after revert {}", version));
+    }
+}
+
+#[test]
+fn test_migration_hooks() {
+    let events = Rc::new(RefCell::new(vec![]));
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_hooks(RecordingHooks(events.clone()));
+    adapter.setup_schema().unwrap();
+
+    adapter.apply_migration(&SecondMigration).unwrap();
+    assert!(adapter.apply_migration(&FailingMigration).is_err());
+    adapter.revert_migration(&SecondMigration).unwrap();
+    assert_eq!(*events.borrow(), vec!["before apply 20", "after apply 20", "before apply 40", "after revert 20"]);
+}
+
+#[test]
+fn test_progress_events() {
+    let events = Rc::new(RefCell::new(vec![]));
+    let recorded = events.clone();
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client)
+        .with_progress(move |event: &ProgressEvent| {
+            if event.status == ProgressStatus::Started {
+                recorded.borrow_mut().push(event.to_string());
+            }
+        });
+    adapter.setup_schema().unwrap();
+    adapter.set_progress_total(2);
+
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+    assert_eq!(*events.borrow(), vec![
+        "applying migration 1 of 2: 10 first migration",
+        "applying migration 2 of 2: 20 second migration",
+    ]);
+}
+
+#[test]
+fn test_statement_timeout() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client)
+        .with_statement_timeout(Duration::from_millis(10));
+    adapter.setup_schema().unwrap();
+
+    assert!(adapter.apply_migration(&SlowMigration).is_err());
+    assert_eq!(adapter.current_version().unwrap(), None);
+}
+
+#[test]
+fn test_migrate_inside_application_transaction() {
+    let mut client = make_database_connection();
+    {
+        let mut transaction = client.transaction().unwrap();
+        {
+            let mut adapter = PostgresAdapter::new(&mut transaction);
+            adapter.setup_schema().unwrap();
+            adapter.apply_migration(&FirstMigration).unwrap();
+            assert!(matches!(
+                adapter.apply_migration(&ConcurrentIndexMigration),
+                Err(SchemamamaPostgresError::NonTransactionalInBatch { version: 30 })
+            ));
+            assert_eq!(adapter.current_version().unwrap(), Some(10));
+        }
+        transaction.rollback().unwrap();
+    }
+
+    let row = client.query_one("SELECT to_regclass('pg_temp.schemamama')::text;", &[]).unwrap();
+    assert_eq!(row.get::<_, Option<String>>(0), None);
+}
+
+#[test]
+fn test_baseline() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+
+    // FailingMigration would fail if its `up` were run.
+    let migrations: Vec<&dyn PostgresMigration> = vec![&FirstMigration, &SecondMigration, &FailingMigration, &SlowMigration];
+    assert_eq!(adapter.baseline(migrations, 40).unwrap(), vec![20, 40]);
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10, 20, 40]);
+}
+
+#[test]
+fn test_repair() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    let recorded: Vec<&dyn PostgresMigration> = vec![&FailingMigration, &SlowMigration];
+    adapter.baseline(recorded, 50).unwrap();
+
+    // SlowMigration is no longer registered, and SecondMigration was never recorded.
+    let migrations: Vec<&dyn PostgresMigration> = vec![&FirstMigration, &SecondMigration, &FailingMigration];
+    assert_eq!(adapter.repair(migrations.clone(), false).unwrap(), vec![Repair::Removed(50)]);
+    assert_eq!(adapter.repair(migrations.clone(), true).unwrap(), vec![Repair::Inserted(20)]);
+    assert_eq!(adapter.repair(migrations, true).unwrap(), vec![]);
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10, 20, 40]);
+}
+
+#[test]
+fn test_out_of_order_policy() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_out_of_order(OutOfOrder::Reject);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+
+    assert!(matches!(
+        adapter.apply_migration(&FirstMigration),
+        Err(SchemamamaPostgresError::OutOfOrder { version: 10, latest: 20 })
+    ));
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![20]);
+
+    let mut adapter = adapter.with_out_of_order(OutOfOrder::WarnAndApply);
+    adapter.apply_migration(&FirstMigration).unwrap();
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10, 20]);
+}
+
+#[test]
+fn test_record_conflict_policy() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.record_versions(&[10]).unwrap();
+
+    let migration = SqlMigration::new(10, "noop", "CREATE TABLE noop (id BIGINT);", None);
+    assert!(matches!(
+        adapter.apply_migration(&migration),
+        Err(SchemamamaPostgresError::AlreadyRecorded { version: 10 })
+    ));
+
+    let mut adapter = adapter.with_record_conflict(RecordConflict::Ignore);
+    adapter.apply_migration(&migration).unwrap();
+    assert_eq!(adapter.migration_log().unwrap()[0].description, None);
+
+    let migration = SqlMigration::new(10, "noop", "SELECT 1;", None);
+    let mut adapter = adapter.with_record_conflict(RecordConflict::Update);
+    adapter.apply_migration(&migration).unwrap();
+    assert_eq!(adapter.migration_log().unwrap()[0].description, Some("noop".to_owned()));
+}
+
+#[test]
+fn test_version_checked_before_running() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.record_versions(&[10]).unwrap();
+
+    // Applied concurrently by another migrator after being found pending: the migration must not
+    // run again.
+    let migration = SqlMigration::new(10, "fails if run", "SELECT 1 / 0;", None);
+    assert!(matches!(
+        adapter.apply_migration(&migration),
+        Err(SchemamamaPostgresError::AlreadyRecorded { version: 10 })
+    ));
+}
+
+#[test]
+fn test_revert_in_applied_order() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_revert_order(RevertOrder::Applied);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    assert_eq!(adapter.applied_order().unwrap(), vec![20, 10]);
+
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration];
+    assert_eq!(adapter.revert_last(migrations.clone(), 1).unwrap(), vec![10]);
+    assert_eq!(adapter.applied_order().unwrap(), vec![20]);
+
+    adapter.apply_migration(&FirstMigration).unwrap();
+    let mut adapter = adapter.with_revert_order(RevertOrder::Version);
+    assert_eq!(adapter.revert_last(migrations, 1).unwrap(), vec![20]);
+}
+
+#[test]
+fn test_validate_unknown_versions() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+
+    adapter.validate(vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration]).unwrap();
+    assert!(matches!(
+        adapter.validate(vec![&FirstMigration as &dyn PostgresMigration]),
+        Err(SchemamamaPostgresError::UnknownVersions { ref versions }) if *versions == vec![20]
+    ));
+}
+
+#[test]
+fn test_status() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+
+    let status = adapter.status(vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration]).unwrap();
+    assert_eq!(status.applied.iter().map(|record| record.version).collect::<Vec<_>>(), vec![20]);
+    assert!(status.applied[0].applied_at.is_some());
+    assert_eq!(status.pending.len(), 1);
+    assert_eq!(status.pending[0].version, 10);
+    assert_eq!(status.pending[0].description, "first migration");
+}
+
+#[test]
+fn test_multi_schema_runner() {
+    let mut client = make_database_connection();
+    let mut transaction = client.transaction().unwrap();
+    transaction.batch_execute("CREATE SCHEMA tenant_b; CREATE TABLE tenant_b.first (id BIGINT);").unwrap();
+
+    let outcomes = {
+        let mut runner = MultiSchemaRunner::new(&mut transaction).with_schemas(vec!["tenant_a", "tenant_b"]);
+        runner.register(Box::new(FirstMigration));
+        runner.register(Box::new(SecondMigration));
+        runner.up(None)
+    };
+
+    assert_eq!(outcomes[0].schema, "tenant_a");
+    assert_eq!(outcomes[0].result.as_ref().unwrap(), &Some(20));
+    assert_eq!(outcomes[1].schema, "tenant_b");
+    assert!(outcomes[1].result.is_err());
+
+    let row = transaction.query_one("SELECT to_regclass('tenant_a.first')::text, current_setting('search_path');", &[]).unwrap();
+    assert_eq!(row.get::<_, Option<String>>(0), Some("tenant_a.first".to_owned()));
+    assert_eq!(row.get::<_, String>(1), "pg_temp");
+    transaction.rollback().unwrap();
+}
+
+#[test]
+fn test_search_path() {
+    let mut client = make_database_connection();
+    let mut transaction = client.transaction().unwrap();
+    transaction.batch_execute("CREATE SCHEMA scoped;").unwrap();
+    {
+        let mut adapter = PostgresAdapter::new(&mut transaction).with_search_path(vec!["scoped"]);
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None)).unwrap();
+        assert_eq!(adapter.current_version().unwrap(), Some(1));
+    }
+
+    let row = transaction.query_one("SELECT to_regclass('scoped.users')::text, current_setting('search_path');", &[]).unwrap();
+    assert_eq!(row.get::<_, Option<String>>(0), Some("scoped.users".to_owned()));
+    assert_eq!(row.get::<_, String>(1), "pg_temp");
+    transaction.rollback().unwrap();
+}
+
+#[test]
+fn test_role() {
+    let mut client = make_database_connection();
+    let mut transaction = client.transaction().unwrap();
+    transaction.batch_execute("CREATE ROLE schemamama_owner; CREATE SCHEMA owned AUTHORIZATION schemamama_owner;").unwrap();
+    {
+        let mut adapter = PostgresAdapter::new(&mut transaction)
+            .with_search_path(vec!["owned"])
+            .with_role("schemamama_owner");
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None)).unwrap();
+    }
+
+    let row = transaction.query_one("SELECT tableowner::text, current_user::text FROM pg_tables WHERE schemaname = 'owned';", &[]).unwrap();
+    assert_eq!(row.get::<_, String>(0), "schemamama_owner");
+    assert_eq!(row.get::<_, String>(1), "postgres");
+    transaction.rollback().unwrap();
+}
+
+struct FlakyMigration(Cell<u32>);
+migration!(FlakyMigration, 60, "flaky migration");
+
+impl PostgresMigration for FlakyMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        self.0.set(self.0.get() + 1);
+        if self.0.get() < 3 {
+            transaction.batch_execute("DO $$ BEGIN RAISE EXCEPTION 'flaky' USING ERRCODE = '40001'; END $$;")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_retry_transient_errors() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_retry(RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+    });
+    adapter.setup_schema().unwrap();
+
+    let migration = FlakyMigration(Cell::new(0));
+    adapter.apply_migration(&migration).unwrap();
+    assert_eq!(migration.0.get(), 3);
+}
+
+#[test]
+fn test_retry_backoff() {
+    let policy = RetryPolicy::new(10);
+    assert_eq!(policy.backoff(1), Duration::from_millis(100));
+    assert_eq!(policy.backoff(3), Duration::from_millis(400));
+    assert_eq!(policy.backoff(40), Duration::from_secs(10));
+}
+
+struct DisconnectingMigration(Cell<u32>);
+migration!(DisconnectingMigration, 70, "disconnecting migration");
+
+impl PostgresMigration for DisconnectingMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        self.0.set(self.0.get() + 1);
+        if self.0.get() == 1 {
+            transaction.batch_execute("SELECT pg_terminate_backend(pg_backend_pid());")?;
+        }
+        transaction.batch_execute("CREATE TABLE resumed (id BIGINT);")
+    }
+}
+
+#[test]
+fn test_reconnect_and_resume() {
+    let connections = Cell::new(0);
+    let mut adapter = PostgresAdapter::reconnecting(|| {
+        connections.set(connections.get() + 1);
+        // Each connection has its own pg_temp schema, so the metadata table must be recreated.
+        let mut client = make_database_connection();
+        PostgresAdapter::new(&mut client).setup_schema().unwrap();
+        Ok(client)
+    }).unwrap();
+
+    let migration = DisconnectingMigration(Cell::new(0));
+    adapter.apply_migration(&migration).unwrap();
+    assert_eq!(migration.0.get(), 2);
+    assert_eq!(adapter.current_version().unwrap(), Some(70));
+    drop(adapter);
+    assert_eq!(connections.get(), 2);
+}
+
+#[test]
+fn test_reconnecting_with_metadata_table_name() {
+    let mut adapter = PostgresAdapter::reconnecting(|| {
+        let mut client = make_database_connection();
+        PostgresAdapter::with_metadata_table(&mut client, "migrations").setup_schema().unwrap();
+        Ok(client)
+    }).unwrap().with_metadata_table_name("migrations");
+
+    let migration = DisconnectingMigration(Cell::new(0));
+    adapter.apply_migration(&migration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(70));
+}
+
+#[test]
+fn test_export_metadata() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&SqlMigration::new(11, "say \"hi\"", "SELECT 1;", None)).unwrap();
+
+    let json = adapter.export_metadata().unwrap();
+    assert!(json.starts_with("{\"migrations\":[{\"version\":10,\"description\":\"first migration\",\"applied_at\":\""));
+    assert!(json.contains("\"applied_by\":\"postgres\",\"checksum\":null}"));
+    assert!(json.contains("{\"version\":11,\"description\":\"say \\\"hi\\\"\""));
+
+    let mut written = vec![];
+    adapter.export_metadata_to(&mut written).unwrap();
+    assert_eq!(written, json.as_bytes());
+}
+
+#[test]
+fn test_import_from_flyway() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE flyway_schema_history (installed_rank INT PRIMARY KEY, version VARCHAR(50), \
+         description VARCHAR(200), type VARCHAR(20), script VARCHAR(1000), checksum INT, \
+         installed_by VARCHAR(100), installed_on TIMESTAMP DEFAULT now(), execution_time INT, success BOOLEAN);
+         INSERT INTO flyway_schema_history (installed_rank, version, description, installed_by, success) VALUES
+             (1, '1', 'create users', 'flyway', true),
+             (2, '2', 'broken', 'flyway', false),
+             (3, NULL, 'refresh views', 'flyway', true),
+             (4, '3', 'create products', 'flyway', true);"
+    ).unwrap();
+
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    assert_eq!(adapter.import_from_flyway("flyway_schema_history").unwrap(), vec![1, 3]);
+    assert_eq!(adapter.import_from_flyway("flyway_schema_history").unwrap(), vec![]);
+
+    let log = adapter.migration_log().unwrap();
+    assert_eq!(log[1].description, Some("create products".to_owned()));
+    assert_eq!(log[1].applied_by, Some("flyway".to_owned()));
+    assert!(log[1].applied_at.is_some());
+}
+
+#[test]
+fn test_import_from_flyway_with_checksums() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE flyway_schema_history (installed_rank INT PRIMARY KEY, version VARCHAR(50), \
+         description VARCHAR(200), type VARCHAR(20), script VARCHAR(1000), checksum INT, \
+         installed_by VARCHAR(100), installed_on TIMESTAMP DEFAULT now(), execution_time INT, success BOOLEAN);
+         INSERT INTO flyway_schema_history (installed_rank, version, description, checksum, installed_by, success) VALUES
+             (1, '1', 'create users', -1133721271, 'flyway', true);"
+    ).unwrap();
+
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    assert_eq!(adapter.import_from_flyway_with_checksums("flyway_schema_history").unwrap(), vec![1]);
+
+    let migration = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT PRIMARY KEY);\r\n", None)
+        .with_checksum_algorithm(ChecksumAlgorithm::Crc32);
+    assert_eq!(adapter.checksum_mismatches(vec![&migration as &dyn PostgresMigration]).unwrap(), vec![]);
+
+    let migration = migration.with_checksum_algorithm(ChecksumAlgorithm::Sha256);
+    assert_eq!(adapter.checksum_mismatches(vec![&migration as &dyn PostgresMigration]).unwrap().len(), 1);
+}
+
+#[test]
+fn test_checksum_algorithms() {
+    let sql = "CREATE TABLE users (id BIGINT PRIMARY KEY);\n";
+    assert_eq!(ChecksumAlgorithm::Md5.checksum(sql), "b35d1956c67a9a3335959d0bdba3e9e7");
+    assert_eq!(ChecksumAlgorithm::Crc32.checksum(sql), "-1133721271");
+    assert_eq!(ChecksumAlgorithm::Crc32.checksum(&format!("\u{feff}{}", sql)), "-1133721271");
+    assert_eq!(ChecksumAlgorithm::default().checksum(sql), sha256_hex(sql.as_bytes()));
+
+    let salt = "v2".to_owned();
+    let migration = SqlMigration::new(1, "create users", sql, None)
+        .with_checksum_algorithm(ChecksumAlgorithm::custom(move |sql| format!("{}:{}", salt, sql.len())));
+    assert_eq!(migration.checksum(), Some("v2:44".to_owned()));
+
+    let migrations = load_sql_migrations_with_checksum_algorithm("tests/migrations", ChecksumAlgorithm::Md5).unwrap();
+    assert!(migrations.iter().all(|migration| migration.checksum().map(|checksum| checksum.len()) == Some(32)));
+}
+
+#[test]
+fn test_import_from_diesel() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE __diesel_schema_migrations (version VARCHAR(50) PRIMARY KEY, \
+         run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP);
+         INSERT INTO __diesel_schema_migrations (version) VALUES ('20240601123000'), ('2024-06-02-080000');"
+    ).unwrap();
+
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    assert_eq!(adapter.import_from_diesel().unwrap(), vec![20240601123000, 20240602080000]);
+    assert!(adapter.applied_at(20240601123000).unwrap().is_some());
+}
+
+#[test]
+fn test_import_from_sqlx() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE _sqlx_migrations (version BIGINT PRIMARY KEY, description TEXT NOT NULL, \
+         installed_on TIMESTAMPTZ NOT NULL DEFAULT now(), success BOOLEAN NOT NULL, checksum BYTEA NOT NULL, \
+         execution_time BIGINT NOT NULL);
+         INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time) VALUES
+             (1, 'create users', true, '\\x0102ff', 10),
+             (2, 'broken', false, '\\x00', 10);"
+    ).unwrap();
+
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    assert_eq!(adapter.import_from_sqlx(true).unwrap(), vec![1]);
+
+    let migration = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None);
+    let mismatches = adapter.checksum_mismatches(vec![&migration as &dyn PostgresMigration]).unwrap();
+    assert_eq!(mismatches[0].expected, "0102ff");
+}
+
+#[test]
+fn test_record_versions() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+
+    assert_eq!(adapter.record_versions(&[30, 10, 20]).unwrap(), vec![20, 30]);
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10, 20, 30]);
+    assert_eq!(adapter.migration_log().unwrap()[1].description, None);
+}
+
+struct TimestampedMigration;
+timestamp_migration!(TimestampedMigration, "2024-06-01 12:30", "timestamped migration");
+
+impl PostgresMigration for TimestampedMigration {}
+
+#[test]
+fn test_timestamp_versions() {
+    use schemamama::Migration;
+
+    assert_eq!(TimestampedMigration.version(), 202406011230);
+    assert_eq!(TimestampedMigration.description(), "timestamped migration");
+    assert_eq!(timestamp_version("2024-06-01T12:30:15"), 20240601123015);
+    assert_eq!(timestamp_version("202406011230"), 202406011230);
+}
+
+#[test]
+#[should_panic]
+fn test_invalid_timestamp_version() {
+    timestamp_version("2024-13-01 12:30");
+}
+
+#[test]
+fn test_destructive_guard() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).guard_destructive(true);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+
+    assert!(matches!(
+        adapter.revert_migration(&FirstMigration),
+        Err(SchemamamaPostgresError::DestructiveNotAllowed { version: 10 })
+    ));
+    assert_eq!(adapter.current_version().unwrap(), Some(10));
+
+    let mut adapter = adapter.allow_destructive();
+    adapter.revert_migration(&FirstMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), None);
+}
+
+#[test]
+fn test_version_named() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&SqlMigration::new(7, "add_billing_tables", "SELECT 1;", None)).unwrap();
+
+    let migrations: Vec<&dyn PostgresMigration> = vec![&FirstMigration, &SecondMigration];
+    assert_eq!(adapter.version_named(migrations.clone(), "second migration").unwrap(), 20);
+    assert_eq!(adapter.version_named(migrations.clone(), "add_billing_tables").unwrap(), 7);
+    assert!(matches!(
+        adapter.version_named(migrations, "missing"),
+        Err(SchemamamaPostgresError::UnknownMigrationName { .. })
+    ));
+}
+
+#[test]
+fn test_script_pending() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let users = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None);
+    let products = SqlMigration::new(2, "create products", "CREATE TABLE products (id BIGINT);", None);
+    adapter.apply_migration(&users).unwrap();
+
+    let mut script = vec![];
+    let versions = adapter.script_pending(vec![&users as &dyn PostgresMigration, &products], &mut script).unwrap();
+    assert_eq!(versions, vec![2]);
+    let script = String::from_utf8(script).unwrap();
+    assert!(script.starts_with("-- up 2: create products\nBEGIN;\nCREATE TABLE products (id BIGINT);\nINSERT INTO \"schemamama\""));
+    assert!(!script.contains("users"));
+
+    assert!(matches!(
+        adapter.script_pending(vec![&FirstMigration as &dyn PostgresMigration], &mut vec![]),
+        Err(SchemamamaPostgresError::NoSqlRepresentation { version: 10 })
+    ));
+}
+
+#[test]
+fn test_backfill() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE accounts (id BIGINT PRIMARY KEY, email TEXT, email_lower TEXT);
+         INSERT INTO accounts (id, email) SELECT n, 'User' || n || '@Example.com' FROM generate_series(1, 25) n;"
+    ).unwrap();
+
+    let total = Backfill::new(
+        "WITH batch AS (SELECT id FROM accounts WHERE id > $1 ORDER BY id LIMIT $2) \
+         UPDATE accounts SET email_lower = lower(email) FROM batch WHERE accounts.id = batch.id \
+         RETURNING accounts.id"
+    ).with_batch_size(10).run(&mut client).unwrap();
+
+    assert_eq!(total, 25);
+    let row = client.query_one("SELECT count(*) FROM accounts WHERE email_lower = lower(email);", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 25);
+}
+
+#[test]
+fn test_keepalive() {
+    let mut client = make_database_connection();
+    {
+        let mut adapter = PostgresAdapter::new(&mut client).with_keepalive(Duration::from_secs(30));
+        adapter.setup_schema().unwrap();
+        let migration = SqlMigration::new(
+            1,
+            "record keepalive",
+            "CREATE TABLE keepalive AS SELECT current_setting('tcp_keepalives_idle') AS idle;",
+            None
+        );
+        adapter.apply_migration(&migration).unwrap();
+    }
+
+    let row = client.query_one("SELECT idle, current_setting('tcp_keepalives_idle') FROM keepalive;", &[]).unwrap();
+    assert_eq!(row.get::<_, String>(0), "30");
+    assert_ne!(row.get::<_, String>(1), "30");
+}
+
+struct SeedCountriesMigration;
+migration!(SeedCountriesMigration, 80, "seed countries");
+
+impl PostgresMigration for SeedCountriesMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute("CREATE TABLE countries (code TEXT PRIMARY KEY, name TEXT, motto TEXT);")?;
+        copy_csv(transaction, "countries", &["code", "name"], "code,name\nfr,France\nde,\"Germany, Federal Republic\"\n", true)?;
+        copy_rows(transaction, "countries", &["code", "name", "motto"], vec![
+            vec![Some("it"), Some("Italy"), None],
+            vec![Some("es"), Some("Spain \"España\""), Some("")],
+        ]).map(|_| ())
+    }
+}
+
+#[test]
+fn test_copy_helpers() {
+    let mut client = make_database_connection();
+    {
+        let mut adapter = PostgresAdapter::new(&mut client);
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&SeedCountriesMigration).unwrap();
+    }
+
+    let rows = client.query("SELECT code, name, motto FROM countries ORDER BY code;", &[]).unwrap();
+    let rows: Vec<(String, String, Option<String>)> = rows.iter().map(|r| (r.get(0), r.get(1), r.get(2))).collect();
+    assert_eq!(rows, vec![
+        ("de".to_owned(), "Germany, Federal Republic".to_owned(), None),
+        ("es".to_owned(), "Spain \"España\"".to_owned(), Some("".to_owned())),
+        ("fr".to_owned(), "France".to_owned(), None),
+        ("it".to_owned(), "Italy".to_owned(), None),
+    ]);
+}
+
+#[test]
+fn test_create_index_concurrently() {
+    let mut client = make_database_connection();
+    client.batch_execute("CREATE TABLE people (id BIGINT, email TEXT); INSERT INTO people VALUES (1, 'a'), (2, 'a');").unwrap();
+
+    let unique = IndexOptions { unique: true, ..IndexOptions::default() };
+    assert!(create_index_concurrently(&mut client, "people", &["email"], &unique).is_err());
+    let row = client.query_one("SELECT to_regclass('people_email_idx')::text;", &[]).unwrap();
+    assert_eq!(row.get::<_, Option<String>>(0), None);
+
+    let name = create_index_concurrently(&mut client, "people", &["email"], &IndexOptions::default()).unwrap();
+    assert_eq!(name, "people_email_idx");
+    create_index_concurrently(&mut client, "people", &["email"], &IndexOptions::default()).unwrap();
+    let row = client.query_one("SELECT count(*) FROM pg_indexes WHERE tablename = 'people';", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 1);
+}
+
+#[test]
+fn test_expand_contract_helpers() {
+    let mut client = make_database_connection();
+    client.batch_execute("CREATE TABLE people (id BIGINT, name TEXT); INSERT INTO people VALUES (1, 'ada'), (2, 'bob');").unwrap();
+
+    add_column_with_backfill(&mut client, "people", "upper_name", "TEXT", "upper(name)", "id", 1).unwrap();
+    let row = client.query_one("SELECT string_agg(upper_name, ',' ORDER BY id) FROM people;", &[]).unwrap();
+    assert_eq!(row.get::<_, String>(0), "ADA,BOB");
+    assert!(client.batch_execute("INSERT INTO people (id, name) VALUES (3, 'eve');").is_err());
+    let row = client.query_one("SELECT count(*) FROM pg_constraint WHERE conrelid = 'people'::regclass;", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 0);
+
+    rename_table_with_shim(&mut client, "people", "persons").unwrap();
+    client.batch_execute("INSERT INTO people VALUES (3, 'eve', 'EVE');").unwrap();
+    let row = client.query_one("SELECT count(*) FROM persons;", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 3);
+
+    drop_rename_shim(&mut client, "people").unwrap();
+    assert!(client.batch_execute("SELECT * FROM people;").is_err());
+}
+
+#[test]
+fn test_lint_locks() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE events (id BIGINT); INSERT INTO events SELECT generate_series(1, 1000); ANALYZE events;
+         CREATE TABLE tiny (id BIGINT); ANALYZE tiny;"
+    ).unwrap();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let migration = SqlMigration::new(
+        1,
+        "alter events",
+        "ALTER TABLE tiny ADD COLUMN name TEXT; CREATE INDEX ON events (id); ALTER TABLE events ADD COLUMN name TEXT;",
+        None
+    );
+
+    let warnings = adapter.lint_locks(vec![&migration as &dyn PostgresMigration, &FirstMigration], 100).unwrap();
+    assert_eq!(warnings, vec![LockWarning {
+        version: 1,
+        statement: "ALTER TABLE events ADD COLUMN name TEXT".to_owned(),
+        table: "events".to_owned(),
+        estimated_rows: 1000,
+    }]);
+
+    adapter.apply_migration(&migration).unwrap();
+    assert!(adapter.lint_locks(vec![&migration as &dyn PostgresMigration], 100).unwrap().is_empty());
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_with_migrated_db() {
+    let migrations: Vec<Box<dyn PostgresMigration>> = vec![Box::new(FirstMigration), Box::new(SecondMigration)];
+    let schema = schemamama_postgres::with_migrated_db("postgres://postgres@localhost", migrations, |client| {
+        let row = client.query_one("SELECT max(version) FROM schemamama;", &[]).unwrap();
+        assert_eq!(row.get::<_, Option<i64>>(0), Some(20));
+        current_schema_name(client)
+    }).unwrap();
+    assert!(schema.starts_with("pg_temp"));
+}
+
+#[cfg(feature = "testcontainers")]
+#[test]
+fn test_postgres_container() {
+    let migrations: Vec<Box<dyn PostgresMigration>> = vec![Box::new(FirstMigration)];
+    let mut container = schemamama_postgres::PostgresContainer::start(migrations).unwrap();
+    let row = container.client().query_one("SELECT count(*) FROM first;", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 0);
+    assert!(Client::connect(container.url(), NoTls).is_ok());
+}
+
+#[test]
+fn test_schema_snapshot() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let migration = SqlMigration::new(
+        1,
+        "create tables",
+        "CREATE TABLE users (id BIGINT PRIMARY KEY, email TEXT NOT NULL UNIQUE, active BOOLEAN DEFAULT true);
+         CREATE TABLE posts (id BIGINT, user_id BIGINT REFERENCES users (id));
+         CREATE INDEX posts_user_id_idx ON posts (user_id);",
+        None
+    );
+    adapter.apply_migration(&migration).unwrap();
+
+    assert_eq!(adapter.schema_snapshot().unwrap(), "\
+table posts
+  column id bigint
+  column user_id bigint
+  constraint posts_user_id_fkey FOREIGN KEY (user_id) REFERENCES users(id)
+  index CREATE INDEX posts_user_id_idx ON posts USING btree (user_id)
+table users
+  column id bigint NOT NULL
+  column email text NOT NULL
+  column active boolean DEFAULT true
+  constraint users_email_key UNIQUE (email)
+  constraint users_pkey PRIMARY KEY (id)
+  index CREATE UNIQUE INDEX users_email_key ON users USING btree (email)
+  index CREATE UNIQUE INDEX users_pkey ON users USING btree (id)
+");
+}
+
+#[test]
+fn test_verify_reversibility() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let leaky = SqlMigration::new(
+        1,
+        "create leaky",
+        "CREATE TABLE leaky (id BIGINT); CREATE TABLE IF NOT EXISTS leftover (id BIGINT);",
+        Some("DROP TABLE leaky;".to_owned())
+    );
+
+    let irreversible = adapter.verify_reversibility(vec![&FirstMigration as &dyn PostgresMigration, &leaky]).unwrap();
+    assert_eq!(irreversible, vec![IrreversibleMigration {
+        version: 1,
+        expected: "".to_owned(),
+        actual: "table leftover\n  column id bigint\n".to_owned(),
+    }]);
+    assert_eq!(adapter.current_version().unwrap(), Some(10));
+}
+
+struct TunedMigration;
+migration!(TunedMigration, 90, "tuned migration");
+
+impl PostgresMigration for TunedMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute("CREATE TABLE tuned AS SELECT current_setting('work_mem') AS work_mem;")
+    }
+
+    fn session_params(&self) -> &[(&str, &str)] {
+        &[("work_mem", "16MB"), ("statement_timeout", "5s")]
+    }
+}
+
+#[test]
+fn test_session_params() {
+    let mut client = make_database_connection();
+    client.batch_execute("SET work_mem TO '4MB';").unwrap();
+    {
+        let mut adapter = PostgresAdapter::new(&mut client).with_statement_timeout(Duration::from_secs(1));
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&TunedMigration).unwrap();
+    }
+
+    let row = client.query_one("SELECT work_mem FROM tuned;", &[]).unwrap();
+    assert_eq!(row.get::<_, String>(0), "16MB");
+    let row = client.query_one("SELECT current_setting('work_mem'), current_setting('statement_timeout');", &[]).unwrap();
+    assert_eq!((row.get::<_, String>(0), row.get::<_, String>(1)), ("4MB".to_owned(), "0".to_owned()));
+}
+
+#[test]
+fn test_dirty_migration() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+
+    // Without the `first` table, the index cannot be created; the failure is reported and no
+    // marker is left behind.
+    assert!(adapter.apply_migration(&ConcurrentIndexMigration).is_err());
+    assert_eq!(adapter.dirty_version().unwrap(), None);
+
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&ConcurrentIndexMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(30));
+    assert_eq!(adapter.dirty_version().unwrap(), None);
+    drop(adapter);
+
+    // Simulate a crash halfway through reverting the index migration.
+    client.batch_execute("UPDATE schemamama SET dirty = true WHERE version = 30;").unwrap();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    assert_eq!(adapter.dirty_version().unwrap(), Some(30));
+    assert_eq!(adapter.current_version().unwrap(), Some(10));
+    assert!(matches!(
+        adapter.apply_migration(&SecondMigration),
+        Err(SchemamamaPostgresError::Dirty { version: 30 })
+    ));
+
+    assert_eq!(adapter.resolve_dirty(true).unwrap(), Some(30));
+    adapter.apply_migration(&SecondMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(30));
+}
+
+#[test]
+fn test_history_table() {
+    let mut client = make_database_connection();
+    {
+        let mut adapter = PostgresAdapter::new(&mut client).with_history_table("schemamama_history");
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&FirstMigration).unwrap();
+        assert!(adapter.apply_migration(&FailingMigration).is_err());
+        adapter.revert_migration(&FirstMigration).unwrap();
+    }
+
+    let rows = client.query(
+        "SELECT version, direction, outcome, error IS NULL, occurred_by = current_user, duration_ms >= 0 \
+         FROM schemamama_history ORDER BY id;",
+        &[]
+    ).unwrap();
+    let events: Vec<(i64, String, String, bool, bool, bool)> = rows.iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2), r.get(3), r.get(4), r.get(5)))
+        .collect();
+    assert_eq!(events, vec![
+        (10, "apply".to_owned(), "succeeded".to_owned(), true, true, true),
+        (40, "apply".to_owned(), "failed".to_owned(), false, true, true),
+        (10, "revert".to_owned(), "succeeded".to_owned(), true, true, true),
+    ]);
+}
+
+#[test]
+fn test_repeatable_migrations() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    let users = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None);
+    adapter.apply_migration(&users).unwrap();
+
+    let repeatable = load_repeatable_migrations("tests/migrations").unwrap();
+    assert_eq!(repeatable.iter().map(|migration| migration.name()).collect::<Vec<_>>(), vec!["user_count"]);
+    assert_eq!(adapter.apply_repeatable(&repeatable).unwrap(), vec!["user_count"]);
+    assert!(adapter.apply_repeatable(&repeatable).unwrap().is_empty());
+
+    let changed = RepeatableMigration::new(
+        "user_count",
+        "CREATE OR REPLACE VIEW user_count AS SELECT count(*) AS users, 1 AS revision FROM users;"
+    );
+    assert_eq!(adapter.apply_repeatable(vec![&changed]).unwrap(), vec!["user_count"]);
+    drop(adapter);
+
+    let row = client.query_one("SELECT revision FROM user_count;", &[]).unwrap();
+    assert_eq!(row.get::<_, i32>(0), 1);
+}
+
+#[test]
+fn test_sql_callbacks() {
+    let mut client = make_database_connection();
+    {
+        let callbacks = load_sql_callbacks("tests/callbacks").unwrap();
+        assert!(callbacks.after_each_migrate.is_some());
+        let mut adapter = PostgresAdapter::new(&mut client).with_sql_callbacks(callbacks);
+        adapter.setup_schema().unwrap();
+        adapter.before_migrate().unwrap();
+        {
+            let mut migrator = Migrator::new(&mut adapter);
+            migrator.register(Box::new(FirstMigration));
+            migrator.register(Box::new(SecondMigration));
+            migrator.up(None).unwrap();
+        }
+        assert!(adapter.apply_migration(&FailingMigration).is_err());
+        adapter.after_migrate().unwrap();
+    }
+
+    let rows = client.query("SELECT event FROM runs;", &[]).unwrap();
+    let events: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
+    assert_eq!(events, vec!["before", "each", "each", "after"]);
+    assert!(load_sql_callbacks("tests/migrations").unwrap().before_migrate.is_none());
+}
+
+#[test]
+fn test_sql_placeholders() {
+    let mut placeholders = HashMap::new();
+    placeholders.insert("table".to_owned(), "accounts".to_owned());
+    let error = load_sql_migrations_with_placeholders("tests/placeholders", &placeholders).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("\"tablespace\""));
+
+    placeholders.insert("tablespace".to_owned(), "TABLESPACE pg_default".to_owned());
+    let migrations = load_sql_migrations_with_placeholders("tests/placeholders", &placeholders).unwrap();
+    assert_eq!(migrations[0].up_sql(), Some("CREATE TABLE accounts (id BIGINT) TABLESPACE pg_default;\n"));
+}
+
+#[test]
+fn test_plan() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    let sql = SqlMigration::new(25, "say \"hi\"", "SELECT 1;", None);
+    let migrations = vec![
+        &FirstMigration as &dyn PostgresMigration, &SecondMigration, &sql, &ConcurrentIndexMigration, &FailingMigration,
+    ];
+
+    let plan = adapter.plan(migrations, Some(30)).unwrap();
+    assert_eq!(plan.migrations.iter().map(|migration| migration.version).collect::<Vec<_>>(), vec![20, 25, 30]);
+    assert_eq!(adapter.current_version().unwrap(), Some(10));
+    assert_eq!(plan.to_json(), format!(
+        "{{\"current_version\":10,\"target_version\":30,\"migrations\":[\
+         {{\"version\":20,\"description\":\"second migration\",\"transactional\":true,\"checksum\":null}},\
+         {{\"version\":25,\"description\":\"say \\\"hi\\\"\",\"transactional\":true,\"checksum\":\"{}\"}},\
+         {{\"version\":30,\"description\":\"concurrent index migration\",\"transactional\":false,\"checksum\":null}}]}}",
+        sql.checksum().unwrap()
+    ));
+}
+
+#[test]
+fn test_compare_with() {
+    let mut staging_client = make_database_connection();
+    let mut production_client = make_database_connection();
+    let mut staging = PostgresAdapter::new(&mut staging_client);
+    let mut production = PostgresAdapter::new(&mut production_client);
+    staging.setup_schema().unwrap();
+    production.setup_schema().unwrap();
+
+    let users = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT);", None);
+    let edited_users = SqlMigration::new(1, "create users", "CREATE TABLE users (id BIGINT, name TEXT);", None);
+    assert!(staging.compare_with(&mut production).unwrap().is_empty());
+
+    staging.apply_migration(&users).unwrap();
+    staging.apply_migration(&FirstMigration).unwrap();
+    production.apply_migration(&edited_users).unwrap();
+    production.apply_migration(&SecondMigration).unwrap();
+    assert_eq!(staging.compare_with(&mut production).unwrap(), Drift {
+        only_here: vec![10],
+        only_there: vec![20],
+        checksum_mismatches: vec![DriftedChecksum {
+            version: 1,
+            here: users.checksum().unwrap(),
+            there: edited_users.checksum().unwrap(),
+        }],
+    });
+}
+
+#[test]
+fn test_up_with_shadow() {
+    let mut client = make_database_connection();
+    let mut shadow = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration, &FailingMigration];
+    let result = adapter.up_with_shadow(&mut shadow, migrations.clone(), None);
+    assert!(matches!(result, Err(SchemamamaPostgresError::Shadow(ref error))
+        if matches!(**error, SchemamamaPostgresError::Migration { version: 40, .. })));
+    assert_eq!(adapter.current_version().unwrap(), None);
+
+    assert_eq!(adapter.up_with_shadow(&mut shadow, migrations, Some(20)).unwrap(), vec![10, 20]);
+    assert_eq!(adapter.current_version().unwrap(), Some(20));
+}
+
+struct BrokenSeedMigration;
+migration!(BrokenSeedMigration, 45, "broken seed");
+
+impl PostgresMigration for BrokenSeedMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.execute("INSERT INTO missing_table VALUES (1);", &[]).map(|_| ())
+    }
+
+    fn tags(&self) -> &[&str] {
+        &["seed"]
+    }
+}
+
+#[test]
+fn test_up_with_shadow_selection() {
+    let mut client = make_database_connection();
+    let mut shadow = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_skipped_versions(vec![40]).without_tags(vec!["seed"]);
+    adapter.setup_schema().unwrap();
+
+    // The failing migrations are skipped or passed over, on the shadow as on the real database.
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &FailingMigration, &BrokenSeedMigration];
+    assert_eq!(adapter.up_with_shadow(&mut shadow, migrations, None).unwrap(), vec![10, 40]);
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10, 40]);
+}
+
+#[test]
+fn test_notify() {
+    let mut listener = make_database_connection();
+    listener.batch_execute("LISTEN schema_changes;").unwrap();
+
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_notify("schema_changes");
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    assert!(adapter.apply_migration(&FailingMigration).is_err());
+    adapter.revert_migration(&FirstMigration).unwrap();
+
+    // Wait for both notifications to arrive.
+    let mut payloads = vec![];
+    let mut notifications = listener.notifications();
+    let mut iter = notifications.timeout_iter(Duration::from_secs(5));
+    while payloads.len() < 2 {
+        payloads.push(iter.next().unwrap().unwrap().payload().to_owned());
+    }
+    assert_eq!(payloads, vec!["10", "10"]);
+}
+
+#[test]
+fn test_lock_behavior() {
+    let mut holder = make_database_connection();
+    holder.batch_execute("SELECT pg_advisory_lock(4343);").unwrap();
+
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client)
+        .with_advisory_lock(4343)
+        .with_lock_behavior(LockBehavior::FailFast);
+    adapter.setup_schema().unwrap();
+    assert!(matches!(
+        adapter.apply_migration(&FirstMigration),
+        Err(SchemamamaPostgresError::AlreadyLocked { key: 4343 })
+    ));
+
+    let mut adapter = adapter.with_lock_behavior(LockBehavior::Timeout(Duration::from_millis(300)));
+    let started = std::time::Instant::now();
+    assert!(matches!(
+        adapter.apply_migration(&FirstMigration),
+        Err(SchemamamaPostgresError::AlreadyLocked { key: 4343 })
+    ));
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    holder.batch_execute("SELECT pg_advisory_unlock(4343);").unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(10));
+}
+
+#[test]
+fn test_up_or_wait_for_peer() {
+    // The instances share a metadata table in a real schema, as their temporary schemas are
+    // private to each connection.
+    let mut peer = make_database_connection();
+    peer.batch_execute("DROP SCHEMA IF EXISTS peer_test CASCADE; SELECT pg_advisory_lock(4444);").unwrap();
+    let mut peer_adapter = PostgresAdapter::with_metadata_table(&mut peer, "peer_test.schemamama");
+    peer_adapter.setup_schema().unwrap();
+    peer_adapter.apply_migration(&FirstMigration).unwrap();
+    drop(peer_adapter);
+    let release_later = |mut peer: Client| std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        peer.batch_execute("SELECT pg_advisory_unlock(4444);").unwrap();
+        peer
+    });
+
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::with_metadata_table(&mut client, "peer_test.schemamama").with_advisory_lock(4444);
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration];
+
+    let releasing = release_later(peer);
+    assert!(matches!(
+        adapter.up_or_wait_for_peer(migrations.clone(), None),
+        Err(SchemamamaPostgresError::PeerIncomplete { ref pending }) if *pending == vec![20]
+    ));
+    let mut peer = releasing.join().unwrap();
+    assert_eq!(adapter.up_or_wait_for_peer(migrations.clone(), None).unwrap(), PeerOutcome::Migrated(vec![20]));
+
+    peer.batch_execute("SELECT pg_advisory_lock(4444);").unwrap();
+    let releasing = release_later(peer);
+    assert_eq!(adapter.up_or_wait_for_peer(migrations, None).unwrap(), PeerOutcome::WaitedForPeer);
+    let mut peer = releasing.join().unwrap();
+
+    // Migrations passed over by the tags are not expected of the peer.
+    let mut adapter = adapter.without_tags(vec!["seed"]);
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration, &DemoDataMigration];
+    peer.batch_execute("SELECT pg_advisory_lock(4444);").unwrap();
+    let releasing = release_later(peer);
+    assert_eq!(adapter.up_or_wait_for_peer(migrations.clone(), None).unwrap(), PeerOutcome::WaitedForPeer);
+    let mut peer = releasing.join().unwrap();
+    assert_eq!(adapter.up_or_wait_for_peer(migrations, None).unwrap(), PeerOutcome::Migrated(vec![]));
+    drop(adapter);
+
+    // A caching adapter reads the versions the peer applied, not the ones it cached before.
+    let mut adapter = PostgresAdapter::with_metadata_table(&mut client, "peer_test.schemamama")
+        .with_advisory_lock(4444)
+        .cache_migrated_versions();
+    assert_eq!(adapter.migrated_versions().unwrap().len(), 2);
+    peer.batch_execute("SELECT pg_advisory_lock(4444);").unwrap();
+    PostgresAdapter::with_metadata_table(&mut peer, "peer_test.schemamama").apply_migration(&SlowMigration).unwrap();
+    let releasing = release_later(peer);
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration, &SlowMigration];
+    assert_eq!(adapter.up_or_wait_for_peer(migrations, None).unwrap(), PeerOutcome::WaitedForPeer);
+    let mut peer = releasing.join().unwrap();
+    peer.batch_execute("DROP SCHEMA peer_test CASCADE;").unwrap();
+}
+
+#[test]
+fn test_missing_metadata_table() {
+    let mut client = make_database_connection();
+    {
+        let mut transaction = client.transaction().unwrap();
+        let mut adapter = PostgresAdapter::new(&mut transaction);
+        assert_eq!(adapter.current_version().unwrap(), None);
+        assert!(adapter.migrated_versions().unwrap().is_empty());
+        assert_eq!(adapter.applied_at(10).unwrap(), None);
+        assert!(adapter.migration_log().unwrap().is_empty());
+        assert_eq!(adapter.dirty_version().unwrap(), None);
+        assert_eq!(adapter.resolve_dirty(true).unwrap(), None);
+        assert_eq!(adapter.export_metadata().unwrap(), "{\"migrations\":[]}");
+        let migrations = vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration];
+        assert_eq!(adapter.version_named(migrations.clone(), "second migration").unwrap(), 20);
+        assert!(matches!(
+            adapter.version_named(migrations.clone(), "third migration"),
+            Err(SchemamamaPostgresError::UnknownMigrationName { .. })
+        ));
+        let status = adapter.status(migrations.clone()).unwrap();
+        assert!(status.applied.is_empty());
+        assert_eq!(status.pending.iter().map(|m| m.version).collect::<Vec<_>>(), vec![10, 20]);
+        adapter.verify_checksums(migrations).unwrap();
+        // The transaction is still usable.
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&FirstMigration).unwrap();
+    }
+
+    let mut adapter = PostgresAdapter::new(&mut client).ensure_schema();
+    assert_eq!(adapter.current_version().unwrap(), None);
+    adapter.apply_migration(&FirstMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(10));
+}
+
+#[test]
+fn test_drop_schema_and_reset() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_history_table("schemamama_history");
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+
+    assert!(matches!(
+        adapter.reset(vec![&FirstMigration as &dyn PostgresMigration]),
+        Err(SchemamamaPostgresError::UnknownVersions { ref versions }) if *versions == vec![20]
+    ));
+    assert_eq!(adapter.reset(vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration]).unwrap(), vec![20, 10]);
+    assert_eq!(adapter.current_version().unwrap(), None);
+    drop(adapter);
+
+    let row = client.query_one(
+        "SELECT to_regclass('first') IS NULL AND to_regclass('schemamama') IS NULL \
+         AND to_regclass('schemamama_history') IS NULL;",
+        &[]
+    ).unwrap();
+    assert!(row.get::<_, bool>(0));
+
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.drop_schema().unwrap();
+    assert_eq!(adapter.current_version().unwrap(), None);
+}
+
+#[test]
+fn test_unrecord_version() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+
+    assert!(adapter.unrecord_version(10).unwrap());
+    assert!(!adapter.unrecord_version(10).unwrap());
+    assert_eq!(adapter.current_version().unwrap(), None);
+    drop(adapter);
+
+    // `down` was not run.
+    let row = client.query_one("SELECT to_regclass('first') IS NOT NULL;", &[]).unwrap();
+    assert!(row.get::<_, bool>(0));
+}
+
+#[test]
+fn test_apply_single() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_out_of_order(OutOfOrder::Reject);
+    adapter.setup_schema().unwrap();
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &SecondMigration, &TunedMigration];
+
+    assert!(matches!(
+        adapter.apply_single(migrations.clone(), 99, false),
+        Err(SchemamamaPostgresError::UnknownVersion { version: 99 })
+    ));
+    assert!(matches!(
+        adapter.apply_single(migrations.clone(), 90, false),
+        Err(SchemamamaPostgresError::SkipsPending { version: 90, ref pending }) if *pending == vec![10, 20]
+    ));
+    assert!(adapter.apply_single(migrations.clone(), 90, true).unwrap());
+    assert!(!adapter.apply_single(migrations.clone(), 90, true).unwrap());
+
+    assert!(matches!(
+        adapter.apply_single(migrations.clone(), 20, false),
+        Err(SchemamamaPostgresError::OutOfOrder { version: 20, latest: 90 })
+    ));
+    assert!(adapter.apply_single(migrations, 20, true).unwrap());
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![20, 90]);
+}
+
+#[test]
+fn test_skipped_versions() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_skipped_versions(vec![40]);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&FailingMigration).unwrap();
+
+    let log = adapter.migration_log().unwrap();
+    assert_eq!(log.iter().map(|record| (record.version, record.skipped)).collect::<Vec<_>>(), vec![(10, false), (40, true)]);
+
+    adapter.revert_migration(&FailingMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(10));
+}
+
+#[test]
+fn test_summary() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).with_skipped_versions(vec![40]);
+    assert_eq!(adapter.summary().unwrap(), MetadataSummary::default());
+
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&FailingMigration).unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+
+    let summary = adapter.summary().unwrap();
+    assert_eq!(summary.current_version, Some(40));
+    assert_eq!(summary.migrated_versions.into_iter().collect::<Vec<_>>(), vec![10, 20, 40]);
+    assert_eq!((summary.skipped, summary.dirty), (1, 0));
+}
+
+#[test]
+fn test_cached_migrated_versions() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).cache_migrated_versions();
+    adapter.setup_schema().unwrap();
+    assert!(adapter.migrated_versions().unwrap().is_empty());
+
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&SecondMigration).unwrap();
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10, 20]);
+
+    adapter.revert_migration(&SecondMigration).unwrap();
+    assert_eq!(adapter.migrated_versions().unwrap().into_iter().collect::<Vec<_>>(), vec![10]);
+
+    assert!(adapter.unrecord_version(10).unwrap());
+    adapter.invalidate_cached_versions();
+    assert!(adapter.migrated_versions().unwrap().is_empty());
+}
+
+#[test]
+fn test_execute_statements() {
+    let mut client = make_database_connection();
+    let sql = "CREATE TABLE orders (id BIGINT); INSERT INTO orders VALUES ('one'); CREATE TABLE lines (id BIGINT);";
+    let error = execute_statements(&mut client, sql).unwrap_err();
+    assert_eq!(error.index, Some(2));
+    assert_eq!(error.statement, "INSERT INTO orders VALUES ('one')");
+    assert!(SchemamamaPostgresError::from(error).to_string().starts_with("statement 2 failed: "));
+
+    let row = client.query_one("SELECT to_regclass('orders') IS NULL;", &[]).unwrap();
+    assert!(row.get::<_, bool>(0));
+
+    let mut transaction = client.transaction().unwrap();
+    execute_statements(&mut transaction, "CREATE TABLE orders (id BIGINT); INSERT INTO orders VALUES (1);").unwrap();
+    transaction.commit().unwrap();
+    let row = client.query_one("SELECT count(*) FROM orders;", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 1);
+
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    match adapter.apply_migration(&StatementsMigration) {
+        Err(SchemamamaPostgresError::Migration { version: 150, statement: Some((2, statement)), .. }) => {
+            assert_eq!(statement, "INSERT INTO lines VALUES ('two')");
+        }
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+struct StatementsMigration;
+migration!(StatementsMigration, 150, "statements");
+
+impl PostgresMigration for StatementsMigration {
+    fn up_ctx(&self, context: &mut MigrationContext<Transaction>) -> Result<(), PostgresError> {
+        context.execute_statements("CREATE TABLE lines (id BIGINT); INSERT INTO lines VALUES ('two');")
+    }
+}
+
+struct ContextMigration;
+migration!(ContextMigration, 110, "context");
+
+impl PostgresMigration for ContextMigration {
+    fn up_ctx(&self, context: &mut MigrationContext<Transaction>) -> Result<(), PostgresError> {
+        assert_eq!(context.schema(), None);
+        let table = context.placeholder("table").unwrap().to_owned();
+        let environment = context.environment().unwrap_or("none").to_owned();
+        context.logger().info("creating the table");
+        context.client().batch_execute(&format!("CREATE TABLE {} (environment TEXT);", table))?;
+        context.client().execute(&*format!("INSERT INTO {} VALUES ($1);", table), &[&environment]).map(|_| ())
+    }
+
+    fn down_ctx(&self, context: &mut MigrationContext<Transaction>) -> Result<(), PostgresError> {
+        let table = context.placeholder("table").unwrap().to_owned();
+        context.client().batch_execute(&format!("DROP TABLE {};", table))
+    }
+}
+
+#[test]
+fn test_migration_context() {
+    let mut client = make_database_connection();
+    {
+        let placeholders = vec![("table".to_owned(), "contexts".to_owned())].into_iter().collect();
+        let mut adapter = PostgresAdapter::new(&mut client).with_environment("dev").with_placeholders(placeholders);
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&ContextMigration).unwrap();
+    }
+    let row = client.query_one("SELECT environment FROM contexts;", &[]).unwrap();
+    assert_eq!(row.get::<_, String>(0), "dev");
+
+    {
+        let placeholders = vec![("table".to_owned(), "contexts".to_owned())].into_iter().collect();
+        let mut adapter = PostgresAdapter::new(&mut client).with_placeholders(placeholders);
+        adapter.revert_migration(&ContextMigration).unwrap();
+    }
+    let row = client.query_one("SELECT to_regclass('contexts') IS NULL;", &[]).unwrap();
+    assert!(row.get::<_, bool>(0));
+}
+
+struct FeatureFlags<'f> {
+    enabled: &'f [&'f str],
+}
+
+struct FlaggedMigration;
+migration!(FlaggedMigration, 120, "flagged");
+
+impl<'f> PostgresMigration<FeatureFlags<'f>> for FlaggedMigration {
+    fn up_ctx(&self, context: &mut MigrationContext<Transaction, FeatureFlags<'f>>) -> Result<(), PostgresError> {
+        if context.app().enabled.contains(&"flagged") {
+            context.client().batch_execute("CREATE TABLE flagged (id BIGINT);")?;
+        }
+        Ok(())
+    }
+}
+
+struct FlaggedIndexMigration;
+migration!(FlaggedIndexMigration, 130, "flagged index");
+
+impl<'f> PostgresMigration<FeatureFlags<'f>> for FlaggedIndexMigration {
+    fn run_in_transaction(&self) -> bool {
+        false
+    }
+
+    fn up_without_transaction_ctx(&self, context: &mut MigrationContext<Client, FeatureFlags<'f>>) -> Result<(), PostgresError> {
+        if context.app().enabled.contains(&"flagged_index") {
+            context.client().batch_execute("CREATE INDEX CONCURRENTLY flagged_id ON flagged (id);")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_app_context() {
+    let mut client = make_database_connection();
+    let enabled = vec!["flagged", "flagged_index"];
+    let flags = FeatureFlags { enabled: &enabled };
+    {
+        let mut adapter = PostgresAdapter::new(&mut client).with_app_context(&flags);
+        adapter.setup_schema().unwrap();
+        let mut migrator = Migrator::new(&mut adapter);
+        migrator.register(Box::new(FlaggedMigration));
+        migrator.register(Box::new(FlaggedIndexMigration));
+        migrator.register(Box::new(SqlMigration::new(140, "sql", "INSERT INTO flagged VALUES (1);", None)));
+        migrator.up(None).unwrap();
+        assert_eq!(migrator.current_version().unwrap(), Some(140));
+    }
+    let row = client.query_one("SELECT to_regclass('flagged_id') IS NOT NULL, (SELECT count(*) FROM flagged);", &[]).unwrap();
+    assert!(row.get::<_, bool>(0));
+    assert_eq!(row.get::<_, i64>(1), 1);
+}
+
+struct DemoDataMigration;
+migration!(DemoDataMigration, 100, "demo data");
+
+impl PostgresMigration for DemoDataMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute("INSERT INTO first (id) VALUES (1);")
+    }
+
+    fn tags(&self) -> &[&str] {
+        &["seed"]
+    }
+}
+
+#[test]
+fn test_tags() {
+    let mut client = make_database_connection();
+    {
+        let mut adapter = PostgresAdapter::new(&mut client).without_tags(vec!["seed"]);
+        adapter.setup_schema().unwrap();
+        let mut migrator = Migrator::new(&mut adapter);
+        migrator.register(Box::new(FirstMigration));
+        migrator.register(Box::new(DemoDataMigration));
+        migrator.up(None).unwrap();
+        assert_eq!(migrator.current_version().unwrap(), Some(10));
+    }
+    assert_eq!(client.query_one("SELECT count(*) FROM first;", &[]).unwrap().get::<_, i64>(0), 0);
+
+    let mut adapter = PostgresAdapter::new(&mut client).with_tags(vec!["seed"]);
+    let migrations = vec![&FirstMigration as &dyn PostgresMigration, &DemoDataMigration];
+    let plan = adapter.plan(migrations, None).unwrap();
+    assert_eq!(plan.migrations.iter().map(|migration| migration.version).collect::<Vec<_>>(), vec![100]);
+    adapter.apply_migration(&DemoDataMigration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(100));
+}
+
+#[test]
+fn test_seeds() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+
+    let mut seeds = load_seeds("tests/seeds").unwrap();
+    assert_eq!(seeds.iter().map(|seed| (seed.version(), seed.name())).collect::<Vec<_>>(), vec![(1, "currencies")]);
+    assert_eq!(adapter.apply_seeds(&seeds).unwrap(), vec![1]);
+    assert_eq!(adapter.apply_seeds(&seeds).unwrap(), Vec::<Version>::new());
+    assert_eq!(adapter.current_version().unwrap(), None);
+
+    seeds.push(Seed::new(2, "more currencies", "INSERT INTO currencies VALUES ('GBP', 'Pound') ON CONFLICT DO NOTHING;"));
+    assert_eq!(adapter.apply_seeds(&seeds).unwrap(), vec![2]);
+    assert_eq!(adapter.rerun_seeds(&seeds).unwrap(), vec![1, 2]);
+}
+
+#[test]
+fn test_side_table_name_too_long() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::with_metadata_table(&mut client, "m".repeat(55));
+    adapter.setup_schema().unwrap();
+
+    let seeds = vec![Seed::new(1, "currencies", "SELECT 1;")];
+    assert!(matches!(
+        adapter.apply_seeds(&seeds),
+        Err(SchemamamaPostgresError::SideTableNameTooLong { ref name }) if *name == format!("{}_seeds", "m".repeat(55))
+    ));
+    let migrations = vec![RepeatableMigration::new("user_count", "SELECT 1;")];
+    assert!(matches!(adapter.apply_repeatable(&migrations), Err(SchemamamaPostgresError::SideTableNameTooLong { .. })));
+    adapter.drop_schema().unwrap();
+}
+
+struct DevFixturesMigration;
+migration!(DevFixturesMigration, 110, "dev fixtures");
+
+impl PostgresMigration for DevFixturesMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute("CREATE TABLE fixtures (id BIGINT);")
+    }
+
+    fn environments(&self) -> &[&str] {
+        &["dev", "test"]
+    }
+}
+
+#[test]
+fn test_environment_conditional_migrations() {
+    let mut client = make_database_connection();
+    {
+        let mut adapter = PostgresAdapter::new(&mut client).with_environment("production");
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&FirstMigration).unwrap();
+        adapter.apply_migration(&DevFixturesMigration).unwrap();
+
+        let log = adapter.migration_log().unwrap();
+        assert_eq!(log.iter().map(|record| (record.version, record.skipped)).collect::<Vec<_>>(), vec![(10, false), (110, true)]);
+    }
+    assert_eq!(client.query_one("SELECT to_regclass('fixtures') IS NULL;", &[]).unwrap().get::<_, bool>(0), true);
+
+    let mut adapter = PostgresAdapter::new(&mut client).with_environment("dev");
+    adapter.revert_migration(&DevFixturesMigration).unwrap();
+    adapter.apply_migration(&DevFixturesMigration).unwrap();
+    assert!(!adapter.migration_log().unwrap().iter().any(|record| record.skipped));
+}
+
+#[test]
+fn test_split_statements() {
+    let sql = "-- setup; not a statement\n\
+               CREATE FUNCTION touch() RETURNS trigger AS $body$\n\
+               BEGIN NEW.updated_at := now(); RETURN NEW; END;\n\
+               $body$ LANGUAGE plpgsql;\n\
+               /* nested /* ; */ comment */ INSERT INTO notes VALUES ('a;b', E'it\\'s;', \"odd;name\");\n\
+               SELECT $1::TEXT;;\n\
+               DO $$ BEGIN PERFORM 1; END $$";
+
+    assert_eq!(split_statements(sql), vec![
+        "CREATE FUNCTION touch() RETURNS trigger AS $body$\n\
+         BEGIN NEW.updated_at := now(); RETURN NEW; END;\n\
+         $body$ LANGUAGE plpgsql",
+        "INSERT INTO notes VALUES ('a;b', E'it\\'s;', \"odd;name\")",
+        "SELECT $1::TEXT",
+        "DO $$ BEGIN PERFORM 1; END $$",
+    ]);
+}
+
+#[test]
+fn test_sql_includes() {
+    let migrations = load_sql_migrations("tests/includes").unwrap();
+    assert_eq!(migrations.len(), 1);
+    assert_eq!(migrations[0].up_statements().len(), 3);
+
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&migrations[0]).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(1));
+}
+
+#[test]
+fn test_sql_includes_confined() {
+    for directory in &["tests/includes_absolute", "tests/includes_parent"] {
+        let error = load_sql_migrations(directory).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("which is outside of"), "{}", error);
+    }
+
+    let error = load_sql_migrations("tests/includes_cycle").unwrap_err();
+    assert!(error.to_string().contains("includes itself"), "{}", error);
+}
+
+#[cfg(feature = "json")]
+struct TicketedMigration;
+#[cfg(feature = "json")]
+migration!(TicketedMigration, 120, "ticketed migration");
+
+#[cfg(feature = "json")]
+impl PostgresMigration for TicketedMigration {
+    fn metadata(&self) -> serde_json::Value {
+        serde_json::json!({"ticket": "OPS-42", "risk": "low"})
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_migration_metadata() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    adapter.apply_migration(&TicketedMigration).unwrap();
+
+    let log = adapter.migration_log().unwrap();
+    assert_eq!(log[0].metadata, None);
+    let metadata: serde_json::Value = serde_json::from_str(log[1].metadata.as_ref().unwrap()).unwrap();
+    assert_eq!(metadata, serde_json::json!({"ticket": "OPS-42", "risk": "low"}));
+}
+
+#[test]
+fn test_store_sql() {
+    let mut client = make_database_connection();
+    let mut adapter = PostgresAdapter::new(&mut client).store_sql(true);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&FirstMigration).unwrap();
+    let migration = SqlMigration::new(20, "create notes", "CREATE TABLE notes (body TEXT);", None);
+    adapter.apply_migration(&migration).unwrap();
+
+    let log = adapter.migration_log().unwrap();
+    assert_eq!(log.iter().map(|record| record.sql.as_deref()).collect::<Vec<_>>(), vec![
+        None,
+        Some("CREATE TABLE notes (body TEXT);"),
+    ]);
+}
+
+#[test]
+fn test_backfill_progress() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE visits (id BIGINT PRIMARY KEY, seen BOOLEAN NOT NULL DEFAULT false);
+         INSERT INTO visits (id) SELECT generate_series(1, 25);"
+    ).unwrap();
+
+    let mut reports: Vec<BackfillProgress> = vec![];
+    let total = Backfill::new(
+        "WITH batch AS (SELECT id FROM visits WHERE id > $1 ORDER BY id LIMIT $2) \
+         UPDATE visits SET seen = true FROM batch WHERE visits.id = batch.id RETURNING visits.id"
+    ).with_batch_size(10).with_estimated_rows(25).run_with_progress(&mut client, |progress| {
+        reports.push(progress.clone())
+    }).unwrap();
+
+    assert_eq!(total, 25);
+    let reports: Vec<(u64, u64, Option<u64>)> = reports.iter()
+        .map(|progress| (progress.batches, progress.rows_processed, progress.estimated_remaining_batches))
+        .collect();
+    assert_eq!(reports, vec![(1, 10, Some(2)), (2, 20, Some(1)), (3, 25, Some(0))]);
+}
+
+#[test]
+fn test_throttled_backfill() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE pings (id BIGINT PRIMARY KEY, done BOOLEAN NOT NULL DEFAULT false);
+         INSERT INTO pings (id) SELECT generate_series(1, 30);"
+    ).unwrap();
+
+    let started = std::time::Instant::now();
+    let total = Backfill::new(
+        "WITH batch AS (SELECT id FROM pings WHERE id > $1 ORDER BY id LIMIT $2) \
+         UPDATE pings SET done = true FROM batch WHERE pings.id = batch.id RETURNING pings.id"
+    )
+        .with_batch_size(10)
+        .with_max_rows_per_second(50)
+        .with_max_replication_lag(Duration::from_secs(60))
+        .run(&mut client)
+        .unwrap();
+
+    assert_eq!(total, 30);
+    // At 50 rows per second, the three full batches take at least 600ms.
+    assert!(started.elapsed() >= Duration::from_millis(600));
+}
+
+#[test]
+fn test_column_type_change() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE readings (id BIGINT PRIMARY KEY, value INTEGER);
+         INSERT INTO readings SELECT n, n * 10 FROM generate_series(1, 25) n;"
+    ).unwrap();
+
+    let change = ColumnTypeChange::new("readings", "value", "BIGINT");
+    change.start(&mut client).unwrap();
+    client.batch_execute("INSERT INTO readings VALUES (26, 260); UPDATE readings SET value = 11 WHERE id = 1;").unwrap();
+    assert_eq!(change.backfill(&mut client, "id", 10).unwrap(), 26);
+    change.swap(&mut client).unwrap();
+    change.drop_old(&mut client).unwrap();
+
+    client.batch_execute("INSERT INTO readings VALUES (27, 3000000000);").unwrap();
+    let row = client.query_one("SELECT sum(value)::BIGINT, pg_typeof(max(value))::TEXT FROM readings;", &[]).unwrap();
+    assert_eq!((row.get::<_, i64>(0), row.get::<_, String>(1)), (3000003511, "bigint".to_owned()));
+}
+
+#[test]
+fn test_set_not_null() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE members (id BIGINT PRIMARY KEY, email TEXT);
+         INSERT INTO members VALUES (1, 'a@example.com'), (2, NULL);"
+    ).unwrap();
+
+    assert!(set_not_null(&mut client, "members", "email").is_err());
+    client.batch_execute("UPDATE members SET email = 'b@example.com' WHERE id = 2;").unwrap();
+    set_not_null(&mut client, "members", "email").unwrap();
+    set_not_null(&mut client, "members", "email").unwrap();
+
+    let row = client.query_one(
+        "SELECT a.attnotnull, (SELECT count(*) FROM pg_constraint WHERE conrelid = 'members'::regclass AND contype = 'c') \
+         FROM pg_attribute a WHERE a.attrelid = 'members'::regclass AND a.attname = 'email';",
+        &[]
+    ).unwrap();
+    assert_eq!((row.get::<_, bool>(0), row.get::<_, i64>(1)), (true, 0));
+}
+
+#[test]
+fn test_constraint_validation_splitting() {
+    let mut client = make_database_connection();
+    client.batch_execute(
+        "CREATE TABLE owners (id BIGINT PRIMARY KEY);
+         CREATE TABLE pets (id BIGINT PRIMARY KEY, owner_id BIGINT, age INTEGER);
+         INSERT INTO owners VALUES (1);
+         INSERT INTO pets VALUES (1, 1, 3);"
+    ).unwrap();
+    add_constraint_not_valid(&mut client, "pets", "pets_owner_fk", "FOREIGN KEY (owner_id) REFERENCES owners (id)").unwrap();
+    add_constraint_not_valid(&mut client, "pets", "pets_age_check", "CHECK (age >= 0)").unwrap();
+    assert!(client.batch_execute("INSERT INTO pets VALUES (2, 2, 1);").is_err());
+    validate_constraint(&mut client, "pets", "pets_age_check").unwrap();
+
+    let schema = current_schema_name(&mut client);
+    let mut adapter = PostgresAdapter::new(&mut client);
+    let pending = vec![PendingValidation {
+        table: format!("{}.pets", schema),
+        constraint: "pets_owner_fk".to_owned(),
+        definition: "FOREIGN KEY (owner_id) REFERENCES owners(id) NOT VALID".to_owned(),
+    }];
+    assert_eq!(adapter.pending_validations().unwrap(), pending);
+    assert_eq!(adapter.validate_pending_constraints().unwrap(), pending);
+    assert!(adapter.pending_validations().unwrap().is_empty());
+}
+
+#[test]
+fn test_time_partitions() {
+    let mut client = make_database_connection();
+    client.batch_execute("CREATE TABLE measurements (taken_on DATE NOT NULL, value INTEGER) PARTITION BY RANGE (taken_on);").unwrap();
+
+    let partitions = TimePartitions::new("measurements", PartitionInterval::Month);
+    assert_eq!(partitions.create(&mut client, "2024-01-15", "2024-04-01").unwrap(), vec![
+        "measurements_p2024_01".to_owned(),
+        "measurements_p2024_02".to_owned(),
+        "measurements_p2024_03".to_owned(),
+    ]);
+    assert_eq!(partitions.create(&mut client, "2024-03-01", "2024-05-01").unwrap(), vec!["measurements_p2024_04".to_owned()]);
+    client.batch_execute("INSERT INTO measurements VALUES ('2024-02-29', 1), ('2024-04-30', 2);").unwrap();
+    assert_eq!(partitions.premake(&mut client, 1).unwrap().len(), 2);
+
+    assert_eq!(partitions.detach_before(&mut client, "2024-03-01").unwrap(), vec![
+        "measurements_p2024_01".to_owned(),
+        "measurements_p2024_02".to_owned(),
+    ]);
+    let row = client.query_one("SELECT count(*) FROM measurements;", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 1);
+}
+
+#[test]
+fn test_enum_value_migrations() {
+    let mut client = make_database_connection();
+    client.batch_execute("CREATE TYPE mood AS ENUM ('sad');").unwrap();
+    add_enum_value(&mut client, "mood", "ok").unwrap();
+    add_enum_value(&mut client, "mood", "ok").unwrap();
+
+    let migration = SqlMigration::new(
+        1,
+        "add happy mood",
+        "ALTER TYPE mood ADD VALUE 'happy';
+         CREATE TABLE moods (value mood NOT NULL DEFAULT 'happy');
+         INSERT INTO moods DEFAULT VALUES;",
+        None
+    );
+    assert!(migration.alters_enums());
+    assert!(!migration.run_in_transaction());
+
+    let mut adapter = PostgresAdapter::new(&mut client);
+    adapter.setup_schema().unwrap();
+    adapter.apply_migration(&migration).unwrap();
+    assert_eq!(adapter.current_version().unwrap(), Some(1));
+    drop(adapter);
+
+    let row = client.query_one("SELECT string_agg(v::TEXT, ',') FROM unnest(enum_range(NULL::mood)) v;", &[]).unwrap();
+    assert_eq!(row.get::<_, String>(0), "sad,ok,happy");
+}
+
+struct ReportingMigration;
+migration!(ReportingMigration, 130, "backfill orders");
+
+impl PostgresMigration for ReportingMigration {
+    fn up(&self, transaction: &mut Transaction) -> Result<(), PostgresError> {
+        transaction.batch_execute("INSERT INTO reports.orders VALUES (2, 30);")
+    }
+
+    fn refreshes_materialized_views(&self) -> &[&str] {
+        &["reports.order_totals"]
+    }
+}
+
+#[test]
+fn test_materialized_view_refresh() {
+    let mut client = make_database_connection();
+    let mut transaction = client.transaction().unwrap();
+    transaction.batch_execute(
+        "CREATE SCHEMA reports;
+         CREATE TABLE reports.orders (id BIGINT PRIMARY KEY, amount BIGINT);
+         INSERT INTO reports.orders VALUES (1, 12);
+         CREATE MATERIALIZED VIEW reports.order_totals AS SELECT 1 AS id, sum(amount)::BIGINT AS total FROM reports.orders;
+         CREATE UNIQUE INDEX ON reports.order_totals (id);"
+    ).unwrap();
+    {
+        let mut adapter = PostgresAdapter::new(&mut transaction);
+        adapter.setup_schema().unwrap();
+        adapter.apply_migration(&ReportingMigration).unwrap();
+        adapter.after_migrate().unwrap();
+        assert!(adapter.refresh_materialized_views().unwrap().is_empty());
+    }
+
+    let row = transaction.query_one("SELECT total FROM reports.order_totals;", &[]).unwrap();
+    assert_eq!(row.get::<_, i64>(0), 42);
+    transaction.rollback().unwrap();
+}
+
+#[test]
+fn test_from_transaction() {
+    let mut client = make_database_connection();
+    let mut transaction = client.transaction().unwrap();
+    {
+        let mut adapter = PostgresAdapter::from_transaction(&mut transaction);
+        adapter.setup_schema().unwrap();
+        let mut migrator = Migrator::new(&mut adapter);
+        migrator.register(Box::new(FirstMigration));
+        migrator.register(Box::new(SecondMigration));
+        migrator.up(None).unwrap();
+        assert_eq!(migrator.current_version().unwrap(), Some(20));
+    }
+    transaction.rollback().unwrap();
+
+    let row = client.query_one("SELECT to_regclass('first') IS NULL AND to_regclass('schemamama') IS NULL;", &[]).unwrap();
+    assert!(row.get::<_, bool>(0));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_fake_adapter() {
+    use schemamama_postgres::{Direction, FakeAdapter};
+
+    let mut adapter = FakeAdapter::with_applied(vec![10]);
+    {
+        let mut migrator = Migrator::new(&mut adapter);
+        migrator.register(Box::new(FirstMigration));
+        migrator.register(Box::new(SecondMigration));
+        migrator.register(Box::new(FailingMigration));
+        migrator.up(Some(20)).unwrap();
+        migrator.down(Some(10)).unwrap();
+        migrator.up(None).unwrap();
+    }
+
+    assert_eq!(adapter.calls(), &[(Direction::Apply, 20), (Direction::Revert, 20), (Direction::Apply, 20), (Direction::Apply, 40)]);
+    assert_eq!(adapter.applied().iter().cloned().collect::<Vec<_>>(), vec![10, 20, 40]);
+}